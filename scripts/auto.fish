@@ -0,0 +1,5 @@
+function __kubeswitch_auto --on-variable PWD
+	__kubeswitch_cmd auto
+end
+
+__kubeswitch_auto
@@ -0,0 +1,15 @@
+__kubeswitch_auto() {
+	# Only check the pinned context when the dir was changed.
+	if [[ "$PWD" == "$__kubeswitch_auto_pwd" ]]; then
+		return
+	fi
+	__kubeswitch_auto_pwd=$PWD
+	__kubeswitch_cmd auto
+}
+
+if [[ -n $ZSH_VERSION ]]; then
+	autoload -Uz add-zsh-hook
+	add-zsh-hook precmd __kubeswitch_auto
+elif [[ $PROMPT_COMMAND != *__kubeswitch_auto* ]]; then
+	PROMPT_COMMAND="__kubeswitch_auto${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+fi
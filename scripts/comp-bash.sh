@@ -15,9 +15,34 @@ __kubeswitch_comp() {
 
 
 	local args=("${words[@]:1}")
-    local comp_cmd="${words[0]} --comp -- ${args[*]}"
 
-	COMPREPLY=($(eval "${comp_cmd}" 2>>/tmp/.kubeswitch_comp_logs))
+	# The last arg is the word under cursor, keep it even if it is empty.
+	COMPREPLY=($("${words[0]}" complete -- "${args[@]}" 2>>/tmp/.kubeswitch_comp_logs))
+
+	# The file and directory paths are completed by bash.
+	case "${COMPREPLY[0]}" in
+	__files__)
+		COMPREPLY=($(compgen -f -- "$cur"))
+		compopt -o filenames 2>/dev/null
+		return
+		;;
+	__dirs__)
+		COMPREPLY=($(compgen -d -- "$cur"))
+		compopt -o filenames 2>/dev/null
+		return
+		;;
+	esac
+
+	# The dirs are completed level by level, and the tag keys are followed by
+	# values, no space after them.
+	if [[ ${#COMPREPLY[@]} -eq 1 && ${COMPREPLY[0]} == *[/=] ]]; then
+		compopt -o nospace 2>/dev/null
+	fi
+
+	# The "context::namespace" items contain ':', which is a word break in bash.
+	if declare -F __ltrim_colon_completions >/dev/null 2>&1; then
+		__ltrim_colon_completions "$cur"
+	fi
 }
 
 complete -o default -F __kubeswitch_comp __kubeswitch_cmd
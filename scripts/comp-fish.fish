@@ -0,0 +1,17 @@
+function __kubeswitch_comp
+	# The last arg is the word under cursor, keep it even if it is empty.
+	set -l args (commandline -opc)[2..-1] (commandline -ct)
+	# Every line is an item, followed by the description after a tab.
+	set -l items (__kubeswitch_cmd complete --shell fish -- $args 2>>/tmp/.kubeswitch_comp_logs)
+	# The file and directory paths are completed by fish.
+	switch "$items[1]"
+		case __files__
+			__fish_complete_path (commandline -ct)
+		case __dirs__
+			__fish_complete_directories (commandline -ct)
+		case '*'
+			printf '%s\n' $items
+	end
+end
+
+complete -c __kubeswitch_cmd -f -a '(__kubeswitch_comp)'
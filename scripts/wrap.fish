@@ -0,0 +1,78 @@
+function __kubeswitch_cmd
+	# The env before switching is restored by session when unsetting.
+	set -gx KUBESWITCH_SESSION $fish_pid
+	# The version of the switch protocol this script speaks.
+	set -gx KUBESWITCH_PROTOCOL __protocol_version
+	# Fish splits the output into list by lines.
+	set -l items (__wrap_cmd $argv)
+	or return 1
+
+	if test (count $items) -eq 0
+		return
+	end
+
+	if test "$items[1]" != "__switch__"
+		printf '%s\n' $items
+		return
+	end
+
+	set -l cmd $items[2]
+	set -l export_kubeconfig $items[3]
+	set -l clean_flag $items[4]
+
+	if set -q KUBESWITCH_SHIMS
+		if set -l idx (contains -i -- $KUBESWITCH_SHIMS $PATH)
+			set -e PATH[$idx]
+		end
+		set -e KUBESWITCH_SHIMS
+	end
+
+	if test "$clean_flag" = "1"
+		set -e KUBESWITCH_NAME KUBESWITCH_NAMESPACE KUBESWITCH_DISPLAY KUBESWITCH_COLOR KUBESWITCH_EMOJI
+		if test "$export_kubeconfig" = "1"
+			set -e KUBECONFIG
+		end
+		for restore in $items[5..-1]
+			set -l kv (string split -m 1 = $restore)
+			set -gx $kv[1] $kv[2]
+		end
+		functions -e $cmd
+		return
+	end
+
+	set -gx KUBESWITCH_NAME $items[5]
+	set -gx KUBESWITCH_NAMESPACE $items[6]
+	set -gx KUBESWITCH_DISPLAY $items[7]
+
+	set -l kubectl_cmd $items[8]
+	set -l kubeconfig_path $items[9]
+
+	alias $cmd "$kubectl_cmd --kubeconfig $kubeconfig_path --namespace $KUBESWITCH_NAMESPACE"
+	if test "$export_kubeconfig" = "1"
+		set -gx KUBECONFIG $kubeconfig_path
+	end
+
+	set -l shims $items[10]
+	if test "$shims" != "-"
+		set -gx KUBESWITCH_SHIMS $shims
+		set -gx PATH $shims $PATH
+	end
+
+	if test "$items[11]" != "-"
+		set -gx KUBESWITCH_COLOR $items[11]
+	else
+		set -e KUBESWITCH_COLOR
+	end
+	if test "$items[12]" != "-"
+		set -gx KUBESWITCH_EMOJI $items[12]
+	else
+		set -e KUBESWITCH_EMOJI
+	end
+
+	set -l k9s_enable $items[13]
+	if test "$k9s_enable" = "1"
+		set -l k9s_exec $items[14]
+		set -l k9s_cmd $items[15]
+		alias $k9s_cmd "$k9s_exec --kubeconfig $kubeconfig_path --namespace $KUBESWITCH_NAMESPACE"
+	end
+end
@@ -1,4 +1,8 @@
 __kubeswitch_cmd() {
+	# The env before switching is restored by session when unsetting.
+	export KUBESWITCH_SESSION=$$
+	# The version of the switch protocol this script speaks.
+	export KUBESWITCH_PROTOCOL=__protocol_version
 	if output=$(__wrap_cmd $@); then
 		if [[ -z $output ]]; then
 			return
@@ -17,11 +21,21 @@ __kubeswitch_cmd() {
 		local cmd=${items[@]:1:1}
 		local export_kubeconfig=${items[@]:2:1}
 		local clean_flag=${items[@]:3:1}
+
+		if [[ -n $KUBESWITCH_SHIMS ]]; then
+			PATH="${PATH//"${KUBESWITCH_SHIMS}:"/}"
+			unset KUBESWITCH_SHIMS
+		fi
+
 		if [[ $clean_flag == "1" ]]; then
-			unset KUBESWITCH_NAME KUBESWITCH_NAMESPACE KUBESWITCH_DISPLAY
+			unset KUBESWITCH_NAME KUBESWITCH_NAMESPACE KUBESWITCH_DISPLAY KUBESWITCH_COLOR KUBESWITCH_EMOJI
 			if [[ $export_kubeconfig == "1" ]]; then
 				unset KUBECONFIG
 			fi
+			local restore
+			for restore in "${items[@]:4}"; do
+				export "${restore}"
+			done
 			unalias ${cmd}
 			return
 		fi
@@ -38,10 +52,29 @@ __kubeswitch_cmd() {
 			export KUBECONFIG="${kubeconfig_path}"
 		fi
 
-		local k9s_enable="${items[@]:9:1}"
+		local shims="${items[@]:9:1}"
+		if [[ $shims != "-" ]]; then
+			export KUBESWITCH_SHIMS="${shims}"
+			export PATH="${shims}:${PATH}"
+		fi
+
+		local color="${items[@]:10:1}"
+		if [[ $color != "-" ]]; then
+			export KUBESWITCH_COLOR="${color}"
+		else
+			unset KUBESWITCH_COLOR
+		fi
+		local emoji="${items[@]:11:1}"
+		if [[ $emoji != "-" ]]; then
+			export KUBESWITCH_EMOJI="${emoji}"
+		else
+			unset KUBESWITCH_EMOJI
+		fi
+
+		local k9s_enable="${items[@]:12:1}"
 		if [[ $k9s_enable == "1" ]]; then
-			local k9s_exec="${items[@]:10:1}"
-			local k9s_cmd="${items[@]:11:1}"
+			local k9s_exec="${items[@]:13:1}"
+			local k9s_cmd="${items[@]:14:1}"
 			alias ${k9s_cmd}="${k9s_exec} --kubeconfig ${kubeconfig_path} --namespace ${KUBESWITCH_NAMESPACE}"
 		fi
 
@@ -0,0 +1,167 @@
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+
+use crate::config::Config;
+use crate::context::{
+    confirm, ensure_dir, get_kubeconfig_store_path, get_kubeconfig_write_path, write_private,
+    KubeContext,
+};
+use crate::encryption;
+use crate::history::format_elapsed;
+use crate::vault;
+
+/// Save the current content of the kubeconfig file before overwriting it. The
+/// backups of a context are stored in their own dir under `backup.dir`, named by
+/// the time they were made, only the latest `backup.keep` ones are kept.
+pub fn save(cfg: &Config, name: &str, path: &Path) -> Result<()> {
+    if cfg.backup.keep == 0 {
+        return Ok(());
+    }
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        // Nothing to lose for a new context.
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("read kubeconfig '{}'", path.display()))
+        }
+    };
+
+    let dir = get_dir(cfg, name);
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)
+        .with_context(|| format!("create backup dir '{}'", dir.display()))?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backwards")
+        .as_nanos();
+    let backup_path = dir.join(format!("{timestamp}.yaml"));
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&backup_path)
+        .with_context(|| format!("create backup file '{}'", backup_path.display()))?;
+    file.write_all(&data)
+        .with_context(|| format!("write backup file '{}'", backup_path.display()))?;
+
+    for (_, path) in list(&dir)?.into_iter().skip(cfg.backup.keep) {
+        fs::remove_file(&path)
+            .with_context(|| format!("remove old backup file '{}'", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Move the backups along with the renamed context.
+pub fn rename(cfg: &Config, name: &str, new_name: &str) -> Result<()> {
+    let dir = get_dir(cfg, name);
+    if fs::metadata(&dir).is_err() {
+        return Ok(());
+    }
+    let new_dir = get_dir(cfg, new_name);
+    // These are left by a removed context with the same name.
+    if fs::metadata(&new_dir).is_ok() {
+        fs::remove_dir_all(&new_dir)
+            .with_context(|| format!("remove stale backup dir '{}'", new_dir.display()))?;
+    }
+    fs::rename(&dir, &new_dir).with_context(|| {
+        format!(
+            "rename backup dir '{}' to '{}'",
+            dir.display(),
+            new_dir.display()
+        )
+    })
+}
+
+/// Restore the kubeconfig of the context from its latest backup. The current
+/// content is backed up first and the restored backup is consumed, so reverting
+/// again undoes it.
+pub fn revert(ctx: &KubeContext) -> Result<()> {
+    if vault::lookup(ctx.cfg, &ctx.name).is_some() {
+        bail!("cannot revert vault context '{}'", ctx.name);
+    }
+
+    let dir = get_dir(ctx.cfg, &ctx.name);
+    let (timestamp, backup_path) = match list(&dir)?.into_iter().next() {
+        Some(latest) => latest,
+        None => bail!("no backup of context '{}'", ctx.name),
+    };
+    let elapsed = format_elapsed((timestamp / 1_000_000_000) as u64);
+    if !confirm(format!(
+        "Revert context '{}' to the backup made {elapsed}",
+        ctx.name
+    ))? {
+        return Ok(());
+    }
+
+    let data = fs::read(&backup_path)
+        .with_context(|| format!("read backup file '{}'", backup_path.display()))?;
+    let encrypted = encryption::find(ctx.cfg, &ctx.name);
+    save(
+        ctx.cfg,
+        &ctx.name,
+        &get_kubeconfig_store_path(ctx.cfg, &ctx.name),
+    )?;
+    match fs::remove_file(&backup_path) {
+        Ok(()) => {}
+        // Already dropped by the save above if only one backup is kept.
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => {
+            return Err(err)
+                .with_context(|| format!("remove backup file '{}'", backup_path.display()))
+        }
+    }
+
+    // Reverting a context in the shared dir makes a copy in the user layer. The
+    // backups of the encrypted context are encrypted too.
+    let path = match encrypted {
+        Some(_) => encryption::get_write_path(ctx.cfg, &ctx.name),
+        None => get_kubeconfig_write_path(ctx.cfg, &ctx.name),
+    };
+    ensure_dir(&path)?;
+    write_private(&path, &data)?;
+    info!(
+        "Reverted context '{}' to the backup made {elapsed}",
+        ctx.name
+    );
+    Ok(())
+}
+
+/// The backups in the dir, the latest comes first.
+fn list(dir: &Path) -> Result<Vec<(u128, PathBuf)>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("read backup dir '{}'", dir.display()))
+        }
+    };
+
+    let mut backups = Vec::new();
+    for entry in entries {
+        let entry = entry.with_context(|| format!("read entry of '{}'", dir.display()))?;
+        let file_name = entry.file_name();
+        let timestamp = file_name
+            .to_str()
+            .and_then(|name| name.strip_suffix(".yaml"))
+            .and_then(|name| name.parse::<u128>().ok());
+        if let Some(timestamp) = timestamp {
+            backups.push((timestamp, entry.path()));
+        }
+    }
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.0));
+    Ok(backups)
+}
+
+/// The context names cannot contain '%', use it in place of '/' so that the
+/// backups of "a" and "a/b" are not nested.
+fn get_dir(cfg: &Config, name: &str) -> PathBuf {
+    PathBuf::from(&cfg.backup.dir).join(name.replace('/', "%"))
+}
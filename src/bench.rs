@@ -0,0 +1,92 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+use crate::complete;
+use crate::config::Config;
+use crate::context::{self, KubeContext};
+use crate::history;
+
+/// The timings of one step of the workflow, in milliseconds.
+#[derive(Serialize)]
+pub struct Step {
+    pub name: &'static str,
+
+    /// The number of items handled in one run, such as files or history
+    /// entries.
+    pub items: usize,
+
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+}
+
+/// Measure the steps of the workflow on the user's machine: walking the kube dirs,
+/// parsing the kubeconfig files, reading the history, listing all the contexts and
+/// completing the context names. Every step runs `runs` times.
+pub fn bench(cfg: &Config, runs: usize) -> Result<Vec<Step>> {
+    if runs == 0 {
+        bail!("the runs should be greater than 0");
+    }
+
+    let mut files: Vec<(String, PathBuf)> = Vec::new();
+    let mut steps = Vec::new();
+    steps.push(measure("scan", runs, || {
+        files.clear();
+        context::walk_kubeconfigs(cfg, "", |name, path| {
+            files.push((name.to_string(), path));
+            Ok(())
+        })?;
+        Ok(files.len())
+    })?);
+
+    steps.push(measure("parse", runs, || {
+        let mut count = 0;
+        for (name, path) in files.iter() {
+            // The invalid files are skipped by listing too.
+            if context::parse_kubeconfig(cfg, name, path).is_ok() {
+                count += 1;
+            }
+        }
+        Ok(count)
+    })?);
+
+    steps.push(measure("history", runs, || {
+        let usage = history::usage(cfg);
+        Ok(usage.values().map(|usage| usage.count).sum())
+    })?);
+
+    steps.push(measure("list", runs, || Ok(KubeContext::list(cfg)?.len()))?);
+
+    // The same as `ks <TAB>`.
+    let args = [String::new()];
+    steps.push(measure("complete", runs, || complete::count(cfg, &args))?);
+
+    Ok(steps)
+}
+
+fn measure<F>(name: &'static str, runs: usize, mut f: F) -> Result<Step>
+where
+    F: FnMut() -> Result<usize>,
+{
+    let mut items = 0;
+    let mut durations = Vec::with_capacity(runs);
+    for _ in 0..runs {
+        let start = Instant::now();
+        items = f()?;
+        durations.push(start.elapsed());
+    }
+    log::debug!("bench {name}: {durations:?}");
+
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    let total: Duration = durations.iter().sum();
+    Ok(Step {
+        name,
+        items,
+        min: ms(*durations.iter().min().unwrap()),
+        mean: ms(total) / runs as f64,
+        max: ms(*durations.iter().max().unwrap()),
+    })
+}
@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+use std::fs::{self, TryLockError};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+
+use crate::config::get_cache_dir;
+use crate::history::{format_elapsed, now};
+
+/// The cached namespaces of a context, written every time we list namespaces
+/// from the cluster, so that they can be served when the cluster cannot be
+/// reached (such as in offline mode).
+pub struct NamespaceCache {
+    pub timestamp: u64,
+    pub namespaces: Vec<String>,
+}
+
+/// The result of [`NamespaceCache::begin_refresh`].
+pub enum Refresh {
+    /// We hold the refresh lock of the context, and should list the namespaces from
+    /// the cluster and write the cache. The lock is released when the file is
+    /// dropped.
+    Owner(fs::File),
+
+    /// Another process refreshed the cache while we were waiting for it.
+    Fresh(NamespaceCache),
+
+    /// Another process is still refreshing after the wait, the old cache is served.
+    Stale(NamespaceCache),
+}
+
+impl NamespaceCache {
+    pub fn read(name: &str) -> Result<Option<NamespaceCache>> {
+        let path = Self::get_path(name);
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("read namespace cache '{}'", path.display()))
+            }
+        };
+
+        let mut lines = data.lines();
+        let timestamp = match lines.next().and_then(|line| line.trim().parse().ok()) {
+            Some(timestamp) => timestamp,
+            None => return Ok(None),
+        };
+        let namespaces = lines
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect();
+
+        Ok(Some(NamespaceCache {
+            timestamp,
+            namespaces,
+        }))
+    }
+
+    pub fn write(name: &str, namespaces: &[String]) -> Result<()> {
+        let path = Self::get_path(name);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("create cache dir '{}'", dir.display()))?;
+        }
+
+        let mut data = format!("{}\n", now());
+        for ns in namespaces {
+            data.push_str(ns);
+            data.push('\n');
+        }
+
+        // Write to a temporary file and rename it, so that the concurrent readers
+        // never see a partial cache.
+        let tmp_path = PathBuf::from(format!("{}.{}.tmp", path.display(), process::id()));
+        fs::write(&tmp_path, data)
+            .with_context(|| format!("write namespace cache '{}'", tmp_path.display()))?;
+        fs::rename(&tmp_path, &path)
+            .with_context(|| format!("rename namespace cache '{}'", path.display()))
+    }
+
+    /// Coordinate the shells listing the namespaces of the same context at the same
+    /// time, so that only one of them runs kubectl. The first one becomes the
+    /// [`Refresh::Owner`], the others wait up to `wait` for its result, and then
+    /// serve the stale cache if there is one. `None` waits until the owner is done.
+    pub fn begin_refresh(name: &str, wait: Option<Duration>) -> Result<Refresh> {
+        let path = Self::get_lock_path(name);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("create cache dir '{}'", dir.display()))?;
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("open namespace lock file '{}'", path.display()))?;
+
+        if Self::try_lock(&file, &path)? {
+            return Self::stamp(file, &path);
+        }
+
+        let started = now();
+        log::debug!(
+            "namespaces of '{name}' are being refreshed by another process ({}), wait for it",
+            fs::read_to_string(&path).unwrap_or_default().trim()
+        );
+        let deadline = wait.map(|wait| Instant::now() + wait);
+        loop {
+            match deadline {
+                Some(deadline) if Instant::now() >= deadline => {
+                    if let Some(cache) = Self::read(name)? {
+                        return Ok(Refresh::Stale(cache));
+                    }
+                    // Nothing to serve, we have to wait for the owner anyway.
+                    file.lock().with_context(|| {
+                        format!("lock namespace lock file '{}'", path.display())
+                    })?;
+                    break;
+                }
+                _ => {
+                    thread::sleep(Duration::from_millis(50));
+                    if Self::try_lock(&file, &path)? {
+                        break;
+                    }
+                }
+            }
+        }
+
+        // The owner is done. Its cache is newer than our waiting unless it failed,
+        // in which case we take over.
+        if let Some(cache) = Self::read(name)? {
+            if cache.timestamp >= started {
+                return Ok(Refresh::Fresh(cache));
+            }
+        }
+        Self::stamp(file, &path)
+    }
+
+    fn try_lock(file: &fs::File, path: &Path) -> Result<bool> {
+        match file.try_lock() {
+            Ok(()) => Ok(true),
+            Err(TryLockError::WouldBlock) => Ok(false),
+            Err(TryLockError::Error(err)) => {
+                Err(err).with_context(|| format!("lock namespace lock file '{}'", path.display()))
+            }
+        }
+    }
+
+    /// Record who is refreshing since when in the lock file we hold, for the
+    /// waiting processes to report.
+    fn stamp(mut file: fs::File, path: &Path) -> Result<Refresh> {
+        file.set_len(0)
+            .and_then(|_| write!(file, "pid {} since {}", process::id(), now()))
+            .with_context(|| format!("write namespace lock file '{}'", path.display()))?;
+        Ok(Refresh::Owner(file))
+    }
+
+    fn get_path(name: &str) -> PathBuf {
+        get_cache_dir().join("namespaces").join(name)
+    }
+
+    fn get_lock_path(name: &str) -> PathBuf {
+        get_cache_dir().join("locks").join("namespaces").join(name)
+    }
+}
+
+/// The earliest expiry time of the certificates (client and CA) of every context,
+/// written by `ks daemon`, every line is a context name followed by the timestamp.
+pub struct CertCache;
+
+impl CertCache {
+    pub fn read() -> Result<BTreeMap<String, u64>> {
+        let path = Self::get_path();
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("read cert cache '{}'", path.display()))
+            }
+        };
+
+        Ok(data
+            .lines()
+            .filter_map(|line| {
+                let (name, expire) = line.split_once(' ')?;
+                Some((name.to_string(), expire.trim().parse().ok()?))
+            })
+            .collect())
+    }
+
+    pub fn write(expires: &BTreeMap<String, u64>) -> Result<()> {
+        let path = Self::get_path();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("create cache dir '{}'", dir.display()))?;
+        }
+
+        let mut data = String::new();
+        for (name, expire) in expires.iter() {
+            data.push_str(&format!("{name} {expire}\n"));
+        }
+        fs::write(&path, data).with_context(|| format!("write cert cache '{}'", path.display()))
+    }
+
+    fn get_path() -> PathBuf {
+        get_cache_dir().join("certs")
+    }
+}
+
+/// Format the expiry time in a human readable way, such as "expires in 3d" or
+/// "expired 3d ago".
+pub fn format_expire(timestamp: u64) -> String {
+    let now = now();
+    if timestamp <= now {
+        return format!("expired {}", format_elapsed(timestamp));
+    }
+    let secs = timestamp - now;
+    match secs {
+        0..=3599 => format!("expires in {}m", secs / 60),
+        3600..=86399 => format!("expires in {}h", secs / 3600),
+        _ => format!("expires in {}d", secs / 86400),
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+use crate::config::{get_state_path, Config};
+use crate::context::walk_kubeconfigs;
+
+/// The state of a managed kubeconfig file when it was seen.
+struct Seen {
+    hash: String,
+    modified: u64,
+}
+
+pub enum ChangeKind {
+    Added,
+    Updated,
+    Removed,
+}
+
+/// Change of a context since the last time user looked.
+pub struct Change {
+    pub name: String,
+    pub kind: ChangeKind,
+    /// The last modified time of the kubeconfig file, none if removed.
+    pub modified: Option<u64>,
+}
+
+const SEEN_NAME: &str = ".kubeswitch_seen";
+
+/// Compare the content of the managed kubeconfig files with the ones recorded last
+/// time, and record the current state. Returns `None` if there was no record yet.
+pub fn check(cfg: &Config) -> Result<Option<Vec<Change>>> {
+    let current = scan(cfg)?;
+    let changes = read_seen()?.map(|last| diff(&last, &current));
+    // Record only after the changes are computed, so that a failure never loses
+    // them.
+    write_seen(&current)?;
+    Ok(changes)
+}
+
+fn diff(last: &BTreeMap<String, Seen>, current: &BTreeMap<String, Seen>) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for (name, seen) in current.iter() {
+        let kind = match last.get(name) {
+            Some(last_seen) if last_seen.hash == seen.hash => continue,
+            Some(_) => ChangeKind::Updated,
+            None => ChangeKind::Added,
+        };
+        changes.push(Change {
+            name: name.clone(),
+            kind,
+            modified: Some(seen.modified),
+        });
+    }
+    for name in last.keys() {
+        if !current.contains_key(name) {
+            changes.push(Change {
+                name: name.clone(),
+                kind: ChangeKind::Removed,
+                modified: None,
+            });
+        }
+    }
+    changes.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+    changes
+}
+
+/// Hash all the managed kubeconfig files. The symlink contexts are skipped, their
+/// changes are reported by the source contexts.
+fn scan(cfg: &Config) -> Result<BTreeMap<String, Seen>> {
+    let mut files = BTreeMap::new();
+    walk_kubeconfigs(cfg, "", |name, path| {
+        let meta = fs::symlink_metadata(&path)
+            .with_context(|| format!("stat metadata for '{}'", path.display()))?;
+        if meta.is_symlink() {
+            return Ok(());
+        }
+        // The seen file is line based.
+        if name.contains('\n') {
+            warning!("skip checking changes of context {name:?} with newline in name");
+            return Ok(());
+        }
+
+        let data =
+            fs::read(&path).with_context(|| format!("read kubeconfig '{}'", path.display()))?;
+        let mut hasher = Sha256::new();
+        hasher.update(&data);
+        let hash = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+
+        let modified = meta
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0);
+
+        files.insert(name.to_string(), Seen { hash, modified });
+        Ok(())
+    })?;
+    Ok(files)
+}
+
+fn read_seen() -> Result<Option<BTreeMap<String, Seen>>> {
+    let path = get_path()?;
+    let data = match fs::read_to_string(&path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err).with_context(|| format!("read seen file '{}'", path.display()))
+        }
+    };
+
+    let mut files = BTreeMap::new();
+    for line in data.lines() {
+        if let Some((name, seen)) = parse_seen_line(line) {
+            files.insert(name.to_string(), seen);
+        }
+    }
+    Ok(Some(files))
+}
+
+/// Parse the line in "{hash} {modified} {name}" format, the name is the last so
+/// that it can contain spaces. The old "{name} {hash} {modified}" format is still
+/// accepted.
+fn parse_seen_line(line: &str) -> Option<(&str, Seen)> {
+    let is_hash = |hash: &str| hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit());
+    let fields: Vec<_> = line.splitn(3, ' ').collect();
+    if fields.len() != 3 {
+        return None;
+    }
+    let (name, hash, modified) = match fields[1].parse() {
+        Ok(modified) if is_hash(fields[0]) => (fields[2], fields[0], modified),
+        _ if is_hash(fields[1]) => (fields[0], fields[1], fields[2].parse().ok()?),
+        _ => return None,
+    };
+    let seen = Seen {
+        hash: hash.to_string(),
+        modified,
+    };
+    Some((name, seen))
+}
+
+fn write_seen(files: &BTreeMap<String, Seen>) -> Result<()> {
+    let path = get_path()?;
+    let mut data = String::new();
+    for (name, seen) in files.iter() {
+        data.push_str(&format!("{} {} {name}\n", seen.hash, seen.modified));
+    }
+
+    // Write to a temporary file and rename it, so that a crash never leaves a
+    // partial seen file.
+    let tmp_path = PathBuf::from(format!("{}.{}.tmp", path.display(), process::id()));
+    fs::write(&tmp_path, data)
+        .with_context(|| format!("write seen file '{}'", tmp_path.display()))?;
+    fs::rename(&tmp_path, &path).with_context(|| format!("rename seen file '{}'", path.display()))
+}
+
+fn get_path() -> Result<PathBuf> {
+    get_state_path(SEEN_NAME)
+}
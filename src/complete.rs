@@ -0,0 +1,721 @@
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+
+use anyhow::{bail, Context, Result};
+use clap::{Arg, Command, CommandFactory, ValueHint};
+
+use crate::config::Config;
+use crate::context::{walk_kubeconfigs, KubeContext, Resolution, SelectOption};
+use crate::tags::Tags;
+use crate::vault;
+use crate::{Args, Shell};
+
+/// Printed alone to ask the shell script to complete the file paths by itself,
+/// for the args with the file path value hint.
+const FILES_MARK: &str = "__files__";
+
+/// The same as [`FILES_MARK`], but for the directory paths.
+const DIRS_MARK: &str = "__dirs__";
+
+/// The completion item, the description is shown by zsh and fish.
+struct Item {
+    value: String,
+    description: Option<String>,
+}
+
+impl Item {
+    fn new(value: impl Into<String>) -> Item {
+        Item {
+            value: value.into(),
+            description: None,
+        }
+    }
+
+    fn with_description(value: impl Into<String>, description: Option<String>) -> Item {
+        Item {
+            value: value.into(),
+            description,
+        }
+    }
+}
+
+/// The word under cursor, located by the clap definition of the args.
+enum CompleteWord<'a> {
+    /// The positional arg, after the subcommand name (if any) and the positional
+    /// args before it.
+    /// The ids of the flags given with values are in `flags`.
+    Positional {
+        words: Vec<&'a str>,
+        flags: HashSet<String>,
+        to_complete: &'a str,
+    },
+    /// The flag of the command.
+    Flag { cmd: Command, to_complete: &'a str },
+    /// The value of a flag, the prefix is the `--flag=` or `-f=` part of the word.
+    Value {
+        arg: Arg,
+        words: Vec<&'a str>,
+        prefix: &'a str,
+        to_complete: &'a str,
+    },
+    /// The args after `--`, they are not completed.
+    Other,
+}
+
+impl CompleteWord<'_> {
+    fn parse(args: &[String]) -> CompleteWord<'_> {
+        let mut cmd = Args::command();
+        // Propagate the global flags to the subcommands.
+        cmd.build();
+
+        let (to_complete, args) = match args.split_last() {
+            Some((last, args)) => (last.as_str(), args),
+            None => ("", args),
+        };
+
+        let mut words = Vec::new();
+        let mut flags = HashSet::new();
+        let mut take_value: Option<Arg> = None;
+        for arg in args {
+            if let Some(flag) = take_value.take() {
+                flags.insert(flag.get_id().to_string());
+                continue;
+            }
+            if arg == "--" {
+                return CompleteWord::Other;
+            }
+            if let Some(flag) = arg.strip_prefix("--") {
+                match flag.split_once('=') {
+                    Some((flag, _)) => {
+                        if let Some(flag) = find_long(&cmd, flag) {
+                            flags.insert(flag.get_id().to_string());
+                        }
+                    }
+                    None => take_value = find_long(&cmd, flag),
+                }
+                continue;
+            }
+            if let Some(shorts) = arg.strip_prefix('-').filter(|shorts| !shorts.is_empty()) {
+                // The combined short flags, the rest after a flag taking value is
+                // its value.
+                for (idx, c) in shorts.char_indices() {
+                    if let Some(flag) = find_short(&cmd, c) {
+                        if idx + c.len_utf8() == shorts.len() {
+                            take_value = Some(flag);
+                        } else {
+                            flags.insert(flag.get_id().to_string());
+                        }
+                        break;
+                    }
+                }
+                continue;
+            }
+
+            if words.is_empty() && cmd.get_name() != "complete" {
+                if let Some(sub) = cmd.find_subcommand(arg) {
+                    let sub = sub.clone();
+                    words.push(arg.as_str());
+                    cmd = sub;
+                    continue;
+                }
+            }
+            words.push(arg.as_str());
+        }
+
+        if let Some(arg) = take_value {
+            return CompleteWord::Value {
+                arg,
+                words,
+                prefix: "",
+                to_complete,
+            };
+        }
+        if let Some((flag, value)) = to_complete
+            .strip_prefix('-')
+            .and_then(|flag| flag.split_once('='))
+        {
+            // Such as `--namespace=`, `-n=` and `-yn=`, the value is for the last
+            // short flag.
+            let arg = match flag.strip_prefix('-') {
+                Some(long) => find_long(&cmd, long),
+                None => flag.chars().last().and_then(|c| find_short(&cmd, c)),
+            };
+            return match arg {
+                Some(arg) => CompleteWord::Value {
+                    arg,
+                    words,
+                    prefix: &to_complete[..to_complete.len() - value.len()],
+                    to_complete: value,
+                },
+                None => CompleteWord::Other,
+            };
+        }
+        if to_complete.starts_with('-') {
+            return CompleteWord::Flag { cmd, to_complete };
+        }
+        CompleteWord::Positional {
+            words,
+            flags,
+            to_complete,
+        }
+    }
+}
+
+/// The flag taking value of the command.
+fn find_long(cmd: &Command, long: &str) -> Option<Arg> {
+    cmd.get_arguments()
+        .find(|arg| arg.get_long() == Some(long))
+        .filter(|arg| arg.get_action().takes_values())
+        .cloned()
+}
+
+fn find_short(cmd: &Command, short: char) -> Option<Arg> {
+    cmd.get_arguments()
+        .find(|arg| arg.get_short() == Some(short))
+        .filter(|arg| arg.get_action().takes_values())
+        .cloned()
+}
+
+/// Print the completion items of the words, the last one is the word under cursor.
+/// With the shell, the items come with descriptions in its format: `value:desc` for
+/// zsh `_describe` and `value<TAB>desc` for fish. The dir items end with '/', the
+/// shell should not add a space after them.
+pub fn complete(cfg: &Config, shell: Option<&Shell>, args: &[String]) -> Result<()> {
+    for item in collect(cfg, args)? {
+        match (shell, item.description) {
+            (Some(Shell::Zsh), Some(description)) => {
+                // The ':' in value should be escaped, otherwise `_describe` takes it
+                // as description.
+                println!("{}:{description}", item.value.replace(':', "\\:"))
+            }
+            (Some(Shell::Zsh), None) => println!("{}", item.value.replace(':', "\\:")),
+            (Some(Shell::Fish), Some(description)) => {
+                println!("{}\t{description}", item.value)
+            }
+            _ => println!("{}", item.value),
+        }
+    }
+    Ok(())
+}
+
+/// Count the completion items without printing them, see `ks bench`.
+pub fn count(cfg: &Config, args: &[String]) -> Result<usize> {
+    Ok(collect(cfg, args)?.len())
+}
+
+fn collect(cfg: &Config, args: &[String]) -> Result<Vec<Item>> {
+    let items = match CompleteWord::parse(args) {
+        CompleteWord::Positional {
+            words,
+            flags,
+            to_complete,
+        } => complete_positional(cfg, &words, &flags, to_complete)?,
+        CompleteWord::Flag { cmd, to_complete } => complete_flags(&cmd, to_complete),
+        CompleteWord::Value {
+            arg,
+            words,
+            prefix,
+            to_complete,
+        } => complete_value(cfg, &arg, &words, to_complete)?
+            .into_iter()
+            .map(|item| Item::with_description(format!("{prefix}{}", item.value), item.description))
+            .collect(),
+        CompleteWord::Other => Vec::new(),
+    };
+    Ok(items)
+}
+
+fn complete_positional(
+    cfg: &Config,
+    words: &[&str],
+    flags: &HashSet<String>,
+    to_complete: &str,
+) -> Result<Vec<Item>> {
+    // The namespace is given by the flag, only the context name is expected.
+    let namespace_given = flags.contains("namespace");
+    let items = match words {
+        [] | ["switch" | "exec" | "pin"]
+            if !namespace_given && cfg.namespace.split(to_complete).is_some() =>
+        {
+            let (name, to_complete) = cfg.namespace.split(to_complete).unwrap();
+            complete_inline_namespaces(cfg, name, to_complete)?
+        }
+        [] if namespace_given => complete_contexts(cfg, to_complete, true)?,
+        [] => {
+            let mut items: Vec<Item> = Args::command()
+                .get_subcommands()
+                .filter(|cmd| !cmd.is_hide_set())
+                .filter(|cmd| cmd.get_name().starts_with(to_complete))
+                .map(|cmd| {
+                    let about = cmd.get_about().map(|about| short_help(&about.to_string()));
+                    Item::with_description(cmd.get_name(), about)
+                })
+                .collect();
+            items.extend(complete_contexts(cfg, to_complete, true)?);
+            items
+        }
+        // The new names, only the dirs are completed.
+        ["link" | "copy" | "rename", _] => complete_dirs(cfg, to_complete)?,
+        ["merge", ..] => complete_name_list(cfg, to_complete)?,
+        ["share", ..] => complete_contexts(cfg, to_complete, false)?,
+        ["tag", name, ..] => {
+            let mut items = complete_removable_tags(name, to_complete)?;
+            items.extend(complete_tags(to_complete)?);
+            items
+        }
+        // Only the dir is accepted.
+        ["pick"] => complete_dirs(cfg, to_complete)?,
+        ["switch" | "describe" | "resolve" | "edit" | "delete" | "rename" | "revert" | "copy"
+        | "shell" | "exec" | "link" | "check" | "tag" | "pick-ns" | "pin"] => {
+            complete_contexts(cfg, to_complete, true)?
+        }
+        ["ns"] => complete_namespaces(cfg, to_complete)?,
+        ["discover"] => cfg
+            .discover
+            .keys()
+            .filter(|name| name.starts_with(to_complete))
+            .map(Item::new)
+            .collect(),
+        [sub, args @ ..] => {
+            // The positional args with possible values, such as the shell of `init`.
+            let cmd = Args::command();
+            let arg = cmd
+                .find_subcommand(sub)
+                .and_then(|cmd| cmd.get_positionals().nth(args.len()).cloned());
+            match arg {
+                Some(arg) => complete_value(cfg, &arg, words, to_complete)?,
+                None => vec![],
+            }
+        }
+    };
+    Ok(items)
+}
+
+fn complete_flags(cmd: &Command, to_complete: &str) -> Vec<Item> {
+    let mut items = Vec::new();
+    for arg in cmd.get_arguments().filter(|arg| !arg.is_hide_set()) {
+        let help = arg.get_help().map(|help| short_help(&help.to_string()));
+        let flags = arg
+            .get_long()
+            .map(|long| format!("--{long}"))
+            .into_iter()
+            .chain(arg.get_short().map(|short| format!("-{short}")));
+        for flag in flags {
+            if flag.starts_with(to_complete) {
+                items.push(Item::with_description(flag, help.clone()));
+            }
+        }
+    }
+    items
+}
+
+/// Complete the value of the flag or positional arg, the words are the subcommand
+/// name (if any) and the positional args before it.
+fn complete_value(cfg: &Config, arg: &Arg, words: &[&str], to_complete: &str) -> Result<Vec<Item>> {
+    match arg.get_id().as_str() {
+        "tag" => return complete_tags(to_complete),
+        // Only the namespaces, the contexts are not expected here.
+        "namespace" => return complete_flag_namespaces(cfg, words, to_complete),
+        // The target dir under the kube dir of the import commands.
+        "prefix" => return complete_dirs(cfg, to_complete),
+        _ => {}
+    }
+    match arg.get_value_hint() {
+        ValueHint::FilePath | ValueHint::AnyPath => return Ok(vec![Item::new(FILES_MARK)]),
+        ValueHint::DirPath => return Ok(vec![Item::new(DIRS_MARK)]),
+        _ => {}
+    }
+    Ok(arg
+        .get_possible_values()
+        .into_iter()
+        .filter(|value| !value.is_hide_set() && value.get_name().starts_with(to_complete))
+        .map(|value| {
+            let help = value.get_help().map(|help| short_help(&help.to_string()));
+            Item::with_description(value.get_name(), help)
+        })
+        .collect())
+}
+
+/// Complete the context names level by level, the dirs under the typed dir come
+/// with a trailing '/', so that `team/<TAB>` only shows that subtree. Only the
+/// typed dir is walked and the kubeconfigs are not parsed, to be instant.
+fn complete_contexts(cfg: &Config, to_complete: &str, skip_current: bool) -> Result<Vec<Item>> {
+    let current = env::var("KUBESWITCH_NAME").ok().filter(|_| skip_current);
+    let dir = match to_complete.rfind('/') {
+        Some(idx) => &to_complete[..=idx],
+        None => "",
+    };
+
+    let mut dirs = HashSet::new();
+    let mut items = Vec::new();
+    for name in list_names(cfg, dir)? {
+        if !name.starts_with(to_complete) || current.as_ref() == Some(&name) {
+            continue;
+        }
+        match name[dir.len()..].find('/') {
+            Some(idx) => {
+                let sub = &name[..dir.len() + idx + 1];
+                if dirs.insert(sub.to_string()) {
+                    items.push(Item::new(sub));
+                }
+            }
+            None => items.push(Item::new(name)),
+        }
+    }
+    Ok(items)
+}
+
+/// Complete the dirs for the new context name.
+fn complete_dirs(cfg: &Config, to_complete: &str) -> Result<Vec<Item>> {
+    Ok(complete_contexts(cfg, to_complete, false)?
+        .into_iter()
+        .filter(|item| item.value.ends_with('/'))
+        .collect())
+}
+
+/// Complete the last name of the ',' separated names, such as `ks merge`.
+fn complete_name_list(cfg: &Config, to_complete: &str) -> Result<Vec<Item>> {
+    let (prefix, to_complete) = match to_complete.rfind(',') {
+        Some(idx) => to_complete.split_at(idx + 1),
+        None => ("", to_complete),
+    };
+    let typed: HashSet<&str> = prefix.split(',').collect();
+    Ok(complete_contexts(cfg, to_complete, false)?
+        .into_iter()
+        .filter(|item| !typed.contains(item.value.as_str()))
+        .map(|item| Item::new(format!("{prefix}{}", item.value)))
+        .collect())
+}
+
+/// The context names under the dir, see [`KubeContext::list_dir`].
+fn list_names(cfg: &Config, dir: &str) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    walk_kubeconfigs(cfg, dir, |name, _| {
+        names.push(name.to_string());
+        Ok(())
+    })
+    .context("list contexts for completion")?;
+    names.extend(
+        vault::list(cfg)
+            .into_iter()
+            .filter(|name| name.starts_with(dir)),
+    );
+
+    if !cfg.tag_filter.is_empty() {
+        let tags = Tags::load()?;
+        names.retain(|name| tags.matches(name, &cfg.tag_filter));
+    }
+    Ok(names)
+}
+
+/// Complete the tags in `key=value` format, the keys come first with a trailing
+/// '=', then the values of the typed key.
+fn complete_tags(to_complete: &str) -> Result<Vec<Item>> {
+    let tags = Tags::load().context("load tags for completion")?;
+    let all = tags.all();
+    if to_complete.contains('=') {
+        return Ok(all
+            .into_iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .filter(|tag| tag.starts_with(to_complete))
+            .map(Item::new)
+            .collect());
+    }
+
+    let mut keys: BTreeMap<&str, usize> = BTreeMap::new();
+    for (key, _) in all {
+        *keys.entry(key).or_default() += 1;
+    }
+    Ok(keys
+        .into_iter()
+        .filter(|(key, _)| key.starts_with(to_complete))
+        .map(|(key, count)| {
+            let description = match count {
+                1 => String::from("1 value"),
+                _ => format!("{count} values"),
+            };
+            Item::with_description(format!("{key}="), Some(description))
+        })
+        .collect())
+}
+
+/// Complete the `key-` items to remove the tags of the context, see `ks tag`.
+fn complete_removable_tags(name: &str, to_complete: &str) -> Result<Vec<Item>> {
+    if to_complete.contains('=') {
+        return Ok(Vec::new());
+    }
+    let tags = Tags::load().context("load tags for completion")?;
+    let tags = match tags.get(name) {
+        Some(tags) => tags,
+        None => return Ok(Vec::new()),
+    };
+    Ok(tags
+        .iter()
+        .filter(|(key, _)| format!("{key}-").starts_with(to_complete))
+        .map(|(key, value)| {
+            let description = format!("remove {key}={value}");
+            Item::with_description(format!("{key}-"), Some(description))
+        })
+        .collect())
+}
+
+/// Get the context with the exact name, the history, dirs, tombstones and fuzzy
+/// matches are not resolved, as they might prompt.
+fn get_exact_context<'a>(cfg: &'a Config, name: &str) -> Result<KubeContext<'a>> {
+    match KubeContext::resolve(cfg, name)? {
+        Resolution::File { .. } | Resolution::Vault => {
+            KubeContext::select(cfg, &Some(name.to_string()), SelectOption::GetRequired)
+        }
+        _ => bail!("context '{name}' not found"),
+    }
+}
+
+fn complete_inline_namespaces(cfg: &Config, name: &str, to_complete: &str) -> Result<Vec<Item>> {
+    let ctx = get_exact_context(cfg, name).context("get context for completing namespace")?;
+    let namespaces = ctx
+        .list_namespaces_cached()
+        .context("list namespaces for completion")?;
+    Ok(namespaces
+        .into_iter()
+        .filter(|ns| ns.starts_with(to_complete))
+        .map(|ns| Item::new(format!("{name}{}{ns}", cfg.namespace.separator)))
+        .collect())
+}
+
+fn complete_namespaces(cfg: &Config, to_complete: &str) -> Result<Vec<Item>> {
+    let ctx = KubeContext::current(cfg).context("get current context for completing namespace")?;
+    let namespaces = ctx
+        .list_namespaces_cached()
+        .context("list namespaces for completion")?;
+    Ok(namespaces
+        .into_iter()
+        .filter(|ns| *ns != ctx.namespace && ns.starts_with(to_complete))
+        .map(Item::new)
+        .collect())
+}
+
+/// Complete the namespaces of `--namespace`, of the context given in the words, or
+/// the current context for the bare `ks -n <TAB>`. Nothing if the context is to be
+/// selected later, such as `ks . -n <TAB>` and `ks switch -n <TAB>`.
+fn complete_flag_namespaces(cfg: &Config, words: &[&str], to_complete: &str) -> Result<Vec<Item>> {
+    let name = match words {
+        ["switch", name, ..] | [name, ..] if *name != "switch" => Some(*name),
+        ["switch", ..] => return Ok(Vec::new()),
+        _ => None,
+    };
+    if name == Some(".") {
+        return Ok(Vec::new());
+    }
+    let name = name.map(|name| match cfg.namespace.split(name) {
+        Some((name, _)) => name,
+        None => name,
+    });
+    let ctx = match name {
+        Some(name) => get_exact_context(cfg, name),
+        None => KubeContext::current(cfg),
+    }
+    .context("get context for completing namespace")?;
+    let namespaces = ctx
+        .list_namespaces_cached()
+        .context("list namespaces for completion")?;
+    Ok(namespaces
+        .into_iter()
+        .filter(|ns| ns.starts_with(to_complete))
+        .map(Item::new)
+        .collect())
+}
+
+/// The first clause of the help, to fit in the completion menu.
+fn short_help(help: &str) -> String {
+    let help = help.lines().next().unwrap_or("");
+    let end = [", ", ". ", " ("]
+        .iter()
+        .filter_map(|sep| help.find(sep))
+        .min()
+        .unwrap_or(help.len());
+    help[..end].trim_end_matches('.').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The comparable form of [`CompleteWord`].
+    #[derive(Debug, PartialEq)]
+    enum Parsed {
+        Positional {
+            words: Vec<String>,
+            flags: Vec<String>,
+            to_complete: String,
+        },
+        Flag {
+            cmd: String,
+            to_complete: String,
+        },
+        Value {
+            arg: String,
+            words: Vec<String>,
+            prefix: String,
+            to_complete: String,
+        },
+        Other,
+    }
+
+    fn parse(args: &[&str]) -> Parsed {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let to_strings = |words: Vec<&str>| words.into_iter().map(String::from).collect();
+        match CompleteWord::parse(&args) {
+            CompleteWord::Positional {
+                words,
+                flags,
+                to_complete,
+            } => {
+                let mut flags: Vec<_> = flags.into_iter().collect();
+                flags.sort();
+                Parsed::Positional {
+                    words: to_strings(words),
+                    flags,
+                    to_complete: to_complete.to_string(),
+                }
+            }
+            CompleteWord::Flag { cmd, to_complete } => Parsed::Flag {
+                cmd: cmd.get_name().to_string(),
+                to_complete: to_complete.to_string(),
+            },
+            CompleteWord::Value {
+                arg,
+                words,
+                prefix,
+                to_complete,
+            } => Parsed::Value {
+                arg: arg.get_id().to_string(),
+                words: to_strings(words),
+                prefix: prefix.to_string(),
+                to_complete: to_complete.to_string(),
+            },
+            CompleteWord::Other => Parsed::Other,
+        }
+    }
+
+    fn positional(words: &[&str], flags: &[&str], to_complete: &str) -> Parsed {
+        Parsed::Positional {
+            words: words.iter().map(|word| word.to_string()).collect(),
+            flags: flags.iter().map(|flag| flag.to_string()).collect(),
+            to_complete: to_complete.to_string(),
+        }
+    }
+
+    fn value(arg: &str, words: &[&str], prefix: &str, to_complete: &str) -> Parsed {
+        Parsed::Value {
+            arg: arg.to_string(),
+            words: words.iter().map(|word| word.to_string()).collect(),
+            prefix: prefix.to_string(),
+            to_complete: to_complete.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_positional() {
+        assert_eq!(parse(&[]), positional(&[], &[], ""));
+        assert_eq!(parse(&["pro"]), positional(&[], &[], "pro"));
+        assert_eq!(
+            parse(&["switch", "pro"]),
+            positional(&["switch"], &[], "pro")
+        );
+        assert_eq!(
+            parse(&["rename", "prod", ""]),
+            positional(&["rename", "prod"], &[], "")
+        );
+        // The value of the flag is not a positional arg.
+        assert_eq!(
+            parse(&["--wrap", "foo", ""]),
+            positional(&[], &["wrap"], "")
+        );
+        assert_eq!(
+            parse(&["--wrap=foo", "pro"]),
+            positional(&[], &["wrap"], "pro")
+        );
+        // The flags without value do not take the next word.
+        assert_eq!(
+            parse(&["-q", "--offline", "switch", ""]),
+            positional(&["switch"], &[], "")
+        );
+    }
+
+    #[test]
+    fn test_parse_namespace_given() {
+        assert_eq!(
+            parse(&["--namespace", "kube-system", ""]),
+            positional(&[], &["namespace"], "")
+        );
+        assert_eq!(
+            parse(&["-n", "kube-system", "pro"]),
+            positional(&[], &["namespace"], "pro")
+        );
+        assert_eq!(
+            parse(&["-n=kube-system", ""]),
+            positional(&[], &["namespace"], "")
+        );
+        // The rest of the combined short flags is the value.
+        assert_eq!(
+            parse(&["-nkube-system", ""]),
+            positional(&[], &["namespace"], "")
+        );
+        assert_eq!(
+            parse(&["-yn", "kube-system", ""]),
+            positional(&[], &["namespace"], "")
+        );
+        assert_eq!(
+            parse(&["-t", "env=prod", "-n", "default", ""]),
+            positional(&[], &["namespace", "tag"], "")
+        );
+    }
+
+    #[test]
+    fn test_parse_value() {
+        assert_eq!(parse(&["--namespace", ""]), value("namespace", &[], "", ""));
+        assert_eq!(parse(&["-n", "ku"]), value("namespace", &[], "", "ku"));
+        assert_eq!(parse(&["-n="]), value("namespace", &[], "-n=", ""));
+        assert_eq!(
+            parse(&["--namespace=ku"]),
+            value("namespace", &[], "--namespace=", "ku")
+        );
+        // The value is for the last one of the combined short flags.
+        assert_eq!(parse(&["-dn", ""]), value("namespace", &[], "", ""));
+        assert_eq!(parse(&["-yn", "ku"]), value("namespace", &[], "", "ku"));
+        assert_eq!(parse(&["-yn="]), value("namespace", &[], "-yn=", ""));
+        assert_eq!(
+            parse(&["switch", "--tag", ""]),
+            value("tag", &["switch"], "", "")
+        );
+        assert_eq!(
+            parse(&["--state-dir", "/tmp/ks"]),
+            value("state_dir", &[], "", "/tmp/ks")
+        );
+        // Unknown flag in the word under cursor.
+        assert_eq!(parse(&["--unknown=a"]), Parsed::Other);
+    }
+
+    #[test]
+    fn test_parse_flag() {
+        let flag = |cmd: &str, to_complete: &str| Parsed::Flag {
+            cmd: cmd.to_string(),
+            to_complete: to_complete.to_string(),
+        };
+        assert_eq!(parse(&["-"]), flag("kubeswitch", "-"));
+        assert_eq!(parse(&["--na"]), flag("kubeswitch", "--na"));
+        assert_eq!(parse(&["-n", "default", "--"]), flag("kubeswitch", "--"));
+        assert_eq!(parse(&["switch", "--"]), flag("switch", "--"));
+    }
+
+    #[test]
+    fn test_parse_double_dash() {
+        assert_eq!(parse(&["--", ""]), Parsed::Other);
+        assert_eq!(parse(&["exec", "prod", "--", "get", ""]), Parsed::Other);
+        // The `--` as the value of a flag is not the separator.
+        assert_eq!(
+            parse(&["-n", "--", ""]),
+            positional(&[], &["namespace"], "")
+        );
+    }
+}
@@ -1,14 +1,22 @@
 use std::borrow::Cow;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::env;
+use std::fmt;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 
 use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
+use crate::glob;
+use crate::tags::{parse_tag, Tags};
+
+/// The valid characters of the context names given by user.
+pub const NAME_REGEX: &str = "^@?[a-zA-Z-_0-9/:]+$";
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Config {
     #[serde(default = "Config::default_cmd")]
@@ -17,15 +25,118 @@ pub struct Config {
     #[serde(default = "Config::default_editor")]
     pub editor: String,
 
+    /// The args passed to the editor, `{file}` is replaced with the file to edit,
+    /// which is appended if absent. Default is the wait flag for the known GUI
+    /// editors, such as `--wait` for VS Code.
+    #[serde(default = "Vec::new")]
+    pub editor_args: Vec<String>,
+
+    /// Show the diff and ask for confirmation before saving the edited kubeconfig.
+    #[serde(default = "default_disable")]
+    pub confirm_edit: bool,
+
+    /// Also check the edited kubeconfig with `kubectl config view`, besides the
+    /// builtin structure check.
+    #[serde(default = "default_disable")]
+    pub verify_edit: bool,
+
+    /// The dir for the temp files of editing, they are private to the user. Default
+    /// is `edit` in the runtime dir.
+    pub edit_tmp_dir: Option<String>,
+
     #[serde(default = "KubeConfig::default")]
     pub kube: KubeConfig,
 
+    #[serde(default = "default_disable")]
+    pub offline: bool,
+
     pub k9s: Option<K9sConfig>,
 
     pub ns_alias: Option<Vec<NsAlias>>,
 
+    /// The namespace to use when the kubeconfig of the context does not set one.
+    pub default_namespace: Option<Vec<DefaultNamespace>>,
+
+    #[serde(default = "NamespaceConfig::default")]
+    pub namespace: NamespaceConfig,
+
+    pub vault: Option<VaultConfig>,
+
+    /// Keep the kubeconfig files encrypted at rest with age, see [`EncryptionConfig`].
+    pub encryption: Option<EncryptionConfig>,
+
+    pub tools: Option<ToolsConfig>,
+
+    pub gitops: Option<Vec<GitOps>>,
+
+    pub protect: Option<Vec<Protect>>,
+
+    pub fingerprint: Option<FingerprintConfig>,
+
+    #[serde(default = "TlsConfig::default")]
+    pub tls: TlsConfig,
+
+    /// Switch to the context pinned by the `.kubeswitch` file when entering its dir,
+    /// see `ks pin` and `ks trust`.
+    #[serde(default = "default_disable")]
+    pub auto_switch: bool,
+
+    /// The bare `ks` switches to the previous context in history, the same as
+    /// `ks -`, instead of launching the selector. `ks .` still launches it.
+    #[serde(default = "default_disable")]
+    pub previous_by_default: bool,
+
+    /// The order of the contexts in the selector and `ks list`.
+    #[serde(default = "Config::default_sort")]
+    pub sort: ContextSort,
+
+    /// The naming convention of the contexts, checked by `ks lint`.
+    pub naming: Option<NamingConfig>,
+
+    /// The color and emoji hints of the contexts, exported by the switch protocol to
+    /// render in shell prompt. The first matched one is used.
+    pub prompt_hint: Option<Vec<PromptHint>>,
+
+    /// The template of `ks prompt`, the placeholders are `{name}`, `{namespace}`
+    /// and `{emoji}` (followed by a space if `prompt_hint` sets it).
+    #[serde(default = "Config::default_prompt_format")]
+    pub prompt_format: String,
+
+    #[serde(skip)]
+    prompt_parts: Vec<PromptPart>,
+
+    pub hooks: Option<HooksConfig>,
+
+    #[serde(default = "HistoryConfig::default")]
+    pub history: HistoryConfig,
+
+    #[serde(default = "BackupConfig::default")]
+    pub backup: BackupConfig,
+
+    #[serde(default = "ImportConfig::default")]
+    pub import: ImportConfig,
+
+    /// The cloud providers to discover clusters from, see `ks discover`. The builtin
+    /// `eks`, `gke` and `aks` are added if they are not configured.
+    #[serde(default = "BTreeMap::new")]
+    pub discover: BTreeMap<String, DiscoverProvider>,
+
+    #[serde(default = "SelectorConfig::default")]
+    pub selector: SelectorConfig,
+
+    #[serde(default = "DaemonConfig::default")]
+    pub daemon: DaemonConfig,
+
+    /// Sync `kube.dir` with a git remote, see [`SyncConfig`].
+    pub sync: Option<SyncConfig>,
+
     #[serde(skip)]
     pub path: Option<PathBuf>,
+
+    /// Only the contexts with all these tags are listed and selected, from the
+    /// `--tag` flags.
+    #[serde(skip)]
+    pub tag_filter: Vec<(String, String)>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -39,11 +150,79 @@ pub struct KubeConfig {
     #[serde(default = "KubeConfig::default_dir")]
     pub dir: String,
 
+    /// The per-user layer over `dir`, useful when `dir` is shared by a team. Its
+    /// kubeconfig files shadow the ones in `dir` by name, and all the write
+    /// operations go to it.
+    pub user_dir: Option<String>,
+
     #[serde(default = "default_disable")]
     pub export_kubeconfig: bool,
 
     #[serde(default = "default_disable")]
     pub update_context: bool,
+
+    #[serde(default = "default_disable")]
+    pub follow_renamed: bool,
+
+    /// Warn and ask before switching away from a context which still has running
+    /// `port-forward` or `exec` processes, since they keep using the old cluster.
+    #[serde(default = "default_disable")]
+    pub guard_forwards: bool,
+
+    /// For the kubeconfig files with multiple contexts, pass its `current-context`
+    /// to the aliased commands by `--context`, so that the shells sharing the file
+    /// won't be affected when it is changed.
+    #[serde(default = "default_disable")]
+    pub pass_context: bool,
+
+    /// Symlink mode, for the users without the wrap script: maintain the symlink
+    /// (such as `~/.kube/config`) to the kubeconfig of the switched context, instead
+    /// of setting the shell env.
+    pub symlink: Option<String>,
+
+    /// The extra args appended to the command listing namespaces, such as
+    /// `["-l", "team=mine"]`.
+    #[serde(default = "Vec::new")]
+    pub namespace_list_args: Vec<String>,
+
+    /// What to do when the namespace to switch to does not exist in the cluster.
+    #[serde(default = "KubeConfig::default_create_namespace")]
+    pub create_namespace: CreateNamespace,
+
+    /// Where to read the namespace of the current context from, useful when
+    /// `update_context` is off and the kubeconfig might be changed by others, such
+    /// as `kubectl config set-context`.
+    #[serde(default = "KubeConfig::default_namespace_source")]
+    pub namespace_source: NamespaceSource,
+
+    /// For the kubeconfig files larger than this, in bytes, only parse their
+    /// `contexts` and `current-context` to get the namespace when listing, skipping
+    /// the embedded certificates and CA bundles. 0 always parses the whole file.
+    #[serde(default = "KubeConfig::default_lazy_parse_size")]
+    pub lazy_parse_size: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NamespaceSource {
+    /// The namespace switched in shell.
+    Env,
+    /// The namespace in kubeconfig file.
+    File,
+    /// The kubeconfig file wins if it was modified after the last switch to the
+    /// context, otherwise the shell env.
+    Merge,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CreateNamespace {
+    /// Do not check, switch to the namespace anyway.
+    Never,
+    /// Ask to create it or abort.
+    Prompt,
+    /// Create it without asking.
+    Auto,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -69,240 +248,1934 @@ pub struct NsAlias {
     parsed_regex: Option<Regex>,
 }
 
-impl Config {
-    const CONFIG_PATH_ENV: &'static str = "KUBESWITCH_CONFIG_PATH";
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DefaultNamespace {
+    pub regex: Option<String>,
 
-    pub fn load() -> Result<Config> {
-        let path = Self::get_path().context("get config path")?;
-        let mut cfg = match path.as_ref() {
-            Some(path) => Self::read(path)?,
-            None => Self::default(),
-        };
-        cfg.path = path;
-        cfg.validate().context("validate config")?;
-        Ok(cfg)
-    }
+    pub names: Option<HashSet<String>>,
 
-    pub fn match_ns_alias<S: AsRef<str>>(&self, name: S) -> Option<Vec<Cow<str>>> {
-        if let Some(alias_list) = self.ns_alias.as_ref() {
-            for alias in alias_list.iter() {
-                if let Some(alias) = alias.match_alias(name.as_ref()) {
-                    return Some(alias);
-                }
-            }
-        }
-        None
-    }
+    pub namespace: String,
 
-    fn get_path() -> Result<Option<PathBuf>> {
-        let path = match env::var_os(Self::CONFIG_PATH_ENV) {
-            Some(path) => PathBuf::from(path),
-            None => {
-                let home_dir = get_home_dir()?;
-                home_dir.join(".config").join("kubeswitch.toml")
-            }
-        };
+    #[serde(skip)]
+    parsed_regex: Option<Regex>,
+}
 
-        match fs::metadata(&path) {
-            Ok(meta) => {
-                if meta.is_dir() {
-                    bail!(
-                        "config path '{}' is a directory, require file",
-                        path.display()
-                    );
-                }
-                Ok(Some(path))
-            }
-            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
-            Err(err) => Err(err).with_context(|| format!("stat config file '{}'", path.display())),
-        }
-    }
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct NamespaceConfig {
+    #[serde(default = "Vec::new")]
+    pub order: Vec<String>,
 
-    fn read<P: AsRef<Path>>(path: P) -> Result<Config> {
-        let data = fs::read(path).context("read config file")?;
-        let config = String::from_utf8(data).context("decode config file as utf-8")?;
-        toml::from_str(&config).context("parse config toml")
-    }
+    #[serde(default = "Vec::new")]
+    pub pin: Vec<String>,
 
-    fn validate(&mut self) -> Result<()> {
-        if self.cmd.is_empty() {
-            bail!("`cmd` cannot be empty");
-        }
-        if self.editor.is_empty() {
-            bail!("`editor` cannot be empty");
-        }
-        self.editor = expand_env(&self.editor).context("expand env for `editor`")?;
+    #[serde(default = "Vec::new")]
+    pub contexts: Vec<NamespaceRule>,
 
-        self.kube.validate().context("validate kube")?;
+    /// The separator to set context and namespace in one name, such as
+    /// `prod::payments`.
+    #[serde(default = "NamespaceConfig::default_separator")]
+    pub separator: String,
 
-        if let Some(k9s) = self.k9s.as_mut() {
-            k9s.validate().context("validate k9s")?;
-        }
+    /// Check if the current namespace still exists when the shell starts, using the
+    /// cached namespaces only.
+    #[serde(default = "NamespaceConfig::default_check")]
+    pub check: NamespaceCheck,
 
-        if let Some(ns_alias) = self.ns_alias.as_mut() {
-            for (idx, alias) in ns_alias.iter_mut().enumerate() {
-                alias
-                    .validate()
-                    .with_context(|| format!("validate ns_alias index {idx}"))?;
-            }
-        }
+    /// Serve the namespaces from the cache younger than this, in seconds, instead
+    /// of listing them from the cluster. Useful with the cache warmed by `ks daemon`.
+    /// 0 disables it.
+    #[serde(default = "NamespaceConfig::default_cache_ttl")]
+    pub cache_ttl: u64,
 
-        Ok(())
-    }
+    /// When another shell is already listing the namespaces of the same context,
+    /// wait this long, in milliseconds, for its result before serving the stale
+    /// cache, so that only one kubectl call hits the cluster.
+    #[serde(default = "NamespaceConfig::default_refresh_wait_ms")]
+    pub refresh_wait_ms: u64,
 
-    fn default() -> Config {
-        Config {
-            cmd: Self::default_cmd(),
-            editor: Self::default_editor(),
-            kube: KubeConfig::default(),
-            k9s: None,
-            ns_alias: None,
-            path: None,
-        }
-    }
+    /// When switching namespace without a current context, select a context first
+    /// and then continue to the namespace, instead of failing.
+    #[serde(default = "default_disable")]
+    pub auto_pick_context: bool,
+}
 
-    fn default_cmd() -> String {
-        String::from("ks")
-    }
+/// What to do when the current namespace no longer exists in the cluster.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum NamespaceCheck {
+    /// Do not check.
+    Off,
+    Warn,
+    /// Warn and switch back to the namespace in kubeconfig.
+    Reset,
+}
 
-    fn default_editor() -> String {
-        String::from("$EDITOR")
-    }
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct NamespaceRule {
+    pub regex: Option<String>,
+
+    pub names: Option<HashSet<String>>,
+
+    pub order: Option<Vec<String>>,
+
+    pub pin: Option<Vec<String>>,
+
+    #[serde(skip)]
+    parsed_regex: Option<Regex>,
 }
 
-impl KubeConfig {
-    fn validate(&mut self) -> Result<()> {
-        if self.exec.is_empty() {
-            bail!("`kube.exec` cannot be empty");
-        }
-        self.exec = expand_env(&self.exec).context("expand env for `kube.exec`")?;
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct VaultConfig {
+    #[serde(default = "VaultConfig::default_exec")]
+    pub exec: String,
 
-        if self.cmd.is_empty() {
-            bail!("`kube.cmd` cannot be empty");
-        }
+    #[serde(default = "VaultConfig::default_dir")]
+    pub dir: String,
 
-        if self.dir.is_empty() {
-            bail!("`kube.dir` cannot be empty");
-        }
-        self.dir = expand_env(&self.dir).context("expand env for `kube.dir`")?;
+    #[serde(default = "VaultConfig::default_prefix")]
+    pub prefix: String,
 
-        Ok(())
-    }
+    #[serde(default = "VaultConfig::default_path")]
+    pub path: String,
 
-    fn default() -> KubeConfig {
-        KubeConfig {
-            exec: Self::default_exec(),
-            cmd: Self::default_cmd(),
-            dir: Self::default_dir(),
-            export_kubeconfig: default_disable(),
-            update_context: default_disable(),
-        }
-    }
+    #[serde(default = "Vec::new")]
+    pub contexts: Vec<VaultContextConfig>,
+}
 
-    fn default_exec() -> String {
-        String::from("kubectl")
-    }
+/// The kubeconfig files with `.age` suffix in the kube dirs are encrypted with age,
+/// they are decrypted to the private `dir` on use, and encrypted back after being
+/// changed.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct EncryptionConfig {
+    #[serde(default = "EncryptionConfig::default_exec")]
+    pub exec: String,
 
-    fn default_cmd() -> String {
-        String::from("k")
-    }
+    /// The age identity file to decrypt.
+    pub identity: String,
 
-    fn default_dir() -> String {
-        String::from("~/.kube/config")
-    }
+    /// The age recipients to encrypt, such as the public key of the identity.
+    pub recipients: Vec<String>,
+
+    /// The private dir for the decrypted kubeconfig files.
+    #[serde(default = "EncryptionConfig::default_dir")]
+    pub dir: String,
 }
 
-impl K9sConfig {
-    fn validate(&mut self) -> Result<()> {
-        if self.exec.is_empty() {
-            bail!("`k9s.exec` cannot be empty");
-        }
-        self.exec = expand_env(&self.exec).context("expand env for `k9s.exec`")?;
+/// The `kube.dir` is used as a git working tree to share the contexts across
+/// machines, by `ks sync push` and `ks sync pull`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SyncConfig {
+    #[serde(default = "SyncConfig::default_exec")]
+    pub exec: String,
 
-        if self.cmd.is_empty() {
-            bail!("`k9s.cmd` cannot be empty");
-        }
+    /// The git remote url, such as a private repo.
+    pub remote: String,
 
-        Ok(())
-    }
+    #[serde(default = "SyncConfig::default_branch")]
+    pub branch: String,
 
-    fn default_exec() -> String {
-        String::from("k9s")
-    }
+    /// The files not to sync, in gitignore syntax, relative to `kube.dir`.
+    #[serde(default = "Vec::new")]
+    pub ignore: Vec<String>,
 
-    fn default_cmd() -> String {
-        String::from("kk")
-    }
+    /// How to treat the credentials in the kubeconfig files when pushing.
+    #[serde(default = "SyncConfig::default_secrets")]
+    pub secrets: SyncSecrets,
 }
 
-impl NsAlias {
-    fn match_alias<S: AsRef<str>>(&self, name: S) -> Option<Vec<Cow<str>>> {
-        let mut is_match = false;
-        if let Some(regex) = self.parsed_regex.as_ref() {
-            is_match = regex.is_match(name.as_ref());
-        }
-        if let Some(names) = self.names.as_ref() {
-            for match_name in names.iter() {
-                if match_name == name.as_ref() {
-                    is_match = true;
-                    break;
-                }
-            }
-        }
-
-        if is_match {
-            Some(
-                self.alias
-                    .iter()
-                    .map(|s| Cow::Borrowed(s.as_str()))
-                    .collect(),
-            )
-        } else {
-            None
-        }
-    }
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncSecrets {
+    /// Push the kubeconfig files as they are.
+    Plain,
+    /// Remove the credentials of the users before committing, only useful when the
+    /// credentials are issued by exec plugins on each machine.
+    Redact,
+    /// Refuse to push the kubeconfig files not encrypted by `encryption`.
+    Encrypted,
+}
 
-    fn validate(&mut self) -> Result<()> {
-        if self.alias.is_empty() {
-            bail!("`ns_alias.alias` cannot be empty");
-        }
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct VaultContextConfig {
+    pub name: String,
 
-        let mut has_regex = false;
-        if let Some(regex) = self.regex.as_ref() {
-            let regex =
-                Regex::new(regex).with_context(|| format!("parse ns_alias regex '{regex}'"))?;
-            self.parsed_regex = Some(regex);
-            has_regex = true;
-        }
+    pub server: String,
 
-        let mut has_names = false;
-        if let Some(names) = self.names.as_ref() {
-            has_names = !names.is_empty();
-        }
+    pub ca: Option<String>,
 
-        if !has_regex && !has_names {
-            bail!("ns_alias must have at least regex or names");
-        }
+    #[serde(default = "default_namespace")]
+    pub namespace: String,
 
-        Ok(())
-    }
+    pub path: Option<String>,
 }
 
-fn default_disable() -> bool {
-    false
-}
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ToolsConfig {
+    #[serde(default = "ToolsConfig::default_path")]
+    pub path: String,
 
-fn expand_env<S: AsRef<str>>(s: S) -> Result<String> {
-    let s = shellexpand::full(s.as_ref())
-        .with_context(|| format!("expand env for '{}'", s.as_ref()))?;
-    Ok(s.to_string())
+    #[serde(default = "Vec::new")]
+    pub pin: Vec<ToolsPin>,
 }
 
-fn get_home_dir() -> Result<PathBuf> {
-    match env::var_os("HOME") {
-        Some(home) => Ok(PathBuf::from(home)),
-        None => bail!(
-            "$HOME env not found in your system, please make sure that you are in an UNIX system"
-        ),
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ToolsPin {
+    pub regex: Option<String>,
+
+    pub names: Option<HashSet<String>>,
+
+    pub versions: BTreeMap<String, String>,
+
+    #[serde(skip)]
+    parsed_regex: Option<Regex>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct GitOps {
+    pub regex: Option<String>,
+
+    pub names: Option<HashSet<String>>,
+
+    pub repo: String,
+
+    #[serde(default = "GitOps::default_message")]
+    pub message: String,
+
+    #[serde(default = "Vec::new")]
+    pub block: Vec<String>,
+
+    #[serde(skip)]
+    parsed_regex: Option<Regex>,
+}
+
+/// The protected contexts, accessing them (switch, shell and exec) needs to pass
+/// the check of the risk level.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct Protect {
+    pub regex: Option<String>,
+
+    pub names: Option<HashSet<String>>,
+
+    #[serde(default = "Protect::default_level")]
+    pub level: RiskLevel,
+
+    /// The message shown when accessing, `{name}` is replaced with the context name.
+    #[serde(default = "Protect::default_message")]
+    pub message: String,
+
+    #[serde(skip)]
+    parsed_regex: Option<Regex>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RiskLevel {
+    /// Only show the message.
+    Warn,
+    /// Ask for y/n.
+    Confirm,
+    /// The context name must be typed to proceed.
+    TypeName,
+}
+
+/// How to handle the clusters without proper TLS verification, checked when
+/// switching and by `ks check`. The imported dev kubeconfigs often carry them
+/// silently.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TlsConfig {
+    /// The cluster sets `insecure-skip-tls-verify`, or its server is plain http.
+    #[serde(default = "TlsConfig::default_severity")]
+    pub insecure: TlsSeverity,
+
+    /// The cluster has no CA configured, so the system roots are trusted.
+    #[serde(default = "TlsConfig::default_severity")]
+    pub missing_ca: TlsSeverity,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TlsSeverity {
+    Off,
+    Warn,
+    /// Ask for y/n before switching.
+    Confirm,
+    /// Refuse to switch.
+    Deny,
+}
+
+/// The `kube.dir` points to a regular file, usually the merged `~/.kube/config`,
+/// while it should be the dir holding one kubeconfig file per context.
+#[derive(Debug)]
+pub struct KubeDirIsFile {
+    pub dir: String,
+}
+
+impl KubeDirIsFile {
+    /// The dir to migrate to.
+    pub const MIGRATE_DIR: &'static str = "~/.kube/configs";
+}
+
+impl fmt::Display for KubeDirIsFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "`kube.dir` '{}' is a file, it should be the dir holding one kubeconfig file per context, such as '{}'",
+            self.dir,
+            Self::MIGRATE_DIR
+        )
+    }
+}
+
+impl std::error::Error for KubeDirIsFile {}
+
+/// The prompt hint matches the contexts by tag or by the risk level of protect.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PromptHint {
+    /// Match the contexts with the tag, in `key=value` format.
+    pub tag: Option<String>,
+
+    /// Match the protected contexts with the risk level.
+    pub level: Option<RiskLevel>,
+
+    pub color: Option<PromptColor>,
+
+    pub emoji: Option<String>,
+
+    #[serde(skip)]
+    parsed_tag: Option<(String, String)>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PromptColor {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct FingerprintConfig {
+    #[serde(default = "default_disable")]
+    pub enable: bool,
+
+    /// The human labels for the cluster fingerprints.
+    #[serde(default = "BTreeMap::new")]
+    pub labels: BTreeMap<String, String>,
+}
+
+/// The commands run around switching, via `sh -c`. The context is passed by env
+/// `KUBESWITCH_NAME`, `KUBESWITCH_NAMESPACE` and `KUBECONFIG`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct HooksConfig {
+    /// Run before switching, the switching is aborted if any of them fails.
+    #[serde(default = "Vec::new")]
+    pub pre_switch: Vec<String>,
+
+    /// Run after switching, the failures are only warned.
+    #[serde(default = "Vec::new")]
+    pub post_switch: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct HistoryConfig {
+    #[serde(default = "HistoryConfig::default_path")]
+    pub path: String,
+
+    /// The max number of entries to keep, the oldest ones are dropped. 0 means
+    /// unlimited.
+    #[serde(default = "HistoryConfig::default_max_entries")]
+    pub max_entries: usize,
+
+    /// Collapse the consecutive entries with the same context and namespace.
+    #[serde(default = "default_enable")]
+    pub dedup: bool,
+}
+
+/// The kubeconfig files are backed up before being overwritten, such as editing
+/// and switching namespace, see `ks revert`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct BackupConfig {
+    #[serde(default = "BackupConfig::default_dir")]
+    pub dir: String,
+
+    /// The max number of backups to keep for each context, the oldest ones are
+    /// dropped. 0 disables the backup.
+    #[serde(default = "BackupConfig::default_keep")]
+    pub keep: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct NamingConfig {
+    /// The context names should match the regex.
+    pub pattern: String,
+
+    /// The fixes applied in order to suggest the canonical name of a violating
+    /// context.
+    #[serde(default = "Vec::new")]
+    pub fixes: Vec<NamingFix>,
+
+    /// Prepended to the suggested name if it still violates the convention after
+    /// the fixes, such as the env prefix `dev-`.
+    pub prefix: Option<String>,
+
+    #[serde(skip)]
+    parsed_pattern: Option<Regex>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum NamingFix {
+    /// Convert to lowercase.
+    Lowercase,
+    /// Replace '_' and '.' with '-', and squeeze the repeated ones.
+    Hyphens,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SelectorConfig {
+    /// The selector command, such as fzf, sk and peco. The builtin selector is used
+    /// if it is not installed.
+    #[serde(default = "SelectorConfig::default_exec")]
+    pub exec: String,
+
+    /// The extra args passed to the selector command.
+    #[serde(default = "Vec::new")]
+    pub args: Vec<String>,
+
+    /// Show the details of the focused context in the preview pane, only for fzf
+    /// and sk.
+    #[serde(default = "default_enable")]
+    pub preview: bool,
+}
+
+/// The background tasks of `ks daemon`, the intervals are in seconds and 0
+/// disables the task.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DaemonConfig {
+    /// The providers in `discover` to import again, refreshing their contexts.
+    #[serde(default = "Vec::new")]
+    pub discover: Vec<String>,
+
+    #[serde(default = "DaemonConfig::default_discover_interval")]
+    pub discover_interval: u64,
+
+    /// Warm the namespace caches of all the contexts.
+    #[serde(default = "DaemonConfig::default_namespaces_interval")]
+    pub namespaces_interval: u64,
+
+    /// Check the expiry of the client and CA certificates of all the contexts.
+    #[serde(default = "DaemonConfig::default_certs_interval")]
+    pub certs_interval: u64,
+
+    /// The delay in milliseconds between two contexts when warming the namespace
+    /// caches, to limit the requests to the shared clusters.
+    #[serde(default = "DaemonConfig::default_delay_ms")]
+    pub delay_ms: u64,
+
+    /// Every interval is randomly shortened or lengthened by this fraction, so that
+    /// the daemons of a team do not hit the clusters at the same time.
+    #[serde(default = "DaemonConfig::default_jitter")]
+    pub jitter: f64,
+
+    /// The openssl command to read the certificates.
+    #[serde(default = "DaemonConfig::default_openssl")]
+    pub openssl: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ImportConfig {
+    #[serde(default = "ImportConfig::default_naming")]
+    pub naming: ImportNaming,
+
+    #[serde(default = "ImportConfig::default_conflict")]
+    pub conflict: ImportConflict,
+}
+
+/// The shell command templates to discover the clusters of a cloud provider. The
+/// `{field}` placeholders are replaced with the fields of each listed cluster.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct DiscoverProvider {
+    /// List the clusters, one per line, the fields are separated by whitespace.
+    pub list: String,
+
+    /// The names of the fields of each line, in order. The fields other than
+    /// `cluster` are tagged to the imported contexts, for `ks list` to group them.
+    #[serde(default = "DiscoverProvider::default_fields")]
+    pub fields: Vec<String>,
+
+    /// Generate the kubeconfig of the cluster, into the file `{kubeconfig}`, which
+    /// is also set as the `KUBECONFIG` env.
+    pub kubeconfig: String,
+
+    /// The context name template.
+    pub name: String,
+}
+
+/// How to name the imported contexts.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportNaming {
+    /// Use the file name (without extension).
+    File,
+    /// Use the context name inside the kubeconfig.
+    Context,
+}
+
+/// The order of the contexts, the ties are broken by name.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextSort {
+    Name,
+    /// The recently used ones first.
+    Recent,
+    /// The frequently used ones first, then by recency.
+    Usage,
+}
+
+/// What to do when the imported context name already exists.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportConflict {
+    Skip,
+    Overwrite,
+    /// Append a number suffix to the name.
+    Rename,
+    /// Ask the user what to do.
+    Prompt,
+}
+
+impl Config {
+    const CONFIG_PATH_ENV: &'static str = "KUBESWITCH_CONFIG_PATH";
+
+    pub fn load() -> Result<Config> {
+        let path = Self::get_path().context("get config path")?;
+        let mut cfg = match path.as_ref() {
+            Some(path) => Self::read(path)?,
+            None => Self::default(),
+        };
+        cfg.path = path;
+        cfg.validate().context("validate config")?;
+        Ok(cfg)
+    }
+
+    pub fn match_ns_alias<S: AsRef<str>>(&self, name: S) -> Option<Vec<Cow<'_, str>>> {
+        if let Some(alias_list) = self.ns_alias.as_ref() {
+            for alias in alias_list.iter() {
+                if let Some(alias) = alias.match_alias(name.as_ref()) {
+                    return Some(alias);
+                }
+            }
+        }
+        None
+    }
+
+    /// Get the namespace to use for the context whose kubeconfig does not set one,
+    /// the first matched rule wins.
+    pub fn match_default_namespace<S: AsRef<str>>(&self, name: S) -> Option<&str> {
+        self.default_namespace
+            .as_ref()?
+            .iter()
+            .find(|rule| match_name(&rule.parsed_regex, &rule.names, name.as_ref()))
+            .map(|rule| rule.namespace.as_str())
+    }
+
+    /// Get the pinned tool versions for the context, if multiple pins match the
+    /// context, the first one wins for the same tool.
+    pub fn match_tools<S: AsRef<str>>(&self, name: S) -> BTreeMap<&str, &str> {
+        let mut versions = BTreeMap::new();
+        if let Some(tools) = self.tools.as_ref() {
+            for pin in tools.pin.iter() {
+                if !match_name(&pin.parsed_regex, &pin.names, name.as_ref()) {
+                    continue;
+                }
+                for (tool, version) in pin.versions.iter() {
+                    versions.entry(tool.as_str()).or_insert(version.as_str());
+                }
+            }
+        }
+        versions
+    }
+
+    /// Sort the namespaces of the context according to the `[namespace]` config:
+    /// pinned namespaces come first, then the namespaces matching the order
+    /// patterns, in the order of patterns. The namespaces not matching any pattern
+    /// come last. The rule for the context overrides the global `order` and `pin`.
+    pub fn sort_namespaces<S: AsRef<str>>(&self, name: &str, namespaces: &mut [S]) {
+        let mut order = &self.namespace.order;
+        let mut pin = &self.namespace.pin;
+        if let Some(rule) = self
+            .namespace
+            .contexts
+            .iter()
+            .find(|rule| match_name(&rule.parsed_regex, &rule.names, name))
+        {
+            if let Some(rule_order) = rule.order.as_ref() {
+                order = rule_order;
+            }
+            if let Some(rule_pin) = rule.pin.as_ref() {
+                pin = rule_pin;
+            }
+        }
+        if order.is_empty() && pin.is_empty() {
+            return;
+        }
+
+        namespaces.sort_by_cached_key(|ns| {
+            let ns = ns.as_ref();
+            if let Some(idx) = pin.iter().position(|pin| pin == ns) {
+                return (0, idx);
+            }
+            match order.iter().position(|pattern| glob::is_match(pattern, ns)) {
+                Some(idx) => (1, idx),
+                None => (2, 0),
+            }
+        });
+    }
+
+    pub fn match_gitops<S: AsRef<str>>(&self, name: S) -> Option<&GitOps> {
+        self.gitops
+            .as_ref()?
+            .iter()
+            .find(|gitops| match_name(&gitops.parsed_regex, &gitops.names, name.as_ref()))
+    }
+
+    pub fn match_protect<S: AsRef<str>>(&self, name: S) -> Option<&Protect> {
+        self.protect
+            .as_ref()?
+            .iter()
+            .find(|protect| match_name(&protect.parsed_regex, &protect.names, name.as_ref()))
+    }
+
+    /// Render the `prompt_format` with the context.
+    pub fn render_prompt(&self, name: &str, namespace: &str, emoji: Option<&str>) -> String {
+        let mut prompt = String::new();
+        for part in self.prompt_parts.iter() {
+            match part {
+                PromptPart::Text(text) => prompt.push_str(text),
+                PromptPart::Name => prompt.push_str(name),
+                PromptPart::Namespace => prompt.push_str(namespace),
+                PromptPart::Emoji => {
+                    if let Some(emoji) = emoji {
+                        prompt.push_str(emoji);
+                        prompt.push(' ');
+                    }
+                }
+            }
+        }
+        prompt
+    }
+
+    /// Find the prompt hint of the context, the tags are only loaded when some hint
+    /// matches by tag.
+    pub fn match_prompt_hint<S: AsRef<str>>(&self, name: S) -> Result<Option<&PromptHint>> {
+        let hints = match self.prompt_hint.as_ref() {
+            Some(hints) => hints,
+            None => return Ok(None),
+        };
+        let name = name.as_ref();
+        let mut tags = None;
+        for hint in hints {
+            if let Some(level) = hint.level {
+                if self.match_protect(name).map(|protect| protect.level) != Some(level) {
+                    continue;
+                }
+            }
+            if let Some((key, value)) = hint.parsed_tag.as_ref() {
+                if tags.is_none() {
+                    tags = Some(Tags::load()?);
+                }
+                let tags = tags.as_ref().unwrap();
+                if tags.get(name).and_then(|tags| tags.get(key)) != Some(value) {
+                    continue;
+                }
+            }
+            return Ok(Some(hint));
+        }
+        Ok(None)
+    }
+
+    /// Set `kube.dir` in the config file, the file is created if absent. Return the
+    /// path of the config file. The rest of the file, comments included, is kept
+    /// as it is.
+    pub fn save_kube_dir(dir: &str) -> Result<PathBuf> {
+        let path = Self::get_raw_path()?;
+        let mut doc = match fs::read_to_string(&path) {
+            Ok(data) => data
+                .parse::<toml_edit::DocumentMut>()
+                .context("parse config toml")?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => toml_edit::DocumentMut::new(),
+            Err(err) => return Err(err).context("read config file"),
+        };
+
+        let kube = doc
+            .entry("kube")
+            .or_insert_with(toml_edit::table)
+            .as_table_like_mut();
+        let kube = match kube {
+            Some(kube) => kube,
+            None => bail!("`kube` in config file should be a table"),
+        };
+        match kube.get_mut("dir").and_then(|item| item.as_value_mut()) {
+            // Replace the value only, to keep the comments around it.
+            Some(value) => {
+                let decor = value.decor().clone();
+                *value = dir.into();
+                *value.decor_mut() = decor;
+            }
+            None => {
+                kube.insert("dir", toml_edit::value(dir));
+            }
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("create config dir '{}'", dir.display()))?;
+        }
+        fs::write(&path, doc.to_string())
+            .with_context(|| format!("write config file '{}'", path.display()))?;
+        Ok(path)
+    }
+
+    fn get_raw_path() -> Result<PathBuf> {
+        match env::var_os(Self::CONFIG_PATH_ENV) {
+            Some(path) => Ok(PathBuf::from(path)),
+            None => {
+                let home_dir = get_home_dir()?;
+                Ok(home_dir.join(".config").join("kubeswitch.toml"))
+            }
+        }
+    }
+
+    fn get_path() -> Result<Option<PathBuf>> {
+        let path = Self::get_raw_path()?;
+
+        match fs::metadata(&path) {
+            Ok(meta) => {
+                if meta.is_dir() {
+                    bail!(
+                        "config path '{}' is a directory, require file",
+                        path.display()
+                    );
+                }
+                Ok(Some(path))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).with_context(|| format!("stat config file '{}'", path.display())),
+        }
+    }
+
+    fn read<P: AsRef<Path>>(path: P) -> Result<Config> {
+        let data = fs::read(path).context("read config file")?;
+        let config = String::from_utf8(data).context("decode config file as utf-8")?;
+        toml::from_str(&config).context("parse config toml")
+    }
+
+    fn validate(&mut self) -> Result<()> {
+        if self.cmd.is_empty() {
+            bail!("`cmd` cannot be empty");
+        }
+        if self.editor.is_empty() {
+            bail!("`editor` cannot be empty");
+        }
+        self.editor = expand_env(&self.editor).context("expand env for `editor`")?;
+        if let Some(dir) = self.edit_tmp_dir.as_ref() {
+            self.edit_tmp_dir = Some(expand_env(dir).context("expand env for `edit_tmp_dir`")?);
+        }
+
+        self.kube.validate().context("validate kube")?;
+        self.history.validate().context("validate history")?;
+        self.backup.validate().context("validate backup")?;
+        if let Some(dir) = get_state_dir() {
+            // The state dir wins over the configured paths, never touch the real state.
+            self.history.path = format!("{}", dir.join(".kubeswitch_history").display());
+            self.backup.dir = format!("{}", dir.join("backups").display());
+            // The runtime files go to the default dirs, which are under the state dir.
+            self.edit_tmp_dir = None;
+            if let Some(encryption) = self.encryption.as_mut() {
+                encryption.dir = EncryptionConfig::default_dir();
+            }
+            if let Some(vault) = self.vault.as_mut() {
+                vault.dir = VaultConfig::default_dir();
+            }
+            if self.kube.symlink.is_some() {
+                self.kube.symlink = Some(format!("{}", dir.join("kubeconfig").display()));
+            }
+        }
+
+        if let Some(k9s) = self.k9s.as_mut() {
+            k9s.validate().context("validate k9s")?;
+        }
+
+        if let Some(ns_alias) = self.ns_alias.as_mut() {
+            for (idx, alias) in ns_alias.iter_mut().enumerate() {
+                alias
+                    .validate()
+                    .with_context(|| format!("validate ns_alias index {idx}"))?;
+            }
+        }
+
+        if let Some(rules) = self.default_namespace.as_mut() {
+            for (idx, rule) in rules.iter_mut().enumerate() {
+                rule.validate()
+                    .with_context(|| format!("validate default_namespace index {idx}"))?;
+            }
+        }
+
+        for (name, provider) in DiscoverProvider::builtin() {
+            self.discover.entry(name.to_string()).or_insert(provider);
+        }
+        for (name, provider) in self.discover.iter_mut() {
+            provider
+                .validate()
+                .with_context(|| format!("validate discover.{name}"))?;
+        }
+
+        self.daemon
+            .validate(&self.discover)
+            .context("validate daemon")?;
+
+        self.namespace.validate().context("validate namespace")?;
+        self.selector.validate().context("validate selector")?;
+
+        if let Some(vault) = self.vault.as_mut() {
+            vault.validate().context("validate vault")?;
+        }
+
+        if let Some(encryption) = self.encryption.as_mut() {
+            encryption.validate().context("validate encryption")?;
+        }
+
+        if let Some(tools) = self.tools.as_mut() {
+            tools.validate().context("validate tools")?;
+        }
+
+        if let Some(sync) = self.sync.as_mut() {
+            sync.validate().context("validate sync")?;
+            if sync.secrets == SyncSecrets::Encrypted && self.encryption.is_none() {
+                bail!("`sync.secrets` is `encrypted`, but `encryption` is not configured");
+            }
+        }
+
+        if let Some(gitops) = self.gitops.as_mut() {
+            for (idx, gitops) in gitops.iter_mut().enumerate() {
+                gitops
+                    .validate()
+                    .with_context(|| format!("validate gitops index {idx}"))?;
+            }
+        }
+
+        if let Some(protect) = self.protect.as_mut() {
+            for (idx, protect) in protect.iter_mut().enumerate() {
+                protect.parsed_regex = parse_match_regex(&protect.regex, &protect.names)
+                    .with_context(|| format!("validate protect index {idx}"))?;
+            }
+        }
+
+        if let Some(naming) = self.naming.as_mut() {
+            naming.validate().context("validate naming")?;
+        }
+
+        self.prompt_parts =
+            parse_prompt_format(&self.prompt_format).context("parse `prompt_format`")?;
+        if let Some(hints) = self.prompt_hint.as_mut() {
+            for (idx, hint) in hints.iter_mut().enumerate() {
+                hint.validate()
+                    .with_context(|| format!("validate prompt_hint index {idx}"))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn default() -> Config {
+        Config {
+            cmd: Self::default_cmd(),
+            editor: Self::default_editor(),
+            editor_args: Vec::new(),
+            confirm_edit: default_disable(),
+            verify_edit: default_disable(),
+            edit_tmp_dir: None,
+            kube: KubeConfig::default(),
+            offline: default_disable(),
+            k9s: None,
+            ns_alias: None,
+            default_namespace: None,
+            namespace: NamespaceConfig::default(),
+            vault: None,
+            encryption: None,
+            tools: None,
+            gitops: None,
+            protect: None,
+            fingerprint: None,
+            tls: TlsConfig::default(),
+            auto_switch: default_disable(),
+            previous_by_default: default_disable(),
+            sort: Self::default_sort(),
+            naming: None,
+            prompt_hint: None,
+            prompt_format: Self::default_prompt_format(),
+            prompt_parts: Vec::new(),
+            hooks: None,
+            history: HistoryConfig::default(),
+            backup: BackupConfig::default(),
+            import: ImportConfig::default(),
+            discover: BTreeMap::new(),
+            selector: SelectorConfig::default(),
+            daemon: DaemonConfig::default(),
+            sync: None,
+            path: None,
+            tag_filter: Vec::new(),
+        }
+    }
+
+    fn default_cmd() -> String {
+        String::from("ks")
+    }
+
+    fn default_editor() -> String {
+        String::from("$EDITOR")
+    }
+
+    fn default_prompt_format() -> String {
+        String::from("{emoji}{name} -> {namespace}")
+    }
+
+    fn default_sort() -> ContextSort {
+        ContextSort::Usage
+    }
+}
+
+impl KubeConfig {
+    fn validate(&mut self) -> Result<()> {
+        if self.exec.is_empty() {
+            bail!("`kube.exec` cannot be empty");
+        }
+        self.exec = expand_env(&self.exec).context("expand env for `kube.exec`")?;
+
+        if self.cmd.is_empty() {
+            bail!("`kube.cmd` cannot be empty");
+        }
+
+        if self.dir.is_empty() {
+            bail!("`kube.dir` cannot be empty");
+        }
+        self.dir = expand_env(&self.dir).context("expand env for `kube.dir`")?;
+        if fs::metadata(&self.dir)
+            .map(|meta| meta.is_file())
+            .unwrap_or(false)
+        {
+            return Err(KubeDirIsFile {
+                dir: self.dir.clone(),
+            }
+            .into());
+        }
+
+        if let Some(user_dir) = self.user_dir.as_ref() {
+            if user_dir.is_empty() {
+                bail!("`kube.user_dir` cannot be empty");
+            }
+            let user_dir = expand_env(user_dir).context("expand env for `kube.user_dir`")?;
+            self.user_dir = Some(user_dir);
+        }
+
+        if let Some(symlink) = self.symlink.as_ref() {
+            if symlink.is_empty() {
+                bail!("`kube.symlink` cannot be empty");
+            }
+            let symlink = expand_env(symlink).context("expand env for `kube.symlink`")?;
+            self.symlink = Some(symlink);
+        }
+
+        Ok(())
+    }
+
+    fn default() -> KubeConfig {
+        KubeConfig {
+            exec: Self::default_exec(),
+            cmd: Self::default_cmd(),
+            dir: Self::default_dir(),
+            user_dir: None,
+            export_kubeconfig: default_disable(),
+            update_context: default_disable(),
+            follow_renamed: default_disable(),
+            guard_forwards: default_disable(),
+            pass_context: default_disable(),
+            symlink: None,
+            namespace_list_args: Vec::new(),
+            create_namespace: Self::default_create_namespace(),
+            namespace_source: Self::default_namespace_source(),
+            lazy_parse_size: Self::default_lazy_parse_size(),
+        }
+    }
+
+    fn default_create_namespace() -> CreateNamespace {
+        CreateNamespace::Never
+    }
+
+    fn default_namespace_source() -> NamespaceSource {
+        NamespaceSource::Env
+    }
+
+    fn default_lazy_parse_size() -> u64 {
+        64 * 1024
+    }
+
+    fn default_exec() -> String {
+        String::from("kubectl")
+    }
+
+    fn default_cmd() -> String {
+        String::from("k")
+    }
+
+    fn default_dir() -> String {
+        String::from(KubeDirIsFile::MIGRATE_DIR)
+    }
+}
+
+impl K9sConfig {
+    fn validate(&mut self) -> Result<()> {
+        if self.exec.is_empty() {
+            bail!("`k9s.exec` cannot be empty");
+        }
+        self.exec = expand_env(&self.exec).context("expand env for `k9s.exec`")?;
+
+        if self.cmd.is_empty() {
+            bail!("`k9s.cmd` cannot be empty");
+        }
+
+        Ok(())
+    }
+
+    fn default_exec() -> String {
+        String::from("k9s")
+    }
+
+    fn default_cmd() -> String {
+        String::from("kk")
+    }
+}
+
+impl NsAlias {
+    fn match_alias<S: AsRef<str>>(&self, name: S) -> Option<Vec<Cow<'_, str>>> {
+        if match_name(&self.parsed_regex, &self.names, name.as_ref()) {
+            Some(
+                self.alias
+                    .iter()
+                    .map(|s| Cow::Borrowed(s.as_str()))
+                    .collect(),
+            )
+        } else {
+            None
+        }
+    }
+
+    fn validate(&mut self) -> Result<()> {
+        if self.alias.is_empty() {
+            bail!("`ns_alias.alias` cannot be empty");
+        }
+
+        self.parsed_regex = parse_match_regex(&self.regex, &self.names)?;
+        Ok(())
+    }
+}
+
+impl DefaultNamespace {
+    fn validate(&mut self) -> Result<()> {
+        if self.namespace.is_empty() {
+            bail!("`default_namespace.namespace` cannot be empty");
+        }
+
+        self.parsed_regex = parse_match_regex(&self.regex, &self.names)?;
+        Ok(())
+    }
+}
+
+impl NamespaceConfig {
+    fn default() -> NamespaceConfig {
+        NamespaceConfig {
+            order: Vec::new(),
+            pin: Vec::new(),
+            contexts: Vec::new(),
+            separator: Self::default_separator(),
+            check: Self::default_check(),
+            cache_ttl: Self::default_cache_ttl(),
+            refresh_wait_ms: Self::default_refresh_wait_ms(),
+            auto_pick_context: default_disable(),
+        }
+    }
+
+    fn default_separator() -> String {
+        String::from("::")
+    }
+
+    fn default_check() -> NamespaceCheck {
+        NamespaceCheck::Off
+    }
+
+    fn default_cache_ttl() -> u64 {
+        0
+    }
+
+    fn default_refresh_wait_ms() -> u64 {
+        3000
+    }
+
+    fn validate(&mut self) -> Result<()> {
+        if self.separator.is_empty() {
+            bail!("`separator` cannot be empty");
+        }
+        if self
+            .separator
+            .chars()
+            .any(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '/'))
+        {
+            bail!("`separator` cannot contain the characters allowed in context name");
+        }
+
+        for (idx, rule) in self.contexts.iter_mut().enumerate() {
+            rule.parsed_regex = parse_match_regex(&rule.regex, &rule.names)
+                .with_context(|| format!("validate contexts index {idx}"))?;
+        }
+        Ok(())
+    }
+
+    /// Split the name into context and namespace by the separator.
+    pub fn split<'a>(&self, name: &'a str) -> Option<(&'a str, &'a str)> {
+        name.split_once(self.separator.as_str())
+    }
+}
+
+impl HistoryConfig {
+    fn default() -> HistoryConfig {
+        HistoryConfig {
+            path: Self::default_path(),
+            max_entries: Self::default_max_entries(),
+            dedup: default_enable(),
+        }
+    }
+
+    fn default_path() -> String {
+        String::from("~/.kubeswitch_history")
+    }
+
+    fn default_max_entries() -> usize {
+        1000
+    }
+
+    fn validate(&mut self) -> Result<()> {
+        if self.path.is_empty() {
+            bail!("`history.path` cannot be empty");
+        }
+        self.path = expand_env(&self.path).context("expand env for `history.path`")?;
+        Ok(())
+    }
+}
+
+impl BackupConfig {
+    fn default() -> BackupConfig {
+        BackupConfig {
+            dir: Self::default_dir(),
+            keep: Self::default_keep(),
+        }
+    }
+
+    fn default_dir() -> String {
+        String::from("~/.local/share/kubeswitch/backups")
+    }
+
+    fn default_keep() -> usize {
+        10
+    }
+
+    fn validate(&mut self) -> Result<()> {
+        if self.dir.is_empty() {
+            bail!("`backup.dir` cannot be empty");
+        }
+        self.dir = expand_env(&self.dir).context("expand env for `backup.dir`")?;
+        Ok(())
+    }
+}
+
+impl NamingConfig {
+    /// Check if the context name follows the convention.
+    pub fn is_match(&self, name: &str) -> bool {
+        self.parsed_pattern.as_ref().unwrap().is_match(name)
+    }
+
+    /// Suggest the canonical name by applying the fixes, return `None` if the fixed
+    /// name still violates the convention.
+    pub fn suggest(&self, name: &str) -> Option<String> {
+        let mut name = name.to_string();
+        for fix in self.fixes.iter() {
+            name = fix.apply(&name);
+        }
+        if let Some(prefix) = self.prefix.as_ref() {
+            if !self.is_match(&name) && !name.starts_with(prefix.as_str()) {
+                name = format!("{prefix}{name}");
+            }
+        }
+        if name.is_empty() || !self.is_match(&name) {
+            return None;
+        }
+        Some(name)
+    }
+
+    fn validate(&mut self) -> Result<()> {
+        if self.pattern.is_empty() {
+            bail!("`naming.pattern` cannot be empty");
+        }
+        let pattern = Regex::new(&self.pattern)
+            .with_context(|| format!("parse `naming.pattern` '{}'", self.pattern))?;
+        self.parsed_pattern = Some(pattern);
+        Ok(())
+    }
+}
+
+impl NamingFix {
+    fn apply(&self, name: &str) -> String {
+        match self {
+            Self::Lowercase => name.to_lowercase(),
+            Self::Hyphens => {
+                let mut fixed = String::with_capacity(name.len());
+                for c in name.chars() {
+                    let c = match c {
+                        '_' | '.' => '-',
+                        c => c,
+                    };
+                    if c == '-' && fixed.ends_with('-') {
+                        continue;
+                    }
+                    fixed.push(c);
+                }
+                // Each segment of the dir should not start or end with '-' either.
+                fixed
+                    .split('/')
+                    .map(|segment| segment.trim_matches('-'))
+                    .collect::<Vec<_>>()
+                    .join("/")
+            }
+        }
+    }
+}
+
+impl SelectorConfig {
+    pub fn default() -> SelectorConfig {
+        SelectorConfig {
+            exec: Self::default_exec(),
+            args: Vec::new(),
+            preview: default_enable(),
+        }
+    }
+
+    fn default_exec() -> String {
+        String::from("fzf")
+    }
+
+    fn validate(&mut self) -> Result<()> {
+        if self.exec.is_empty() {
+            bail!("`exec` cannot be empty");
+        }
+        self.exec = expand_env(&self.exec).context("expand env for `exec`")?;
+        Ok(())
+    }
+}
+
+impl TlsConfig {
+    fn default() -> TlsConfig {
+        TlsConfig {
+            insecure: Self::default_severity(),
+            missing_ca: Self::default_severity(),
+        }
+    }
+
+    fn default_severity() -> TlsSeverity {
+        TlsSeverity::Warn
+    }
+}
+
+impl DaemonConfig {
+    fn default() -> DaemonConfig {
+        DaemonConfig {
+            discover: Vec::new(),
+            discover_interval: Self::default_discover_interval(),
+            namespaces_interval: Self::default_namespaces_interval(),
+            certs_interval: Self::default_certs_interval(),
+            delay_ms: Self::default_delay_ms(),
+            jitter: Self::default_jitter(),
+            openssl: Self::default_openssl(),
+        }
+    }
+
+    fn default_discover_interval() -> u64 {
+        6 * 3600
+    }
+
+    fn default_namespaces_interval() -> u64 {
+        1800
+    }
+
+    fn default_certs_interval() -> u64 {
+        6 * 3600
+    }
+
+    fn default_delay_ms() -> u64 {
+        500
+    }
+
+    fn default_jitter() -> f64 {
+        0.1
+    }
+
+    fn default_openssl() -> String {
+        String::from("openssl")
+    }
+
+    fn validate(&mut self, discover: &BTreeMap<String, DiscoverProvider>) -> Result<()> {
+        for provider in self.discover.iter() {
+            if !discover.contains_key(provider) {
+                bail!("unknown provider '{provider}' in `daemon.discover`");
+            }
+        }
+        if !(0.0..1.0).contains(&self.jitter) {
+            bail!("`daemon.jitter` should be in [0, 1)");
+        }
+        if self.openssl.is_empty() {
+            bail!("`daemon.openssl` cannot be empty");
+        }
+        self.openssl = expand_env(&self.openssl).context("expand env for `daemon.openssl`")?;
+        Ok(())
+    }
+}
+
+impl ImportConfig {
+    fn default() -> ImportConfig {
+        ImportConfig {
+            naming: Self::default_naming(),
+            conflict: Self::default_conflict(),
+        }
+    }
+
+    fn default_naming() -> ImportNaming {
+        ImportNaming::File
+    }
+
+    fn default_conflict() -> ImportConflict {
+        ImportConflict::Skip
+    }
+}
+
+impl DiscoverProvider {
+    /// The field name of the generated kubeconfig file.
+    pub const KUBECONFIG_FIELD: &'static str = "kubeconfig";
+
+    /// The field of the cluster name, the other fields are tagged to the imported
+    /// context along with the provider name, see [`Self::PROVIDER_TAG`].
+    pub const CLUSTER_FIELD: &'static str = "cluster";
+
+    /// The tag of the provider name, set on the imported contexts.
+    pub const PROVIDER_TAG: &'static str = "provider";
+
+    fn builtin() -> Vec<(&'static str, DiscoverProvider)> {
+        let provider =
+            |list: &str, fields: &[&str], kubeconfig: &str, name: &str| DiscoverProvider {
+                list: list.to_string(),
+                fields: fields.iter().map(|field| field.to_string()).collect(),
+                kubeconfig: kubeconfig.to_string(),
+                name: name.to_string(),
+            };
+        vec![
+            (
+                "eks",
+                provider(
+                    r#"account=$(aws sts get-caller-identity --query Account --output text); region=$(aws configure get region); aws eks list-clusters --region "$region" --query 'clusters[]' --output text | tr '\t' '\n' | sed "s|^|$account $region |""#,
+                    &["account", "region", "cluster"],
+                    "aws eks update-kubeconfig --name {cluster} --region {region} --kubeconfig {kubeconfig}",
+                    "aws/{region}/{cluster}",
+                ),
+            ),
+            (
+                "gke",
+                provider(
+                    r#"account=$(gcloud config get-value project); gcloud container clusters list --format='value(name,location)' | sed "s|^|$account |""#,
+                    &["account", "cluster", "region"],
+                    "gcloud container clusters get-credentials {cluster} --location {region}",
+                    "gcp/{region}/{cluster}",
+                ),
+            ),
+            (
+                "aks",
+                provider(
+                    r#"account=$(az account show --query id --output tsv); az aks list --query '[].[name,resourceGroup,location]' --output tsv | sed "s|^|$account |""#,
+                    &["account", "cluster", "group", "region"],
+                    "az aks get-credentials --name {cluster} --resource-group {group} --file {kubeconfig}",
+                    "azure/{region}/{cluster}",
+                ),
+            ),
+        ]
+    }
+
+    fn default_fields() -> Vec<String> {
+        vec![String::from(Self::CLUSTER_FIELD)]
+    }
+
+    fn validate(&mut self) -> Result<()> {
+        if self.list.is_empty() {
+            bail!("`list` cannot be empty");
+        }
+        if self.kubeconfig.is_empty() {
+            bail!("`kubeconfig` cannot be empty");
+        }
+        if self.fields.is_empty() {
+            bail!("`fields` cannot be empty");
+        }
+        if self
+            .fields
+            .iter()
+            .any(|field| field.is_empty() || field == Self::KUBECONFIG_FIELD)
+        {
+            bail!(
+                "`fields` cannot contain empty or the reserved '{}'",
+                Self::KUBECONFIG_FIELD
+            );
+        }
+
+        if self.name.is_empty() {
+            bail!("`name` cannot be empty");
+        }
+        let mut rest = self.name.as_str();
+        while let Some((_, after)) = rest.split_once('{') {
+            let (placeholder, after) = match after.split_once('}') {
+                Some(split) => split,
+                None => bail!("unclosed placeholder in `name` '{}'", self.name),
+            };
+            if !self.fields.iter().any(|field| field == placeholder) {
+                bail!("unknown placeholder '{{{placeholder}}}' in `name`, it should be one of the fields");
+            }
+            rest = after;
+        }
+        Ok(())
+    }
+}
+
+impl SyncConfig {
+    fn validate(&mut self) -> Result<()> {
+        if self.exec.is_empty() {
+            bail!("`sync.exec` cannot be empty");
+        }
+        self.exec = expand_env(&self.exec).context("expand env for `sync.exec`")?;
+
+        if self.remote.is_empty() {
+            bail!("`sync.remote` cannot be empty");
+        }
+        self.remote = expand_env(&self.remote).context("expand env for `sync.remote`")?;
+
+        if self.branch.is_empty() {
+            bail!("`sync.branch` cannot be empty");
+        }
+        Ok(())
+    }
+
+    fn default_exec() -> String {
+        String::from("git")
+    }
+
+    fn default_branch() -> String {
+        String::from("main")
+    }
+
+    fn default_secrets() -> SyncSecrets {
+        SyncSecrets::Plain
+    }
+}
+
+impl EncryptionConfig {
+    fn validate(&mut self) -> Result<()> {
+        if self.exec.is_empty() {
+            bail!("`encryption.exec` cannot be empty");
+        }
+        self.exec = expand_env(&self.exec).context("expand env for `encryption.exec`")?;
+
+        if self.identity.is_empty() {
+            bail!("`encryption.identity` cannot be empty");
+        }
+        self.identity =
+            expand_env(&self.identity).context("expand env for `encryption.identity`")?;
+
+        if self.recipients.is_empty() {
+            bail!("`encryption.recipients` cannot be empty");
+        }
+
+        if self.dir.is_empty() {
+            bail!("`encryption.dir` cannot be empty");
+        }
+        self.dir = expand_env(&self.dir).context("expand env for `encryption.dir`")?;
+        Ok(())
+    }
+
+    fn default_exec() -> String {
+        String::from("age")
+    }
+
+    fn default_dir() -> String {
+        format!("{}", get_runtime_dir().join("decrypted").display())
+    }
+}
+
+impl VaultConfig {
+    fn validate(&mut self) -> Result<()> {
+        if self.exec.is_empty() {
+            bail!("`vault.exec` cannot be empty");
+        }
+        self.exec = expand_env(&self.exec).context("expand env for `vault.exec`")?;
+
+        if self.dir.is_empty() {
+            bail!("`vault.dir` cannot be empty");
+        }
+        self.dir = expand_env(&self.dir).context("expand env for `vault.dir`")?;
+
+        if self.prefix.is_empty() {
+            bail!("`vault.prefix` cannot be empty");
+        }
+        if self.path.is_empty() {
+            bail!("`vault.path` cannot be empty");
+        }
+
+        let mut names = HashSet::new();
+        for (idx, ctx) in self.contexts.iter_mut().enumerate() {
+            ctx.validate()
+                .with_context(|| format!("validate vault.contexts index {idx}"))?;
+            if !names.insert(ctx.name.clone()) {
+                bail!("duplicate vault context name '{}'", ctx.name);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn default_exec() -> String {
+        String::from("vault")
+    }
+
+    fn default_dir() -> String {
+        format!("{}", get_runtime_dir().join("vault").display())
+    }
+
+    fn default_prefix() -> String {
+        String::from("vault")
+    }
+
+    fn default_path() -> String {
+        String::from("kubernetes/creds/{name}")
+    }
+}
+
+impl VaultContextConfig {
+    fn validate(&mut self) -> Result<()> {
+        if self.name.is_empty() {
+            bail!("`name` cannot be empty");
+        }
+        // The name is used as the file name in `vault.dir`, it must not escape it.
+        let re = Regex::new(NAME_REGEX).unwrap();
+        if !re.is_match(&self.name)
+            || self
+                .name
+                .split('/')
+                .any(|segment| segment.is_empty() || segment == "..")
+        {
+            bail!("invalid `name` '{}'", self.name);
+        }
+        if self.server.is_empty() {
+            bail!("`server` cannot be empty");
+        }
+        if self.namespace.is_empty() {
+            bail!("`namespace` cannot be empty");
+        }
+        if let Some(path) = self.path.as_ref() {
+            if path.is_empty() {
+                bail!("`path` cannot be empty");
+            }
+        }
+        if let Some(ca) = self.ca.as_ref() {
+            self.ca = Some(expand_env(ca).context("expand env for `ca`")?);
+        }
+        Ok(())
+    }
+}
+
+impl ToolsConfig {
+    fn validate(&mut self) -> Result<()> {
+        if self.path.is_empty() {
+            bail!("`tools.path` cannot be empty");
+        }
+        self.path = expand_env(&self.path).context("expand env for `tools.path`")?;
+
+        for (idx, pin) in self.pin.iter_mut().enumerate() {
+            pin.parsed_regex = parse_match_regex(&pin.regex, &pin.names)
+                .with_context(|| format!("validate tools.pin index {idx}"))?;
+            for (tool, version) in pin.versions.iter() {
+                if tool.is_empty() || tool.contains('/') {
+                    bail!("invalid tool name '{tool}' in tools.pin index {idx}");
+                }
+                if version.is_empty() {
+                    bail!("version for tool '{tool}' cannot be empty in tools.pin index {idx}");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn default_path() -> String {
+        String::from("~/.local/share/kubeswitch/tools/{name}/{version}/{name}")
+    }
+}
+
+impl GitOps {
+    pub fn render_message(&self, name: &str) -> String {
+        self.message
+            .replace("{name}", name)
+            .replace("{repo}", &self.repo)
+    }
+
+    fn validate(&mut self) -> Result<()> {
+        if self.repo.is_empty() {
+            bail!("`gitops.repo` cannot be empty");
+        }
+        for verb in self.block.iter() {
+            if verb.is_empty() || !verb.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                bail!("invalid verb '{verb}' in `gitops.block`");
+            }
+        }
+        self.parsed_regex = parse_match_regex(&self.regex, &self.names)?;
+        Ok(())
+    }
+
+    fn default_message() -> String {
+        String::from("GitOps cluster, changes via repo {repo}")
+    }
+}
+
+impl Protect {
+    pub fn render_message(&self, name: &str) -> String {
+        self.message.replace("{name}", name)
+    }
+
+    fn default_level() -> RiskLevel {
+        RiskLevel::Confirm
+    }
+
+    fn default_message() -> String {
+        String::from("Context '{name}' is protected")
+    }
+}
+
+impl PromptHint {
+    fn validate(&mut self) -> Result<()> {
+        if self.tag.is_none() && self.level.is_none() {
+            bail!("at least one of `tag` and `level` is required");
+        }
+        if self.color.is_none() && self.emoji.is_none() {
+            bail!("at least one of `color` and `emoji` is required");
+        }
+        if let Some(tag) = self.tag.as_ref() {
+            self.parsed_tag = Some(parse_tag(tag)?);
+        }
+        if let Some(emoji) = self.emoji.as_ref() {
+            if emoji.trim().is_empty() || emoji.contains('\n') {
+                bail!("`emoji` cannot be empty or contain newline");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PromptColor {
+    pub fn parse(name: &str) -> Option<PromptColor> {
+        match name {
+            "black" => Some(Self::Black),
+            "red" => Some(Self::Red),
+            "green" => Some(Self::Green),
+            "yellow" => Some(Self::Yellow),
+            "blue" => Some(Self::Blue),
+            "magenta" => Some(Self::Magenta),
+            "cyan" => Some(Self::Cyan),
+            "white" => Some(Self::White),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Black => "black",
+            Self::Red => "red",
+            Self::Green => "green",
+            Self::Yellow => "yellow",
+            Self::Blue => "blue",
+            Self::Magenta => "magenta",
+            Self::Cyan => "cyan",
+            Self::White => "white",
+        }
+    }
+
+    /// The ANSI foreground color code.
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Black => 30,
+            Self::Red => 31,
+            Self::Green => 32,
+            Self::Yellow => 33,
+            Self::Blue => 34,
+            Self::Magenta => 35,
+            Self::Cyan => 36,
+            Self::White => 37,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum PromptPart {
+    Text(String),
+    Name,
+    Namespace,
+    Emoji,
+}
+
+/// Parse the prompt format once when loading config, so that rendering is cheap.
+fn parse_prompt_format(format: &str) -> Result<Vec<PromptPart>> {
+    let mut parts = Vec::new();
+    let mut rest = format;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(PromptPart::Text(rest[..start].to_string()));
+        }
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => bail!("unclosed placeholder in '{format}'"),
+        };
+        let part = match &rest[start + 1..end] {
+            "name" => PromptPart::Name,
+            "namespace" => PromptPart::Namespace,
+            "emoji" => PromptPart::Emoji,
+            placeholder => bail!(
+                "unknown placeholder '{{{placeholder}}}', should be one of '{{name}}', '{{namespace}}' and '{{emoji}}'"
+            ),
+        };
+        parts.push(part);
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(PromptPart::Text(rest.to_string()));
+    }
+    Ok(parts)
+}
+
+/// Check if the context name matches the regex or is one of the names.
+fn match_name(regex: &Option<Regex>, names: &Option<HashSet<String>>, name: &str) -> bool {
+    if let Some(regex) = regex.as_ref() {
+        if regex.is_match(name) {
+            return true;
+        }
+    }
+    if let Some(names) = names.as_ref() {
+        return names.contains(name);
+    }
+    false
+}
+
+/// Parse the regex for context matching, at least one of regex and names is
+/// required.
+fn parse_match_regex(
+    regex: &Option<String>,
+    names: &Option<HashSet<String>>,
+) -> Result<Option<Regex>> {
+    let has_names = names.as_ref().map(|n| !n.is_empty()).unwrap_or(false);
+    match regex.as_ref() {
+        Some(regex) => {
+            let regex = Regex::new(regex).with_context(|| format!("parse regex '{regex}'"))?;
+            Ok(Some(regex))
+        }
+        None if has_names => Ok(None),
+        None => bail!("must have at least regex or names"),
+    }
+}
+
+fn default_namespace() -> String {
+    String::from("default")
+}
+
+fn default_disable() -> bool {
+    false
+}
+
+fn default_enable() -> bool {
+    true
+}
+
+fn expand_env<S: AsRef<str>>(s: S) -> Result<String> {
+    let s = shellexpand::full(s.as_ref())
+        .with_context(|| format!("expand env for '{}'", s.as_ref()))?;
+    Ok(s.to_string())
+}
+
+/// The env to keep all the state (history, tags, caches, sessions, backups and
+/// the runtime files) in an isolated dir instead of the user's, so that CI jobs
+/// and tests neither touch nor depend on it. The `--state-dir` flag sets it too.
+pub const STATE_DIR_ENV: &str = "KUBESWITCH_STATE_DIR";
+
+pub fn get_state_dir() -> Option<PathBuf> {
+    env::var_os(STATE_DIR_ENV)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+/// The path of the state file, such as tags and tombstones. It is in the state dir
+/// if set, otherwise in home. The state dir is created if absent, so that the file
+/// can be written.
+pub fn get_state_path(name: &str) -> Result<PathBuf> {
+    match get_state_dir() {
+        Some(dir) => {
+            fs::create_dir_all(&dir)
+                .with_context(|| format!("create state dir '{}'", dir.display()))?;
+            Ok(dir.join(name))
+        }
+        None => Ok(get_home_dir()?.join(name)),
+    }
+}
+
+/// The private dir to store runtime files, such as short-lived credentials and
+/// generated scripts.
+pub fn get_runtime_dir() -> PathBuf {
+    if let Some(dir) = get_state_dir() {
+        return dir.join("runtime");
+    }
+    if let Some(dir) = env::var_os("XDG_RUNTIME_DIR") {
+        return PathBuf::from(dir).join("kubeswitch");
+    }
+    // The users without `$USER` must not share the dir, use the uid instead.
+    let user = env::var("USER").unwrap_or_else(|_| get_euid().to_string());
+    PathBuf::from("/tmp").join(format!("kubeswitch-{user}"))
+}
+
+/// The effective user id of the process.
+pub fn get_euid() -> u32 {
+    // SAFETY: geteuid is always successful and has no side effect.
+    unsafe { libc::geteuid() }
+}
+
+/// The dir to store cache files, such as the namespaces listed from clusters.
+pub fn get_cache_dir() -> PathBuf {
+    if let Some(dir) = get_state_dir() {
+        return dir.join("cache");
+    }
+    if let Some(dir) = env::var_os("XDG_CACHE_HOME") {
+        return PathBuf::from(dir).join("kubeswitch");
+    }
+    match env::var_os("HOME") {
+        Some(home) => PathBuf::from(home).join(".cache").join("kubeswitch"),
+        None => get_runtime_dir().join("cache"),
+    }
+}
+
+/// The user dir for bash-completion to lazily load completion files.
+pub fn get_bash_completion_dir() -> Result<PathBuf> {
+    if let Some(dir) = env::var_os("BASH_COMPLETION_USER_DIR") {
+        return Ok(PathBuf::from(dir).join("completions"));
+    }
+    let dir = match env::var_os("XDG_DATA_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => get_home_dir()?.join(".local").join("share"),
+    };
+    Ok(dir.join("bash-completion").join("completions"))
+}
+
+/// The user dir for fish to lazily load completion files.
+pub fn get_fish_completion_dir() -> Result<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => get_home_dir()?.join(".config"),
+    };
+    Ok(dir.join("fish").join("completions"))
+}
+
+pub fn get_home_dir() -> Result<PathBuf> {
+    match env::var_os("HOME") {
+        Some(home) => Ok(PathBuf::from(home)),
+        None => bail!(
+            "$HOME env not found in your system, please make sure that you are in an UNIX system"
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_naming(fixes: &[&str], prefix: Option<&str>) -> NamingConfig {
+        let pattern = if prefix.is_some() {
+            r"^dev-[a-z0-9]+(-[a-z0-9]+)*$"
+        } else {
+            r"^[a-z0-9]+(-[a-z0-9]+)*(/[a-z0-9]+(-[a-z0-9]+)*)*$"
+        };
+        let mut naming = NamingConfig {
+            pattern: String::from(pattern),
+            fixes: fixes
+                .iter()
+                .map(|fix| serde_yaml::from_str(fix).unwrap())
+                .collect(),
+            prefix: prefix.map(String::from),
+            parsed_pattern: None,
+        };
+        naming.validate().unwrap();
+        naming
+    }
+
+    #[test]
+    fn test_naming_suggest() {
+        let naming = new_naming(&["lowercase", "hyphens"], None);
+        let cases = [
+            ("team-a-prod", Some("team-a-prod")),
+            ("Team__A.prod", Some("team-a-prod")),
+            ("team_-.a", Some("team-a")),
+            ("_team_/.prod.", Some("team/prod")),
+            ("Team_A/-dev-/b__", Some("team-a/dev/b")),
+            ("team a", None),
+            ("team/_/dev", None),
+            ("__", None),
+        ];
+        for (name, expect) in cases {
+            assert_eq!(naming.suggest(name).as_deref(), expect, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_naming_suggest_order() {
+        // Without lowercase, the fixed name still violates the pattern.
+        let naming = new_naming(&["hyphens"], None);
+        assert_eq!(naming.suggest("team_a").as_deref(), Some("team-a"));
+        assert_eq!(naming.suggest("Team_A"), None);
+
+        let naming = new_naming(&[], None);
+        assert_eq!(naming.suggest("team-a").as_deref(), Some("team-a"));
+        assert_eq!(naming.suggest("team_a"), None);
+    }
+
+    #[test]
+    fn test_naming_suggest_prefix() {
+        let naming = new_naming(&["lowercase", "hyphens"], Some("dev-"));
+        let cases = [
+            ("dev-team-a", Some("dev-team-a")),
+            ("Team__A", Some("dev-team-a")),
+            ("DEV_team", Some("dev-team")),
+            ("team a", None),
+        ];
+        for (name, expect) in cases {
+            assert_eq!(naming.suggest(name).as_deref(), expect, "{name}");
+        }
     }
 }
@@ -1,17 +1,35 @@
 use std::borrow::Cow;
+use std::collections::HashSet;
 use std::ffi::OsStr;
 use std::fmt::Display;
-use std::io::{self, Read, Write};
+use std::io::{self, IsTerminal};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 use std::{env, fs};
 
 use anyhow::{bail, Context, Result};
-use rev_lines::RevLines;
+use regex::Regex;
 use serde::Deserialize;
 
-use crate::config::Config;
+use crate::backup;
+use crate::cache::{format_expire, CertCache, NamespaceCache, Refresh};
+use crate::config::{
+    get_euid, get_runtime_dir, Config, ContextSort, CreateNamespace, NamespaceSource, PromptHint,
+    RiskLevel, TlsSeverity,
+};
+use crate::diff;
+use crate::encryption;
+use crate::history::{self, History};
+use crate::ignore::IgnoreRules;
+use crate::process;
+use crate::selector::{self, Selection};
+use crate::session::Session;
+use crate::tags::Tags;
+use crate::tombstone::Tombstone;
+use crate::vault;
 
 pub struct KubeContext<'a> {
     pub name: String,
@@ -30,6 +48,10 @@ struct KubeConfig {
     current_context: Option<String>,
 
     contexts: Option<Vec<KubeConfigContextWithName>>,
+
+    clusters: Option<Vec<KubeConfigClusterWithName>>,
+
+    users: Option<Vec<KubeConfigUserWithName>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -41,6 +63,123 @@ struct KubeConfigContextWithName {
 #[derive(Debug, Deserialize)]
 struct KubeConfigContext {
     namespace: Option<String>,
+
+    cluster: Option<String>,
+
+    user: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KubeConfigClusterWithName {
+    name: String,
+    cluster: Option<KubeConfigCluster>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KubeConfigCluster {
+    server: Option<String>,
+
+    #[serde(rename = "certificate-authority")]
+    certificate_authority: Option<String>,
+
+    #[serde(rename = "certificate-authority-data")]
+    certificate_authority_data: Option<String>,
+
+    #[serde(rename = "insecure-skip-tls-verify")]
+    insecure_skip_tls_verify: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KubeConfigUserWithName {
+    name: String,
+    user: Option<KubeConfigUser>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KubeConfigUser {
+    token: Option<String>,
+
+    #[serde(rename = "client-certificate")]
+    client_certificate: Option<String>,
+
+    #[serde(rename = "client-certificate-data")]
+    client_certificate_data: Option<String>,
+
+    username: Option<String>,
+
+    exec: Option<serde_yaml::Value>,
+
+    #[serde(rename = "auth-provider")]
+    auth_provider: Option<serde_yaml::Value>,
+}
+
+/// The TLS issue of the cluster, see [`TlsConfig`](crate::config::TlsConfig).
+#[derive(Debug, Clone, Copy)]
+pub enum TlsIssue {
+    Insecure,
+    PlainHttp,
+    MissingCa,
+}
+
+impl TlsIssue {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::Insecure => "the cluster skips TLS verification (insecure-skip-tls-verify)",
+            Self::PlainHttp => "the cluster server is plain http",
+            Self::MissingCa => "the cluster has no CA configured, trusting the system roots",
+        }
+    }
+
+    /// The short form for the check report.
+    pub fn short(&self) -> &'static str {
+        match self {
+            Self::Insecure => "insecure-skip-tls-verify",
+            Self::PlainHttp => "plain http",
+            Self::MissingCa => "no CA",
+        }
+    }
+}
+
+impl KubeConfigUser {
+    /// Read the client certificate content (PEM), return `None` if the user does
+    /// not authenticate by certificate.
+    fn read_client_certificate(&self, kubeconfig_path: &Path) -> Result<Option<Vec<u8>>> {
+        use base64::Engine;
+
+        if let Some(data) = self.client_certificate_data.as_ref() {
+            let data = base64::engine::general_purpose::STANDARD
+                .decode(data.trim())
+                .context("decode client-certificate-data")?;
+            return Ok(Some(data));
+        }
+        if let Some(path) = self.client_certificate.as_ref() {
+            // The relative path is relative to the kubeconfig file.
+            let path = match kubeconfig_path.parent() {
+                Some(dir) => dir.join(path),
+                None => PathBuf::from(path),
+            };
+            let data = fs::read(&path)
+                .with_context(|| format!("read client-certificate '{}'", path.display()))?;
+            return Ok(Some(data));
+        }
+        Ok(None)
+    }
+
+    fn auth_kind(&self) -> &'static str {
+        if self.exec.is_some() {
+            "exec plugin"
+        } else if self.auth_provider.is_some() {
+            "auth provider"
+        } else if self.token.is_some() {
+            "token"
+        } else if self.client_certificate.is_some() || self.client_certificate_data.is_some() {
+            "client certificate"
+        } else if self.username.is_some() {
+            "basic"
+        } else {
+            "none"
+        }
+    }
 }
 
 impl KubeConfig {
@@ -51,6 +190,110 @@ impl KubeConfig {
             .with_context(|| format!("parse kubeconfig file '{}'", path.as_ref().display()))
     }
 
+    /// Read only the `contexts` and `current-context` of the kubeconfig file larger
+    /// than `kube.lazy_parse_size`, which is enough for the namespace. The top-level
+    /// sections are cut by lines, fall back to parsing the whole file if they are
+    /// not found or cannot be parsed alone, such as referring to the anchors in the
+    /// other sections.
+    fn read_lazy<P: AsRef<Path>>(cfg: &Config, path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let size = fs::metadata(path)
+            .with_context(|| format!("stat kubeconfig file '{}'", path.display()))?
+            .len();
+        if cfg.kube.lazy_parse_size == 0 || size <= cfg.kube.lazy_parse_size {
+            return Self::read(path);
+        }
+
+        let data =
+            fs::read(path).with_context(|| format!("read kubeconfig file '{}'", path.display()))?;
+        let head = String::from_utf8(data)
+            .ok()
+            .and_then(|data| Self::cut_head(&data));
+        match head.map(|head| serde_yaml::from_str(&head)) {
+            Some(Ok(kubeconfig)) => Ok(kubeconfig),
+            _ => {
+                log::debug!(
+                    "cannot parse the head of kubeconfig '{}' alone, parse the whole file",
+                    path.display()
+                );
+                Self::read(path)
+            }
+        }
+    }
+
+    /// Cut the `contexts` and `current-context` sections out of the kubeconfig, a
+    /// section starts at its top-level key and ends at the next one. The items of
+    /// the top-level sequences can be unindented, so the lines starting with `-` do
+    /// not end a section.
+    fn cut_head(data: &str) -> Option<String> {
+        let mut head = String::new();
+        let mut found_contexts = false;
+        let mut found_current = false;
+        let mut keep = false;
+        for line in data.lines() {
+            if line.starts_with(|c: char| !c.is_whitespace() && c != '-' && c != '#') {
+                keep = false;
+                if line.starts_with("contexts:") {
+                    keep = true;
+                    found_contexts = true;
+                } else if line.starts_with("current-context:") {
+                    keep = true;
+                    found_current = true;
+                }
+            }
+            if keep {
+                head.push_str(line);
+                head.push('\n');
+            }
+        }
+        if !found_contexts || !found_current {
+            return None;
+        }
+        Some(head)
+    }
+
+    /// Check that the kubeconfig is usable: the current context exists, and its
+    /// cluster and user are defined.
+    fn validate(&self) -> Result<()> {
+        let current = match self.current_context.as_ref() {
+            Some(current) if !current.is_empty() => current,
+            _ => bail!("missing `current-context`"),
+        };
+        let ctx = match self
+            .contexts
+            .iter()
+            .flatten()
+            .find(|ctx| &ctx.name == current)
+        {
+            Some(ctx) => ctx,
+            None => bail!("current context '{current}' is not found in `contexts`"),
+        };
+        let entry = match ctx.context.as_ref() {
+            Some(entry) => entry,
+            None => bail!("context '{current}' has no `context` entry"),
+        };
+
+        let cluster = match entry.cluster.as_ref() {
+            Some(cluster) if !cluster.is_empty() => cluster,
+            _ => bail!("context '{current}' has no cluster"),
+        };
+        let cluster = match self.clusters.iter().flatten().find(|c| &c.name == cluster) {
+            Some(cluster) => cluster,
+            None => bail!("cluster '{cluster}' of context '{current}' is not found in `clusters`"),
+        };
+        let server = cluster.cluster.as_ref().and_then(|c| c.server.as_ref());
+        if server.map(|server| server.is_empty()).unwrap_or(true) {
+            bail!("cluster '{}' has no server", cluster.name);
+        }
+
+        if let Some(user) = entry.user.as_ref().filter(|user| !user.is_empty()) {
+            if !self.users.iter().flatten().any(|u| &u.name == user) {
+                bail!("user '{user}' of context '{current}' is not found in `users`");
+            }
+        }
+        Ok(())
+    }
+
     fn current_namespace(mut self) -> Option<String> {
         let cur_ctx = self.current_context.take()?;
         let ctxs = self.contexts.take()?;
@@ -58,6 +301,97 @@ impl KubeConfig {
         let ctx = ctx.context?;
         ctx.namespace
     }
+
+    /// The current context name, only if there are multiple contexts to choose.
+    fn current_context_in_multiple(self) -> Option<String> {
+        if self.contexts.as_ref()?.len() <= 1 {
+            return None;
+        }
+        self.current_context
+    }
+
+    fn current_context(&self) -> Option<&KubeConfigContext> {
+        let cur_ctx = self.current_context.as_ref()?;
+        let ctxs = self.contexts.as_ref()?;
+        let ctx = ctxs.iter().find(|ctx| &ctx.name == cur_ctx)?;
+        ctx.context.as_ref()
+    }
+
+    fn current_user(&self) -> Option<(&str, Option<&KubeConfigUser>)> {
+        let user_name = self.current_context()?.user.as_ref()?;
+        let user = self
+            .users
+            .as_ref()
+            .and_then(|users| users.iter().find(|u| &u.name == user_name))
+            .and_then(|user| user.user.as_ref());
+        Some((user_name, user))
+    }
+
+    fn current_cluster(&self) -> Option<&KubeConfigCluster> {
+        let cur_ctx = self.current_context.as_ref()?;
+        let ctxs = self.contexts.as_ref()?;
+        let ctx = ctxs.iter().find(|ctx| &ctx.name == cur_ctx)?;
+        let cluster_name = ctx.context.as_ref()?.cluster.as_ref()?;
+        let clusters = self.clusters.as_ref()?;
+        let cluster = clusters.iter().find(|c| &c.name == cluster_name)?;
+        cluster.cluster.as_ref()
+    }
+}
+
+impl KubeConfigCluster {
+    /// Describe where the CA certificate comes from.
+    fn ca_info(&self) -> String {
+        if self.insecure_skip_tls_verify.unwrap_or(false) {
+            return String::from("none (insecure-skip-tls-verify)");
+        }
+        if let Some(data) = self.certificate_authority_data.as_ref() {
+            return format!("embedded data ({} bytes base64)", data.trim().len());
+        }
+        if let Some(path) = self.certificate_authority.as_ref() {
+            return format!("file {path}");
+        }
+        String::from("system roots")
+    }
+
+    /// Check if the cluster is verified by TLS properly.
+    fn tls_issue(&self) -> Option<TlsIssue> {
+        if self.insecure_skip_tls_verify.unwrap_or(false) {
+            return Some(TlsIssue::Insecure);
+        }
+        if let Some(server) = self.server.as_ref() {
+            if server.starts_with("http://") {
+                return Some(TlsIssue::PlainHttp);
+            }
+        }
+        if self.certificate_authority_data.is_none() && self.certificate_authority.is_none() {
+            return Some(TlsIssue::MissingCa);
+        }
+        None
+    }
+
+    /// Read the CA certificate content (PEM), return `None` if the cluster has no
+    /// CA configured.
+    fn read_ca(&self, kubeconfig_path: &Path) -> Result<Option<Vec<u8>>> {
+        use base64::Engine;
+
+        if let Some(data) = self.certificate_authority_data.as_ref() {
+            let data = base64::engine::general_purpose::STANDARD
+                .decode(data.trim())
+                .context("decode certificate-authority-data")?;
+            return Ok(Some(data));
+        }
+        if let Some(path) = self.certificate_authority.as_ref() {
+            // The relative CA path is relative to the kubeconfig file.
+            let path = match kubeconfig_path.parent() {
+                Some(dir) => dir.join(path),
+                None => PathBuf::from(path),
+            };
+            let data = fs::read(&path)
+                .with_context(|| format!("read certificate-authority '{}'", path.display()))?;
+            return Ok(Some(data));
+        }
+        Ok(None)
+    }
 }
 
 fn get_kubeconfig_namespace<P: AsRef<Path>>(path: P) -> Result<Cow<'static, str>> {
@@ -103,9 +437,12 @@ fn get_kubeconfig_link<P: AsRef<Path>>(cfg: &Config, path: P) -> Result<Option<S
         }
 
         let dest = get_symlink_abs_dest(path.as_ref(), &link);
-        let link = match dest.strip_prefix(&cfg.kube.dir) {
-            Ok(link) => link,
-            Err(_) => return Ok(None),
+        let link = match get_kube_dirs(cfg)
+            .into_iter()
+            .find_map(|dir| dest.strip_prefix(dir).ok())
+        {
+            Some(link) => link,
+            None => return Ok(None),
         };
         let link = link.to_str().unwrap_or("").trim_matches('/');
         if link.is_empty() {
@@ -117,11 +454,198 @@ fn get_kubeconfig_link<P: AsRef<Path>>(cfg: &Config, path: P) -> Result<Option<S
     Ok(None)
 }
 
-fn get_kubeconfig_path<S: AsRef<str>>(cfg: &Config, name: S) -> PathBuf {
+pub fn get_kubeconfig_path<S: AsRef<str>>(cfg: &Config, name: S) -> PathBuf {
+    if let Some((vault_cfg, vault_ctx)) = vault::lookup(cfg, name.as_ref()) {
+        return vault::get_path(vault_cfg, vault_ctx);
+    }
+    // It is only decrypted when used, see `KubeContext::materialize`.
+    if encryption::find(cfg, name.as_ref()).is_some() {
+        return encryption::get_plain_path(cfg, name.as_ref());
+    }
+    if let Some(user_dir) = cfg.kube.user_dir.as_ref() {
+        let path = PathBuf::from(user_dir).join(name.as_ref());
+        if fs::symlink_metadata(&path).is_ok() {
+            return path;
+        }
+    }
     PathBuf::from(&cfg.kube.dir).join(name.as_ref())
 }
 
-fn ensure_dir(path: &Path) -> Result<()> {
+/// The kubeconfig file stored in the kube dirs. For the encrypted context, it is
+/// the encrypted file, rather than the decrypted one from [`get_kubeconfig_path`].
+pub fn get_kubeconfig_store_path<S: AsRef<str>>(cfg: &Config, name: S) -> PathBuf {
+    match encryption::find(cfg, name.as_ref()) {
+        Some(encrypted) => encrypted,
+        None => get_kubeconfig_path(cfg, name),
+    }
+}
+
+/// The path to write the kubeconfig, it is always in the user layer if
+/// `kube.user_dir` is configured.
+pub fn get_kubeconfig_write_path<S: AsRef<str>>(cfg: &Config, name: S) -> PathBuf {
+    let dir = cfg.kube.user_dir.as_ref().unwrap_or(&cfg.kube.dir);
+    PathBuf::from(dir).join(name.as_ref())
+}
+
+/// The kubeconfig dirs, the user layer comes first.
+fn get_kube_dirs(cfg: &Config) -> Vec<&str> {
+    cfg.kube
+        .user_dir
+        .iter()
+        .map(String::as_str)
+        .chain([cfg.kube.dir.as_str()])
+        .collect()
+}
+
+/// Get the context name that the default kubeconfig symlink points to, in symlink
+/// mode.
+fn get_default_symlink_name(cfg: &Config) -> Option<String> {
+    let target = fs::read_link(cfg.kube.symlink.as_ref()?).ok()?;
+    for dir in get_kube_dirs(cfg) {
+        if let Ok(name) = target.strip_prefix(dir) {
+            return Some(name.to_str()?.trim_matches('/').to_string());
+        }
+    }
+    vault::list(cfg)
+        .into_iter()
+        .find(|name| get_kubeconfig_path(cfg, name) == target)
+}
+
+/// Point the symlink to the kubeconfig. A new symlink is renamed over the old one,
+/// so that the readers never see a missing file.
+fn update_default_symlink(link: &Path, path: &Path) -> Result<()> {
+    use std::os::unix::fs::symlink;
+
+    check_default_symlink(link)?;
+    ensure_dir(link)?;
+
+    let tmp = PathBuf::from(format!("{}.kubeswitch-tmp", link.display()));
+    match fs::remove_file(&tmp) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => {
+            return Err(err).with_context(|| format!("remove tmp symlink '{}'", tmp.display()))
+        }
+    }
+    symlink(path, &tmp)
+        .with_context(|| format!("create symlink {} -> {}", tmp.display(), path.display()))?;
+    fs::rename(&tmp, link)
+        .with_context(|| format!("rename symlink '{}' to '{}'", tmp.display(), link.display()))
+}
+
+fn remove_default_symlink(link: &Path) -> Result<()> {
+    check_default_symlink(link)?;
+    match fs::remove_file(link) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err).with_context(|| format!("remove symlink '{}'", link.display())),
+    }
+}
+
+/// Never touch the user's own kubeconfig file.
+fn check_default_symlink(link: &Path) -> Result<()> {
+    match fs::symlink_metadata(link) {
+        Ok(meta) if !meta.is_symlink() => bail!(
+            "'{}' is not a symlink, please move it away to let kubeswitch manage it",
+            link.display()
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Walk all the kubeconfig files under the `prefix` dir of the kubeconfig dirs, the
+/// handle receives the context name and the file path. The files in the user layer
+/// shadow the ones with the same name in `kube.dir`.
+pub fn walk_kubeconfigs<F>(cfg: &Config, prefix: &str, mut handle: F) -> Result<()>
+where
+    F: FnMut(&str, PathBuf) -> Result<()>,
+{
+    let mut seen = HashSet::new();
+    // The symlink is usually `~/.kube/config`, which might be inside the kube dir.
+    let symlink = cfg.kube.symlink.as_ref().map(PathBuf::from);
+    for dir in get_kube_dirs(cfg) {
+        let rules = IgnoreRules::load(Path::new(dir))?;
+        log::debug!("walk kube dir '{dir}' with prefix '{prefix}'");
+        walk_files(PathBuf::from(dir).join(prefix), |path| {
+            if symlink.as_ref() == Some(&path) {
+                log::trace!("skip symlink '{}'", path.display());
+                return Ok(());
+            }
+            let name = path
+                .strip_prefix(dir)
+                .context("inner: strip prefix for walk path")?
+                .to_str()
+                .unwrap_or("")
+                .trim_matches('/');
+            let name = encryption::strip_suffix(cfg, name)
+                .unwrap_or(name)
+                .to_string();
+            if name.is_empty() {
+                return Ok(());
+            }
+            if rules.is_ignored(&name) {
+                log::trace!("skip '{}', it is ignored", path.display());
+                return Ok(());
+            }
+            if !seen.insert(name.clone()) {
+                log::trace!("skip '{}', '{name}' is shadowed", path.display());
+                return Ok(());
+            }
+            log::trace!("found context '{name}' at '{}'", path.display());
+            handle(&name, path)
+        })?;
+    }
+    Ok(())
+}
+
+/// Parse the kubeconfig file the same way as listing, without building the
+/// context, see `ks bench`.
+pub fn parse_kubeconfig(cfg: &Config, name: &str, path: &Path) -> Result<()> {
+    KubeconfigInfo::parse(cfg, name, path).map(|_| ())
+}
+
+/// Order the contexts by the history, the walk order of the kube dirs means
+/// nothing to the user.
+pub fn sort_contexts(cfg: &Config, ctxs: &mut [KubeContext], sort: ContextSort) {
+    if let ContextSort::Name = sort {
+        ctxs.sort_by(|a, b| a.name.cmp(&b.name));
+        return;
+    }
+    let usage = history::usage(cfg);
+    let key = |ctx: &KubeContext| match usage.get(&ctx.name) {
+        Some(usage) => match sort {
+            ContextSort::Usage => (usage.count, usage.last_used),
+            _ => (0, usage.last_used),
+        },
+        None => (0, 0),
+    };
+    ctxs.sort_by(|a, b| key(b).cmp(&key(a)).then_with(|| a.name.cmp(&b.name)));
+}
+
+/// Remove the parent dirs of the removed kubeconfig file which are left empty, up
+/// to the root of the kube dir.
+fn remove_empty_dirs(cfg: &Config, path: &Path) -> Result<()> {
+    let root = Path::new(cfg.kube.user_dir.as_ref().unwrap_or(&cfg.kube.dir));
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        if current == root || !current.starts_with(root) {
+            break;
+        }
+        let is_empty = match fs::read_dir(current) {
+            Ok(mut entries) => entries.next().is_none(),
+            Err(_) => false,
+        };
+        if !is_empty {
+            break;
+        }
+        fs::remove_dir(current)
+            .with_context(|| format!("remove empty dir '{}'", current.display()))?;
+        dir = current.parent();
+    }
+    Ok(())
+}
+
+pub fn ensure_dir(path: &Path) -> Result<()> {
     if let Some(dir) = path.parent() {
         match fs::metadata(dir) {
             Ok(_) => {}
@@ -138,6 +662,179 @@ fn ensure_dir(path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Create a dir (and its parents) that can only be accessed by the current user.
+/// The runtime dir might be under the shared `/tmp` with a predictable name, so it
+/// is verified to be the user's own even if it already exists.
+pub fn create_private_dir(dir: &Path) -> Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+        .with_context(|| format!("create private dir '{}'", dir.display()))?;
+
+    let runtime_dir = get_runtime_dir();
+    if dir.starts_with(&runtime_dir) {
+        check_private_dir(&runtime_dir)?;
+    }
+    Ok(())
+}
+
+/// Write the file that can only be accessed by the current user, such as the
+/// kubeconfigs with credentials.
+pub fn write_private(path: &Path, data: &[u8]) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+        .with_context(|| format!("open file '{}' for writing", path.display()))?;
+    file.write_all(data)
+        .with_context(|| format!("write file '{}'", path.display()))
+}
+
+/// Bail unless the dir is a real dir owned by the current user with mode 0700.
+fn check_private_dir(dir: &Path) -> Result<()> {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let meta = fs::symlink_metadata(dir)
+        .with_context(|| format!("get metadata of private dir '{}'", dir.display()))?;
+    if !meta.is_dir() {
+        bail!("private dir '{}' is a symlink or not a dir", dir.display());
+    }
+    let euid = get_euid();
+    if meta.uid() != euid {
+        bail!(
+            "private dir '{}' is owned by uid {}, not the current user {euid}, remove it or set XDG_RUNTIME_DIR",
+            dir.display(),
+            meta.uid()
+        );
+    }
+    let mode = meta.permissions().mode() & 0o777;
+    if mode != 0o700 {
+        bail!(
+            "private dir '{}' has mode {mode:o}, should be 700, fix it by `chmod 700 {}`",
+            dir.display(),
+            dir.display()
+        );
+    }
+    Ok(())
+}
+
+/// Find the executable path from `$PATH`, if the name contains '/', return itself.
+pub fn find_exec(name: &str) -> Option<PathBuf> {
+    if name.contains('/') {
+        return Some(PathBuf::from(name));
+    }
+    let paths = env::var_os("PATH")?;
+    env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Find the executable, the context shims dir takes precedence over `$PATH`.
+fn find_exec_with_shims(exec: &str, shims: Option<&Path>) -> Option<PathBuf> {
+    if let Some(shims) = shims {
+        let path = shims.join(get_exec_name(exec));
+        if path.is_file() {
+            return Some(path);
+        }
+    }
+    find_exec(exec)
+}
+
+fn get_exec_name(exec: &str) -> &str {
+    Path::new(exec)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(exec)
+}
+
+/// The GUI editors return immediately without these flags, before the file is
+/// edited.
+const EDITOR_WAIT_FLAGS: [(&str, &str); 10] = [
+    ("code", "--wait"),
+    ("code-insiders", "--wait"),
+    ("codium", "--wait"),
+    ("cursor", "--wait"),
+    ("zed", "--wait"),
+    ("atom", "--wait"),
+    ("subl", "-w"),
+    ("mate", "-w"),
+    ("gvim", "-f"),
+    ("mvim", "-f"),
+];
+
+/// Build the editor command to edit the file. The editor can carry args itself,
+/// such as `code --wait`.
+fn get_editor_command(cfg: &Config, path: &Path) -> Command {
+    let mut fields = cfg.editor.split_whitespace();
+    let exec = fields.next().unwrap_or(&cfg.editor);
+    let mut args: Vec<String> = fields.map(String::from).collect();
+    args.extend(cfg.editor_args.iter().cloned());
+    if args.is_empty() {
+        let name = get_exec_name(exec);
+        if let Some((_, flag)) = EDITOR_WAIT_FLAGS.iter().find(|(editor, _)| *editor == name) {
+            args.push(flag.to_string());
+        }
+    }
+
+    let file = format!("{}", path.display());
+    let mut has_file = false;
+    for arg in args.iter_mut() {
+        if arg.contains("{file}") {
+            *arg = arg.replace("{file}", &file);
+            has_file = true;
+        }
+    }
+    if !has_file {
+        args.push(file);
+    }
+
+    let mut cmd = Command::new(exec);
+    cmd.args(args);
+    cmd
+}
+
+/// Write a wrapper script to `dir`, which execs `exec` with extra `--namespace`
+/// flag from the kubeswitch env. The flags passed by user come after it, so they
+/// can still override the namespace.
+fn write_namespace_shim(dir: &Path, exec: &str, shims: Option<&Path>) -> Result<()> {
+    let name = get_exec_name(exec);
+    let exec = match find_exec_with_shims(exec, shims) {
+        Some(exec) => exec,
+        None => {
+            warning!("cannot find '{exec}' in your system, skip generating its wrapper");
+            return Ok(());
+        }
+    };
+    let script = format!(
+        "#!/bin/sh\nexec {} --namespace \"${{{}:-default}}\" \"$@\"\n",
+        quote_shell(&exec.display().to_string()),
+        KubeContextBuilder::NAMESPACE_ENV
+    );
+    write_script(&dir.join(name), &script)
+}
+
+/// Quote the value in single quotes for the scripts, such as the paths.
+pub fn quote_shell(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Write an executable script that can only be accessed by the current user.
+fn write_script(path: &Path, script: &str) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::write(path, script).with_context(|| format!("write script '{}'", path.display()))?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o700))
+        .with_context(|| format!("set permissions for script '{}'", path.display()))
+}
+
 fn find_share_parent_dir(path1: &Path, path2: &Path) -> PathBuf {
     let mut dir = PathBuf::new();
     let mut iter2 = path2.iter();
@@ -171,21 +868,24 @@ fn get_symlink_rel_source(source: &Path, dest: &Path) -> PathBuf {
     rel_path.join(source_rel)
 }
 
-pub fn create_symlink(cfg: &Config, target: &str) -> Result<()> {
+pub fn create_symlink(cfg: &Config, source: &str, dest: &str) -> Result<()> {
     use std::os::unix::fs::symlink;
 
-    let fields: Vec<_> = target.split(':').collect();
-    if fields.len() != 2 {
-        bail!("bad link name format, should be '<source>:<target>'");
+    if vault::lookup(cfg, source).is_some() {
+        bail!("link source cannot be a vault context");
+    }
+
+    if encryption::find(cfg, source).is_some() {
+        bail!("link source cannot be an encrypted context");
     }
 
-    let source = get_kubeconfig_path(cfg, fields[0]);
+    let source = get_kubeconfig_path(cfg, source);
     let meta = fs::metadata(&source).context("read metadata for link source")?;
     if meta.is_dir() {
         bail!("link source cannot be a dir");
     }
 
-    let dest = get_kubeconfig_path(cfg, fields[1]);
+    let dest = get_kubeconfig_write_path(cfg, dest);
     ensure_dir(&dest)?;
 
     let source = get_symlink_rel_source(&source, &dest);
@@ -195,7 +895,7 @@ pub fn create_symlink(cfg: &Config, target: &str) -> Result<()> {
     Ok(())
 }
 
-fn walk_files<P, F>(dir: P, mut handle: F) -> Result<()>
+pub fn walk_files<P, F>(dir: P, mut handle: F) -> Result<()>
 where
     P: AsRef<Path>,
     F: FnMut(PathBuf) -> Result<()>,
@@ -223,7 +923,8 @@ where
                 continue;
             }
 
-            if meta.is_dir() {
+            // The git metadata of the synced kube dir, see `ks sync`.
+            if meta.is_dir() && ent.file_name() != ".git" {
                 stack.push(Cow::Owned(path));
             }
         }
@@ -232,6 +933,46 @@ where
     Ok(())
 }
 
+/// The info parsed from the kubeconfig file, it is independent of the builder so
+/// that the files can be parsed in parallel.
+struct KubeconfigInfo {
+    namespace: Cow<'static, str>,
+    link: Option<String>,
+}
+
+impl KubeconfigInfo {
+    fn parse<P: AsRef<Path>>(cfg: &Config, name: &str, path: P) -> Result<Self> {
+        if encryption::is_encrypted(cfg, path.as_ref()) {
+            // Never decrypt for parsing, such as listing all the contexts. The
+            // namespace is unknown until it is decrypted by using the context.
+            if let Some(path) = encryption::get_fresh_plain_path(cfg, name, path.as_ref()) {
+                return Self::parse(cfg, name, path);
+            }
+            return Ok(KubeconfigInfo {
+                namespace: Self::default_namespace(cfg, name),
+                link: None,
+            });
+        }
+        let kubeconfig = KubeConfig::read_lazy(cfg, path.as_ref())
+            .with_context(|| format!("read kubeconfig file '{}'", path.as_ref().display()))?;
+        // The namespace set in kubeconfig wins, the `default_namespace` config only
+        // replaces the "default".
+        let namespace = match kubeconfig.current_namespace() {
+            Some(ns) => Cow::Owned(ns),
+            None => Self::default_namespace(cfg, name),
+        };
+        let link = get_kubeconfig_link(cfg, path.as_ref())?;
+        Ok(KubeconfigInfo { namespace, link })
+    }
+
+    fn default_namespace(cfg: &Config, name: &str) -> Cow<'static, str> {
+        match cfg.match_default_namespace(name) {
+            Some(ns) => Cow::Owned(ns.to_string()),
+            None => Cow::Borrowed("default"),
+        }
+    }
+}
+
 struct KubeContextBuilder {
     current: Option<String>,
     namespace: Option<String>,
@@ -244,8 +985,10 @@ impl KubeContextBuilder {
     const NAME_ENV: &'static str = "KUBESWITCH_NAME";
     const NAMESPACE_ENV: &'static str = "KUBESWITCH_NAMESPACE";
 
-    fn new() -> Self {
-        let current = env::var_os(Self::NAME_ENV).map(|s| s.to_string_lossy().into_owned());
+    fn new(cfg: &Config) -> Self {
+        let current = env::var_os(Self::NAME_ENV)
+            .map(|s| s.to_string_lossy().into_owned())
+            .or_else(|| get_default_symlink_name(cfg));
         let namespace = env::var_os(Self::NAMESPACE_ENV).map(|s| s.to_string_lossy().into_owned());
         KubeContextBuilder {
             current,
@@ -255,14 +998,29 @@ impl KubeContextBuilder {
         }
     }
 
-    fn parse_kubeconfig<P: AsRef<Path>>(&mut self, cfg: &Config, path: P) -> Result<()> {
-        let namespace = get_kubeconfig_namespace(path.as_ref())?;
-        self.kubeconfig_namespace = Some(namespace);
+    fn parse_kubeconfig<P: AsRef<Path>>(
+        &mut self,
+        cfg: &Config,
+        name: &str,
+        path: P,
+    ) -> Result<()> {
+        let info = KubeconfigInfo::parse(cfg, name, path)?;
+        self.set_kubeconfig(info);
+        Ok(())
+    }
 
-        let link = get_kubeconfig_link(cfg, path.as_ref())?;
-        self.kubeconfig_link = link;
+    fn set_kubeconfig(&mut self, info: KubeconfigInfo) {
+        self.kubeconfig_namespace = Some(info.namespace);
+        self.kubeconfig_link = info.link;
+    }
 
-        Ok(())
+    fn parse_context(&mut self, cfg: &Config, name: &str) -> Result<()> {
+        if let Some((_, vault_ctx)) = vault::lookup(cfg, name) {
+            self.kubeconfig_namespace = Some(Cow::Owned(vault_ctx.namespace.clone()));
+            self.kubeconfig_link = None;
+            return Ok(());
+        }
+        self.parse_kubeconfig(cfg, name, get_kubeconfig_store_path(cfg, name))
     }
 
     fn set_namespace(&mut self, namespace: String) {
@@ -284,7 +1042,7 @@ impl KubeContextBuilder {
         if is_current {
             let name = self.current.take().unwrap();
             let namespace = match self.namespace.take() {
-                Some(ns) => Cow::Owned(ns),
+                Some(ns) => choose_current_namespace(cfg, &name, ns, namespace),
                 None => namespace,
             };
             return KubeContext {
@@ -312,12 +1070,12 @@ impl KubeContextBuilder {
         }
         let name = name.unwrap();
 
-        let path = get_kubeconfig_path(cfg, name.as_str());
-        let namespace = get_kubeconfig_namespace(&path)?;
-        let link = get_kubeconfig_link(cfg, &path)?;
+        self.parse_context(cfg, &name)?;
+        let namespace = self.kubeconfig_namespace.take().unwrap();
+        let link = self.kubeconfig_link.take();
 
         let namespace = match self.namespace.take() {
-            Some(ns) => Cow::Owned(ns),
+            Some(ns) => choose_current_namespace(cfg, &name, ns, namespace),
             None => namespace,
         };
 
@@ -331,96 +1089,44 @@ impl KubeContextBuilder {
     }
 }
 
-struct History {
-    rev_file: RevLines<fs::File>,
-}
-
-impl History {
-    const HISTORY_NAME: &'static str = ".kubeswitch_history";
-
-    fn open() -> Result<History> {
-        let file = fs::File::open(Self::get_path()?)
-            .with_context(|| format!("open history file '{}' for reading", Self::HISTORY_NAME))?;
-        let rev_file = RevLines::new(file);
-        Ok(History { rev_file })
-    }
-
-    fn write(ctx: &KubeContext) -> Result<()> {
-        let mut opts = fs::OpenOptions::new();
-        opts.create(true).write(true).append(true);
-
-        let mut file = opts
-            .open(Self::get_path()?)
-            .with_context(|| format!("open history file '{}' for writing", Self::HISTORY_NAME))?;
-
-        let now = Self::now()?;
-        let line = format!("{now} {} {}\n", ctx.name, ctx.namespace);
-
-        file.write_all(line.as_bytes())
-            .context("write content to history file")?;
-        file.flush().context("flush history file")?;
-
-        Ok(())
+/// Choose the namespace of the current context between the one switched in shell
+/// and the one in kubeconfig file, see `kube.namespace_source`.
+fn choose_current_namespace(
+    cfg: &Config,
+    name: &str,
+    env_namespace: String,
+    file_namespace: Cow<'static, str>,
+) -> Cow<'static, str> {
+    match cfg.kube.namespace_source {
+        NamespaceSource::Env => Cow::Owned(env_namespace),
+        NamespaceSource::File => file_namespace,
+        NamespaceSource::Merge if changed_after_switch(cfg, name) => file_namespace,
+        NamespaceSource::Merge => Cow::Owned(env_namespace),
     }
+}
 
-    fn now() -> Result<u64> {
-        let current_time = SystemTime::now();
-
-        let timestamp = current_time
-            .duration_since(UNIX_EPOCH)
-            .expect("time went backwards")
-            .as_secs();
-        Ok(timestamp)
+/// Check if the kubeconfig file was modified after the last switch to the context,
+/// according to the history.
+fn changed_after_switch(cfg: &Config, name: &str) -> bool {
+    if vault::lookup(cfg, name).is_some() {
+        return false;
     }
-
-    fn get_path() -> Result<PathBuf> {
-        let home = match env::var_os("HOME") {
-            Some(home) => home,
-            None => bail!("cannot find $HOME env in your system"),
-        };
-
-        let path = PathBuf::from(home);
-        Ok(path.join(Self::HISTORY_NAME))
-    }
-}
-
-impl Iterator for History {
-    type Item = Result<(String, String)>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            let item = self.rev_file.next()?;
-            if let Err(err) = item {
-                return Some(Err(err).context("read history file"));
-            }
-            let line = item.unwrap();
-            let line = line.trim();
-            if line.is_empty() {
-                continue;
-            }
-
-            let fields: Vec<_> = line.split(' ').collect();
-            if fields.len() != 3 {
-                continue;
-            }
-
-            let mut iter = fields.into_iter();
-
-            // Ignore the first timestamp
-            iter.next();
-
-            let name = iter.next().unwrap();
-            if name.is_empty() {
-                continue;
-            }
-
-            let namespace = iter.next().unwrap();
-            if namespace.is_empty() {
-                continue;
-            }
-
-            return Some(Ok((name.to_string(), namespace.to_string())));
-        }
+    let modified = match fs::metadata(get_kubeconfig_store_path(cfg, name))
+        .and_then(|meta| meta.modified())
+        .map(|time| time.duration_since(UNIX_EPOCH).unwrap_or_default())
+    {
+        Ok(modified) => modified.as_secs(),
+        Err(_) => return false,
+    };
+    let switched = History::open(cfg).ok().and_then(|history| {
+        history
+            .flatten()
+            .find(|entry| entry.name == name)
+            .map(|entry| entry.timestamp)
+    });
+    match switched {
+        Some(switched) => modified > switched,
+        None => true,
     }
 }
 
@@ -438,20 +1144,31 @@ where
     cmd.stdin(Stdio::inherit());
     cmd.stdout(Stdio::piped());
 
+    log::debug!(
+        "execute {}, KUBECONFIG='{}'",
+        process::format_command(&cmd),
+        path.as_ref().display()
+    );
+    let start = Instant::now();
     let output = cmd.output().context("execute kubectl command")?;
+    log::debug!(
+        "{} exited with {} in {:.2?}",
+        cfg.kube.exec,
+        output.status,
+        start.elapsed()
+    );
     let stdout = String::from_utf8(output.stdout).context("decode kubectl output")?;
     match output.status.code() {
         Some(code) => {
             if code != 0 {
                 let stderr = String::from_utf8_lossy(&output.stderr);
-                let args: Vec<_> = cmd.get_args().map(|arg| arg.to_str().unwrap()).collect();
-                eprintln!(
-                    "Execute kubectl command failed: {} {}",
+                let args: Vec<_> = cmd.get_args().map(|arg| arg.to_string_lossy()).collect();
+                bail!(
+                    "execute kubectl command '{} {}' exited with bad code {code}: {}",
                     cfg.kube.exec,
-                    args.join(" ")
+                    args.join(" "),
+                    stderr.trim()
                 );
-                eprintln!();
-                bail!("Command exited with bad code {code}: {stderr}");
             }
         }
         None => bail!("kubectl command exited with unknown code"),
@@ -480,59 +1197,74 @@ where
     Ok(items)
 }
 
-fn search_fzf<S: AsRef<str>>(keys: &Vec<S>) -> Result<usize> {
-    let mut input = String::with_capacity(keys.len());
-    for key in keys {
-        input.push_str(key.as_ref());
-        input.push('\n');
+/// Validate the namespace name, it should be a RFC 1123 label.
+fn validate_namespace_name(name: &str) -> Result<()> {
+    if name.len() > 63 {
+        bail!("invalid namespace '{name}', should be no more than 63 characters");
     }
+    let re = Regex::new("^[a-z0-9]([-a-z0-9]*[a-z0-9])?$").unwrap();
+    if !re.is_match(name) {
+        bail!(
+            "invalid namespace '{name}', should consist of lower case alphanumeric characters or '-', and start and end with an alphanumeric character"
+        );
+    }
+    Ok(())
+}
 
-    let mut cmd = Command::new("fzf");
-    cmd.stdin(Stdio::piped());
-    cmd.stderr(Stdio::inherit());
-    cmd.stdout(Stdio::piped());
-
-    let mut child = match cmd.spawn() {
-        Ok(child) => child,
-        Err(e) if e.kind() == io::ErrorKind::NotFound => {
-            bail!("cannot find fzf in your system, please install it first");
+/// The version of the switch protocol, it is embedded in the wrap scripts by
+/// `init`, and the scripts pass it back by the `KUBESWITCH_PROTOCOL` env. Bump it
+/// when the protocol changes, the output for the older scripts should be kept.
+///
+/// - 2: the shims line.
+/// - 3: the color and emoji lines.
+/// - 4: the env to restore after the clean flag.
+/// - 5: the version is passed by the scripts.
+pub const PROTOCOL_VERSION: u32 = 5;
+
+const PROTOCOL_ENV: &str = "KUBESWITCH_PROTOCOL";
+
+/// Get the protocol version of the wrap script in use. The scripts before version
+/// 5 do not pass it, guess it by the session env exported since version 4. Version
+/// 1 is assumed for the older ones, the unknown lines would be evaluated by them.
+fn get_wrapper_protocol() -> u32 {
+    if let Some(version) = env::var(PROTOCOL_ENV).ok().filter(|v| !v.is_empty()) {
+        match version.parse() {
+            Ok(version) => return version,
+            Err(_) => warning!("invalid protocol version '{version}' in env {PROTOCOL_ENV}"),
         }
-        Err(e) => {
-            return Err(e).context("failed to launch fzf");
-        }
-    };
-
-    let handle = child.stdin.as_mut().unwrap();
-    write!(handle, "{input}").context("write input to fzf")?;
-    drop(child.stdin.take());
+    }
+    if Session::is_enabled() {
+        4
+    } else {
+        1
+    }
+}
 
-    let mut stdout = child.stdout.take();
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+static NO_INTERACTIVE: AtomicBool = AtomicBool::new(false);
 
-    let status = child.wait().context("wait fzf done")?;
+/// Answer yes to all the confirmations, see [`confirm`].
+pub fn set_assume_yes(yes: bool) {
+    ASSUME_YES.store(yes, Ordering::Relaxed);
+}
 
-    match status.code() {
-        Some(0) => {
-            let result = match stdout.as_mut() {
-                Some(stdout) => {
-                    let mut out = String::new();
-                    stdout.read_to_string(&mut out).context("read fzf output")?;
-                    out
-                }
-                None => bail!("fzf did not output anything"),
-            };
-            let result = result.trim();
+/// Fail instead of prompting user, the selector should be replaced by
+/// [`selector::NoInteractiveSelector`] too.
+pub fn set_no_interactive(no_interactive: bool) {
+    NO_INTERACTIVE.store(no_interactive, Ordering::Relaxed);
+}
 
-            match keys.iter().position(|s| s.as_ref() == result) {
-                Some(idx) => Ok(idx),
-                None => bail!("cannot find key '{result}' from fzf output"),
-            }
-        }
-        Some(1) => bail!("fzf no match found"),
-        Some(2) => bail!("fzf returned an error"),
-        Some(130) => bail!("fzf canceled"),
-        Some(128..=254) | None => bail!("fzf was terminated"),
-        _ => bail!("fzf returned an unknown error"),
+fn ensure_interactive(what: &str) -> Result<()> {
+    if NO_INTERACTIVE.load(Ordering::Relaxed) {
+        bail!("cannot {what} in non-interactive mode");
     }
+    Ok(())
+}
+
+/// Check if the name contains all the characters of the query in order.
+fn fuzzy_match(name: &str, query: &str) -> bool {
+    let mut chars = name.chars();
+    query.chars().all(|c| chars.any(|n| n == c))
 }
 
 /// Ask user to confirm.
@@ -541,6 +1273,15 @@ pub fn confirm(msg: impl AsRef<str>) -> Result<bool> {
         // In testing, skip confirm.
         return Ok(true);
     }
+    if ASSUME_YES.load(Ordering::Relaxed) {
+        return Ok(true);
+    }
+    if NO_INTERACTIVE.load(Ordering::Relaxed) {
+        bail!(
+            "cannot confirm '{}' in non-interactive mode, use `--yes` to skip",
+            msg.as_ref()
+        );
+    }
 
     eprint!("{}? [Y/n] ", msg.as_ref());
 
@@ -553,11 +1294,159 @@ pub fn confirm(msg: impl AsRef<str>) -> Result<bool> {
     Ok(true)
 }
 
+/// The private temp file for editing, unique per invocation. It is removed when
+/// dropped, including the error paths, unless `keep` is set to leave the edited
+/// content to the user.
+struct EditFile {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl EditFile {
+    fn create(cfg: &Config, name: &str, content: &[u8]) -> Result<EditFile> {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let dir = match cfg.edit_tmp_dir.as_ref() {
+            Some(dir) => PathBuf::from(dir),
+            None => get_runtime_dir().join("edit"),
+        };
+        create_private_dir(&dir)?;
+
+        // Keep the extension for the syntax highlighting of editor.
+        let file_name = format!("{}-{}.yaml", name.replace('/', "-"), std::process::id());
+        let path = dir.join(file_name);
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .with_context(|| format!("create edit tmp file '{}'", path.display()))?;
+        let edit_file = EditFile { path, keep: false };
+        file.write_all(content)
+            .context("write raw content to edit tmp file")?;
+        Ok(edit_file)
+    }
+}
+
+impl Drop for EditFile {
+    fn drop(&mut self) {
+        if self.keep {
+            return;
+        }
+        if let Err(err) = fs::remove_file(&self.path) {
+            warning!("remove edit tmp file '{}': {err}", self.path.display());
+        }
+    }
+}
+
+/// The result of the reachability probe of a context.
+pub enum Health {
+    Ok,
+    Unreachable(String),
+    AuthExpired(String),
+}
+
+impl Health {
+    /// The error messages of kubectl that mean the credential is no longer valid.
+    const AUTH_ERRORS: [&'static str; 5] = [
+        "unauthorized",
+        "must be logged in",
+        "token has expired",
+        "certificate has expired",
+        "getting credentials",
+    ];
+
+    pub fn status(&self) -> &'static str {
+        match self {
+            Self::Ok => "OK",
+            Self::Unreachable(_) => "UNREACHABLE",
+            Self::AuthExpired(_) => "AUTH-EXPIRED",
+        }
+    }
+
+    pub fn detail(&self) -> &str {
+        match self {
+            Self::Ok => "",
+            Self::Unreachable(detail) | Self::AuthExpired(detail) => detail,
+        }
+    }
+
+    /// Probe the contexts in parallel, the results are in the same order.
+    pub fn check_all(ctxs: &[KubeContext], timeout: u64) -> Vec<Health> {
+        parallel_map(ctxs, |ctx| ctx.check_health(timeout))
+    }
+}
+
+/// Map the items in parallel with a small thread pool, the results are in the same
+/// order as the items.
+fn parallel_map<T, R, F>(items: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    const MAX_WORKERS: usize = 16;
+
+    let workers = items.len().clamp(1, MAX_WORKERS);
+    let f = &f;
+    let mut results: Vec<_> = thread::scope(|s| {
+        let handles: Vec<_> = (0..workers)
+            .map(|worker| {
+                s.spawn(move || {
+                    items
+                        .iter()
+                        .enumerate()
+                        .skip(worker)
+                        .step_by(workers)
+                        .map(|(idx, item)| (idx, f(item)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("worker thread panicked"))
+            .collect()
+    });
+    results.sort_unstable_by_key(|(idx, _)| *idx);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// How the query given by the user resolves to the context, in the order that
+/// [`KubeContext::select`] tries them.
+pub enum Resolution {
+    /// `-`, the latest context in history other than the current one.
+    History,
+
+    /// `@<time>`, the context that was in use at the time.
+    Time(String),
+
+    /// `<dir>/`, select from the contexts under the dir.
+    Dir(String),
+
+    /// The context whose kubeconfig is fetched from the vault.
+    Vault,
+
+    /// The kubeconfig file of the context, `link` is the context it links to if it
+    /// is a symlink context.
+    File { path: PathBuf, link: Option<String> },
+
+    /// The context was deleted or renamed.
+    Tombstone(Tombstone),
+
+    /// No context has the name, select from the ones matching it.
+    Match,
+}
+
 pub enum SelectOption {
     GetRequired,
     GetNotRequired,
 
     Switch,
+
+    /// Always launch the selector, the current context is included.
+    Pick,
 }
 
 impl Display for KubeContext<'_> {
@@ -572,89 +1461,111 @@ impl Display for KubeContext<'_> {
 }
 
 impl KubeContext<'_> {
-    const EDIT_TMP_PATH: &'static str = "/tmp/kubeswitch-edit-config.yaml";
+    const SHELL_ENV: &'static str = "KUBESWITCH_SHELL";
+    const DESCRIBE_RECENT: usize = 5;
 
-    pub fn list(cfg: &Config) -> Result<Vec<KubeContext>> {
-        Self::list_inner(cfg, None)
+    pub fn list(cfg: &Config) -> Result<Vec<KubeContext<'_>>> {
+        Self::list_inner(cfg, "")
     }
 
-    fn list_inner(cfg: &Config, dir: Option<PathBuf>) -> Result<Vec<KubeContext>> {
-        let dir = dir.unwrap_or(PathBuf::from(&cfg.kube.dir));
+    /// List the contexts under the dir.
+    pub fn list_dir<'a>(cfg: &'a Config, dir: &str) -> Result<Vec<KubeContext<'a>>> {
+        Self::list_inner(cfg, dir)
+    }
 
+    fn list_inner<'a>(cfg: &'a Config, prefix: &str) -> Result<Vec<KubeContext<'a>>> {
         let mut ctxs = Vec::new();
-        let mut builder = KubeContextBuilder::new();
+        let mut builder = KubeContextBuilder::new(cfg);
 
-        walk_files(&dir, |path| {
-            if !path.starts_with(&cfg.kube.dir) {
-                bail!(
-                    "inner: invalid walk path '{}', it should starts with '{}'",
-                    path.display(),
-                    dir.display()
-                );
+        let mut files = Vec::new();
+        walk_kubeconfigs(cfg, prefix, |name, path| {
+            files.push((name.to_string(), path));
+            Ok(())
+        })?;
+        // Parsing is the most expensive part for a large kube dir, do it in parallel.
+        let infos = parallel_map(&files, |(name, path)| {
+            KubeconfigInfo::parse(cfg, name, path)
+        });
+        for ((name, _), info) in files.into_iter().zip(infos) {
+            match info {
+                Ok(info) => builder.set_kubeconfig(info),
+                Err(err) => {
+                    warning!("skip invalid kubeconfig '{name}': {err:#}");
+                    continue;
+                }
             }
+            ctxs.push(builder.build(cfg, name));
+        }
 
-            let name = path
-                .strip_prefix(&cfg.kube.dir)
-                .context("inner: strip prefix for walk path")?
-                .to_str()
-                .unwrap_or("")
-                .trim_matches('/');
-            if name.is_empty() {
-                return Ok(());
+        vault::purge_expired(cfg).context("purge expired vault credentials")?;
+        let prefix = prefix.trim_matches('/');
+        for name in vault::list(cfg) {
+            if !prefix.is_empty() && !name.starts_with(&format!("{prefix}/")) {
+                continue;
             }
+            builder.parse_context(cfg, &name)?;
+            ctxs.push(builder.build(cfg, name));
+        }
 
-            builder.parse_kubeconfig(cfg, &path)?;
-            let ctx = builder.build(cfg, name);
-            ctxs.push(ctx);
-
-            Ok(())
-        })?;
+        if !cfg.tag_filter.is_empty() {
+            let tags = Tags::load()?;
+            ctxs.retain(|ctx| tags.matches(&ctx.name, &cfg.tag_filter));
+        }
 
         Ok(ctxs)
     }
 
-    pub fn current(cfg: &Config) -> Result<KubeContext> {
-        let mut builder = KubeContextBuilder::new();
+    pub fn current(cfg: &Config) -> Result<KubeContext<'_>> {
+        let mut builder = KubeContextBuilder::new(cfg);
         builder.must_current(cfg)
     }
 
+    /// The same as [`Self::current`], but `None` if no context was switched to.
+    pub fn try_current(cfg: &Config) -> Result<Option<KubeContext<'_>>> {
+        let mut builder = KubeContextBuilder::new(cfg);
+        if builder.current.is_none() {
+            return Ok(None);
+        }
+        builder.must_current(cfg).map(Some)
+    }
+
     pub fn select<'a>(
         cfg: &'a Config,
         query: &Option<String>,
         opt: SelectOption,
     ) -> Result<KubeContext<'a>> {
         if let Some(query) = query.as_ref() {
-            if query == "-" {
-                return Self::select_by_history(cfg);
-            }
-
-            if query.ends_with('/') {
-                let dir = query.strip_suffix('/').unwrap_or("");
-                return Self::select_by_dir(cfg, dir, opt);
-            }
-
-            let mut builder = KubeContextBuilder::new();
-            let path = get_kubeconfig_path(cfg, query);
-            return match fs::metadata(&path) {
-                Ok(_) => {
-                    builder.parse_kubeconfig(cfg, &path)?;
+            let mut builder = KubeContextBuilder::new(cfg);
+            return match Self::resolve(cfg, query)? {
+                Resolution::History => Self::select_by_history(cfg),
+                Resolution::Time(time) => Self::select_by_time(cfg, &time),
+                Resolution::Dir(dir) => Self::select_by_dir(cfg, &dir, opt),
+                Resolution::Vault => {
+                    builder.parse_context(cfg, query)?;
                     Ok(builder.build(cfg, query))
                 }
-                Err(err) if err.kind() == io::ErrorKind::NotFound => match opt {
-                    SelectOption::GetNotRequired => Ok(builder.build(cfg, query)),
-                    _ => bail!("context '{query}' not found"),
-                },
-                Err(err) => Err(err)
-                    .with_context(|| format!("stat metadata for kubeconfig '{}'", path.display())),
+                Resolution::File { path, .. } => {
+                    builder.parse_kubeconfig(cfg, query, &path)?;
+                    Ok(builder.build(cfg, query))
+                }
+                _ if matches!(opt, SelectOption::GetNotRequired) => Ok(builder.build(cfg, query)),
+                Resolution::Tombstone(tombstone) => {
+                    Self::select_by_tombstone(cfg, query, tombstone, opt)
+                }
+                // The commands acting on the context need the exact name.
+                Resolution::Match if matches!(opt, SelectOption::GetRequired) => {
+                    Self::bail_not_found(cfg, query)
+                }
+                Resolution::Match => Self::select_by_match(cfg, query, opt),
             };
         }
 
-        let mut builder = KubeContextBuilder::new();
+        let mut builder = KubeContextBuilder::new(cfg);
         match opt {
-            SelectOption::GetNotRequired | SelectOption::GetRequired => {
-                if builder.current.is_some() {
-                    return builder.must_current(cfg);
-                }
+            SelectOption::GetNotRequired | SelectOption::GetRequired
+                if builder.current.is_some() =>
+            {
+                return builder.must_current(cfg);
             }
             _ => {}
         }
@@ -666,25 +1577,29 @@ impl KubeContext<'_> {
         if ctxs.is_empty() {
             bail!("no context to select");
         }
+        sort_contexts(cfg, &mut ctxs, cfg.sort);
 
         let items: Vec<&str> = ctxs.iter().map(|c| c.name.as_str()).collect();
-        let idx = search_fzf(&items)?;
+        let idx = selector::select_with_info(
+            &items,
+            &Self::get_select_infos(cfg, &ctxs),
+            Self::get_preview_command(cfg, "").as_deref(),
+        )?;
         let ctx = ctxs.remove(idx);
 
         Ok(ctx)
     }
 
-    fn select_by_history(cfg: &Config) -> Result<KubeContext> {
-        let mut builder = KubeContextBuilder::new();
-        let history = History::open()?;
-        for item in history {
-            let (name, namespace) = item?;
-            let path = get_kubeconfig_path(cfg, &name);
+    fn select_by_history(cfg: &Config) -> Result<KubeContext<'_>> {
+        let mut builder = KubeContextBuilder::new(cfg);
+        let history = History::open(cfg)?;
+        for entry in history {
+            let entry = entry?;
 
-            builder.parse_kubeconfig(cfg, &path)?;
-            builder.set_namespace(namespace);
+            builder.parse_context(cfg, &entry.name)?;
+            builder.set_namespace(entry.namespace);
 
-            let ctx = builder.build(cfg, name);
+            let ctx = builder.build(cfg, entry.name);
             if ctx.current {
                 continue;
             }
@@ -695,166 +1610,1558 @@ impl KubeContext<'_> {
         bail!("no history kubeconfig to select");
     }
 
-    fn select_by_dir<'a>(cfg: &'a Config, dir: &str, opt: SelectOption) -> Result<KubeContext<'a>> {
-        let dir_path = PathBuf::from(&cfg.kube.dir).join(dir);
-        let mut ctxs = Self::list_inner(cfg, Some(dir_path))?;
-        if let SelectOption::Switch = opt {
-            ctxs.retain(|c| !c.current);
+    /// Select from the recent distinct context and namespace pairs in history, the
+    /// newest first. The current pair and the deleted contexts are skipped.
+    pub fn select_from_history(cfg: &Config, limit: usize) -> Result<KubeContext<'_>> {
+        let builder = KubeContextBuilder::new(cfg);
+        let mut seen = HashSet::new();
+        let mut entries = Vec::new();
+        for entry in History::open(cfg)? {
+            let entry = entry?;
+            if builder.current.as_ref() == Some(&entry.name)
+                && builder.namespace.as_ref() == Some(&entry.namespace)
+            {
+                continue;
+            }
+            if !seen.insert((entry.name.clone(), entry.namespace.clone())) {
+                continue;
+            }
+            if vault::lookup(cfg, &entry.name).is_none()
+                && fs::metadata(get_kubeconfig_store_path(cfg, &entry.name)).is_err()
+            {
+                continue;
+            }
+            entries.push(entry);
+            if entries.len() >= limit {
+                break;
+            }
         }
-        if ctxs.is_empty() {
-            bail!("no context under '{dir}'");
+        if entries.is_empty() {
+            bail!("no history kubeconfig to select");
         }
 
-        let items: Vec<_> = ctxs
+        let keys: Vec<_> = entries.iter().map(|entry| entry.name.as_str()).collect();
+        let width = entries
             .iter()
-            .filter_map(|ctx| ctx.name.strip_prefix(dir).map(|s| s.trim_matches('/')))
+            .map(|entry| entry.namespace.chars().count())
+            .max()
+            .unwrap_or(0);
+        let infos: Vec<_> = entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{:width$}  {}",
+                    entry.namespace,
+                    history::format_elapsed(entry.timestamp)
+                )
+            })
             .collect();
-        let idx = search_fzf(&items)?;
-        let ctx = ctxs.remove(idx);
-
-        Ok(ctx)
+        let preview = Self::get_preview_command(cfg, "");
+        let idx = selector::select_with_info(&keys, &infos, preview.as_deref())?;
+        let entry = entries.remove(idx);
+
+        let mut builder = builder;
+        builder.parse_context(cfg, &entry.name)?;
+        builder.set_namespace(entry.namespace);
+        Ok(builder.build(cfg, entry.name))
     }
 
-    pub fn switch(&self) -> Result<()> {
-        History::write(self)?;
-        self.switch_inner(false);
-        Ok(())
+    /// The indexes of the contexts whose name contains the query, or else the ones
+    /// whose name contains the query characters in order (fuzzy).
+    pub fn find_matches(ctxs: &[KubeContext], query: &str) -> Vec<usize> {
+        let lower = query.to_lowercase();
+        let names: Vec<_> = ctxs.iter().map(|ctx| ctx.name.to_lowercase()).collect();
+        let matched: Vec<_> = (0..ctxs.len())
+            .filter(|idx| names[*idx].contains(&lower))
+            .collect();
+        if !matched.is_empty() {
+            return matched;
+        }
+        (0..ctxs.len())
+            .filter(|idx| fuzzy_match(&names[*idx], &lower))
+            .collect()
     }
 
-    pub fn unset(&self) {
-        self.switch_inner(true);
+    /// Resolve the query to the context the same way as [`Self::select`], without
+    /// parsing the kubeconfig or launching the selector.
+    pub fn resolve(cfg: &Config, query: &str) -> Result<Resolution> {
+        if query == "-" {
+            return Ok(Resolution::History);
+        }
+        if let Some(time) = query.strip_prefix('@') {
+            return Ok(Resolution::Time(time.to_string()));
+        }
+        if let Some(dir) = query.strip_suffix('/') {
+            return Ok(Resolution::Dir(dir.to_string()));
+        }
+        if vault::lookup(cfg, query).is_some() {
+            return Ok(Resolution::Vault);
+        }
+
+        let path = get_kubeconfig_store_path(cfg, query);
+        match fs::metadata(&path) {
+            Ok(_) => {
+                let link = get_kubeconfig_link(cfg, &path)?;
+                Ok(Resolution::File { path, link })
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => match Tombstone::lookup(query)? {
+                Some(tombstone) => Ok(Resolution::Tombstone(tombstone)),
+                None => Ok(Resolution::Match),
+            },
+            Err(err) => Err(err)
+                .with_context(|| format!("stat metadata for kubeconfig '{}'", path.display())),
+        }
     }
 
-    fn switch_inner(&self, clean: bool) {
-        println!("__switch__");
-        println!("{}", self.cfg.kube.cmd);
+    /// The context was not found, consult the tombstones to see if it was renamed
+    /// or deleted.
+    fn select_by_tombstone<'a>(
+        cfg: &'a Config,
+        query: &str,
+        tombstone: Tombstone,
+        opt: SelectOption,
+    ) -> Result<KubeContext<'a>> {
+        let elapsed = history::format_elapsed(tombstone.timestamp);
+        let successor = match tombstone.successor {
+            Some(successor) => successor,
+            None => bail!("context '{query}' was deleted {elapsed}"),
+        };
 
-        if self.cfg.kube.export_kubeconfig {
-            println!("1");
-        } else {
-            println!("0");
+        // Only follow it for switching, the other commands might be destructive.
+        let switching = matches!(opt, SelectOption::Switch | SelectOption::Pick);
+        if !cfg.kube.follow_renamed || !switching {
+            bail!(
+                "context '{query}' was renamed to '{successor}' {elapsed}, please use the new name"
+            );
         }
+        info!("Context '{query}' was renamed to '{successor}', redirecting");
+        Self::select(cfg, &Some(successor), opt)
+    }
 
-        if clean {
-            println!("1");
-            return;
+    /// Bail that the context does not exist, with the matched contexts as hints.
+    fn bail_not_found<T>(cfg: &Config, query: &str) -> Result<T> {
+        let ctxs = Self::list(cfg)?;
+        let matched = Self::find_matches(&ctxs, query);
+        if matched.is_empty() {
+            bail!("context '{query}' not found");
         }
+        let names: Vec<&str> = matched.iter().map(|idx| ctxs[*idx].name.as_str()).collect();
+        bail!(
+            "context '{query}' not found, did you mean: {}",
+            names.join(", ")
+        );
+    }
 
-        println!("0");
-        println!("{}", self.name);
-        println!("{}", self.namespace);
-        println!("{self}"); // display
-        println!("{}", self.cfg.kube.exec);
-        println!("{}", self.get_path().display());
+    /// Select from the contexts whose name contains the query, or else the ones
+    /// whose name contains the query characters in order (fuzzy). Switch to it
+    /// directly if there is only one.
+    fn select_by_match<'a>(
+        cfg: &'a Config,
+        query: &str,
+        opt: SelectOption,
+    ) -> Result<KubeContext<'a>> {
+        let mut ctxs = Self::list(cfg)?;
+        if let SelectOption::Switch = opt {
+            ctxs.retain(|c| !c.current);
+        }
 
-        if self.cfg.k9s.is_none() {
-            println!("0");
-            return;
+        let matched = Self::find_matches(&ctxs, query);
+        if matched.is_empty() {
+            bail!("context '{query}' not found");
         }
 
-        println!("1");
+        let mut ctxs: Vec<_> = ctxs
+            .into_iter()
+            .enumerate()
+            .filter(|(idx, _)| matched.contains(idx))
+            .map(|(_, ctx)| ctx)
+            .collect();
+        if ctxs.len() == 1 {
+            return Ok(ctxs.remove(0));
+        }
+        sort_contexts(cfg, &mut ctxs, cfg.sort);
 
-        let k9s = self.cfg.k9s.as_ref().unwrap();
-        println!("{}", k9s.exec);
-        println!("{}", k9s.cmd);
+        let items: Vec<&str> = ctxs.iter().map(|c| c.name.as_str()).collect();
+        let idx = selector::select_with_info(
+            &items,
+            &Self::get_select_infos(cfg, &ctxs),
+            Self::get_preview_command(cfg, "").as_deref(),
+        )?;
+        Ok(ctxs.remove(idx))
     }
 
-    fn get_path(&self) -> PathBuf {
-        get_kubeconfig_path(self.cfg, &self.name)
-    }
+    /// Select the context that was in use at the given time, see
+    /// [`history::parse_time`] for the query format.
+    fn select_by_time<'a>(cfg: &'a Config, query: &str) -> Result<KubeContext<'a>> {
+        let time = history::parse_time(query)?;
 
-    pub fn edit(&mut self) -> Result<()> {
-        let path = self.get_path();
-        let raw_content = match fs::read(&path) {
-            Ok(data) => data,
-            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
-            Err(err) => {
-                return Err(err)
-                    .with_context(|| format!("read kubeconfig file '{}'", path.display()))
+        let mut builder = KubeContextBuilder::new(cfg);
+        let history = History::open(cfg)?;
+        for entry in history {
+            let entry = entry?;
+            if entry.timestamp > time {
+                continue;
             }
-        };
 
-        let edit_path = PathBuf::from(Self::EDIT_TMP_PATH);
-        fs::write(&edit_path, &raw_content).context("write raw content to edit tmp file")?;
+            builder.parse_context(cfg, &entry.name)?;
+            builder.set_namespace(entry.namespace);
+            return Ok(builder.build(cfg, entry.name));
+        }
 
-        let mut cmd = Command::new(&self.cfg.editor);
-        cmd.arg(format!("{}", edit_path.display()));
-        cmd.stdin(Stdio::inherit());
-        cmd.stdout(io::stderr());
-        cmd.stderr(Stdio::inherit());
+        bail!("no context was in use at '{query}'");
+    }
 
-        cmd.output().with_context(|| {
-            format!(
-                "run edit command '{} {}'",
-                self.cfg.editor,
-                edit_path.display()
-            )
-        })?;
+    /// The auxiliary info of contexts shown in selector, including the namespace and
+    /// the last used time.
+    fn get_select_infos(cfg: &Config, ctxs: &[KubeContext]) -> Vec<String> {
+        let last_used = history::last_used(cfg, ctxs);
 
-        self.namespace =
-            get_kubeconfig_namespace(&edit_path).context("get namespace from edited kubeconfig")?;
+        let width = ctxs
+            .iter()
+            .map(|ctx| ctx.namespace.chars().count())
+            .max()
+            .unwrap_or(0);
+        ctxs.iter()
+            .map(|ctx| {
+                let last_used = match last_used.get(&ctx.name) {
+                    Some(timestamp) => history::format_elapsed(*timestamp),
+                    None => String::from("never used"),
+                };
+                format!("{:<width$}  {last_used}", ctx.namespace)
+            })
+            .collect()
+    }
+
+    /// The command for the selector to preview the item, it calls back to the
+    /// hidden `preview-item` command. The prefix is prepended to the item to get the
+    /// context name.
+    fn get_preview_command(cfg: &Config, prefix: &str) -> Option<String> {
+        if !cfg.selector.preview {
+            return None;
+        }
+        let exe = env::current_exe().ok()?;
+        let prefix = if prefix.is_empty() {
+            String::new()
+        } else {
+            quote_shell(prefix)
+        };
+        Some(format!(
+            "{} preview-item {prefix}{{2}}",
+            quote_shell(&exe.display().to_string())
+        ))
+    }
 
-        let edit_content = fs::read(&edit_path).context("read edit file")?;
-        if edit_content.is_empty() {
-            bail!("edit content cannot be empty");
+    fn select_by_dir<'a>(cfg: &'a Config, dir: &str, opt: SelectOption) -> Result<KubeContext<'a>> {
+        let mut ctxs = Self::list_inner(cfg, dir)?;
+        if let SelectOption::Switch = opt {
+            ctxs.retain(|c| !c.current);
         }
-        if edit_content == raw_content {
-            bail!("edit content not changed");
+        if ctxs.is_empty() {
+            bail!("no context under '{dir}'");
         }
+        sort_contexts(cfg, &mut ctxs, cfg.sort);
 
-        ensure_dir(&path)?;
-        fs::write(&path, edit_content).context("write edit content to kubeconfig")?;
-        fs::remove_file(&edit_path).context("remove edit file")?;
+        let items: Vec<_> = ctxs
+            .iter()
+            .filter_map(|ctx| ctx.name.strip_prefix(dir).map(|s| s.trim_matches('/')))
+            .collect();
+        let prefix = format!("{}/", dir.trim_matches('/'));
+        let idx = selector::select_with_info(
+            &items,
+            &Self::get_select_infos(cfg, &ctxs),
+            Self::get_preview_command(cfg, &prefix).as_deref(),
+        )?;
+        let ctx = ctxs.remove(idx);
 
-        Ok(())
+        Ok(ctx)
     }
 
-    pub fn delete(self) -> Result<()> {
-        let confirm_msg = format!("Do you want to delete {}", self.name);
-        if !confirm(confirm_msg)? {
-            bail!("user aborted");
+    pub fn switch(&self) -> Result<()> {
+        if !self.current {
+            self.check_protect()?;
+            self.check_tls()?;
         }
-
-        let path = self.get_path();
-        fs::remove_file(&path)
-            .with_context(|| format!("remove the kubeconfig file '{}'", path.display()))?;
-        if self.current {
-            self.switch_inner(true);
+        if self.cfg.kube.guard_forwards && !self.current {
+            self.guard_forwards()?;
+        }
+        if let Some((vault_cfg, vault_ctx)) = vault::lookup(self.cfg, &self.name) {
+            vault::ensure(self.cfg, vault_cfg, vault_ctx)?;
+        }
+        if let Some(hooks) = self.cfg.hooks.as_ref() {
+            for hook in hooks.pre_switch.iter() {
+                self.run_hook(hook).context("run pre_switch hook")?;
+            }
+        }
+        History::write(self)?;
+        let shims = self.write_shims()?;
+        if let Some(gitops) = self.cfg.match_gitops(&self.name) {
+            info!("{}", gitops.render_message(&self.name));
+        }
+        self.show_fingerprint();
+        self.switch_inner(false, shims.as_deref())?;
+        if let Some(hooks) = self.cfg.hooks.as_ref() {
+            for hook in hooks.post_switch.iter() {
+                if let Err(err) = self.run_hook(hook) {
+                    warning!("run post_switch hook: {err:#}");
+                }
+            }
         }
         Ok(())
     }
 
-    pub fn list_namespaces(&self) -> Result<Vec<Cow<str>>> {
-        match self.cfg.match_ns_alias(&self.name) {
-            Some(alias) => Ok(alias),
-            None => self.list_namespace_from_command(),
+    fn run_hook(&self, hook: &str) -> Result<()> {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", hook]);
+        cmd.env(KubeContextBuilder::NAME_ENV, &self.name);
+        cmd.env(KubeContextBuilder::NAMESPACE_ENV, self.namespace.as_ref());
+        cmd.env("KUBECONFIG", self.get_path());
+
+        // The stdout is reserved for the wrapper script.
+        cmd.stdin(Stdio::inherit());
+        cmd.stdout(io::stderr());
+        cmd.stderr(Stdio::inherit());
+
+        let status = cmd
+            .status()
+            .with_context(|| format!("execute hook '{hook}'"))?;
+        if !status.success() {
+            bail!("hook '{hook}' failed with {status}");
         }
+        Ok(())
     }
 
-    fn list_namespace_from_command(&self) -> Result<Vec<Cow<str>>> {
-        Ok(execute_kubectl_lines(
-            self.cfg,
-            self.get_path(),
-            [
-                "get",
-                "namespaces",
-                "-o",
-                "custom-columns=NAME:.metadata.name",
-                "--no-headers",
-            ],
-        )?
-        .into_iter()
-        .map(Cow::Owned)
-        .collect())
+    /// The TLS issue of the cluster and its severity in config, `None` if there is
+    /// no issue or it is turned off. The vault contexts are issued by config, they
+    /// are not checked.
+    pub fn tls_issue(&self) -> Option<(TlsIssue, TlsSeverity)> {
+        if vault::lookup(self.cfg, &self.name).is_some() {
+            return None;
+        }
+        let kubeconfig = KubeConfig::read(self.get_path()).ok()?;
+        let issue = kubeconfig.current_cluster()?.tls_issue()?;
+        let severity = match issue {
+            TlsIssue::Insecure | TlsIssue::PlainHttp => self.cfg.tls.insecure,
+            TlsIssue::MissingCa => self.cfg.tls.missing_ca,
+        };
+        if severity == TlsSeverity::Off {
+            return None;
+        }
+        Some((issue, severity))
     }
 
-    pub fn select_namespace(&self, namespace: &Option<String>) -> Result<String> {
-        if let Some(namespace) = namespace.as_ref() {
-            if namespace == "-" {
-                return self.select_namespace_history();
+    fn check_tls(&self) -> Result<()> {
+        let (issue, severity) = match self.tls_issue() {
+            Some(issue) => issue,
+            None => return Ok(()),
+        };
+        match severity {
+            TlsSeverity::Off => {}
+            TlsSeverity::Warn => warning!("context '{}': {}", self.name, issue.message()),
+            TlsSeverity::Confirm => {
+                warning!("context '{}': {}", self.name, issue.message());
+                if !confirm(format!("Continue to switch to {}", self.name))? {
+                    bail!("user aborted");
+                }
             }
-
-            return Ok(namespace.clone());
+            TlsSeverity::Deny => bail!(
+                "context '{}': {}, refuse to switch, see `tls` in config",
+                self.name,
+                issue.message()
+            ),
+        }
+        Ok(())
+    }
+
+    /// Check the risk level of the protected context before accessing it.
+    fn check_protect(&self) -> Result<()> {
+        let protect = match self.cfg.match_protect(&self.name) {
+            Some(protect) => protect,
+            None => return Ok(()),
+        };
+
+        // The message is essential for the protected context, it won't be suppressed
+        // by quiet mode.
+        eprintln!("{}", protect.render_message(&self.name));
+        match protect.level {
+            RiskLevel::Warn => {}
+            RiskLevel::Confirm => {
+                if !confirm(format!("Continue to access {}", self.name))? {
+                    bail!("user aborted");
+                }
+            }
+            RiskLevel::TypeName => {
+                // The strongest level is never skipped by `--yes`.
+                ensure_interactive(&format!(
+                    "type the name of protected context '{}'",
+                    self.name
+                ))?;
+                eprint!("Type the context name to continue: ");
+                let mut answer = String::new();
+                io::stdin()
+                    .read_line(&mut answer)
+                    .context("read context name from stdin")?;
+                if answer.trim() != self.name {
+                    bail!("context name mismatched, aborted");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Ask user to confirm if the current context still has running long-running
+    /// kubectl processes, such as port-forward.
+    fn guard_forwards(&self) -> Result<()> {
+        let current = match KubeContext::current(self.cfg) {
+            Ok(current) => current,
+            Err(_) => return Ok(()),
+        };
+        let exec_name = get_exec_name(&self.cfg.kube.exec);
+        let procs = process::find_kubectl(exec_name, &current.get_path())
+            .context("find running kubectl processes")?;
+        if procs.is_empty() {
+            return Ok(());
+        }
+
+        warning!(
+            "these processes are still using context '{}', they won't follow the switch:",
+            current.name
+        );
+        for proc in procs {
+            eprintln!("  {} {}", proc.pid, proc.args);
+        }
+        if !confirm(format!("Continue switching to {}", self.name))? {
+            bail!("user aborted");
+        }
+        Ok(())
+    }
+
+    pub fn unset(&self) -> Result<()> {
+        self.switch_inner(true, None)
+    }
+
+    /// Apply the switch, by printing the switch protocol for the wrap script, or by
+    /// updating the symlink in symlink mode (see `kube.symlink`).
+    fn switch_inner(&self, clean: bool, shims: Option<&Path>) -> Result<()> {
+        let link = match self.cfg.kube.symlink.as_ref() {
+            Some(link) => Path::new(link),
+            None => {
+                self.print_switch(clean, shims, None);
+                return Ok(());
+            }
+        };
+        if clean {
+            return remove_default_symlink(link);
+        }
+        update_default_symlink(link, &self.get_path())?;
+        info!("Switched to {self}");
+        Ok(())
+    }
+
+    /// Switch to the kubeconfig merged from several contexts by `ks merge`, so that
+    /// the tools see all of them at once. This is the first of them, which is the
+    /// current context of the merged file. The merged file is always exported as
+    /// KUBECONFIG, switching to a single context goes back.
+    pub fn switch_merged(&self, path: &Path, count: usize) -> Result<()> {
+        if let Some(link) = self.cfg.kube.symlink.as_ref() {
+            update_default_symlink(Path::new(link), path)?;
+            info!("Switched to {self} merged with {} contexts", count - 1);
+            return Ok(());
+        }
+        self.print_switch(false, None, Some((path, count)));
+        Ok(())
+    }
+
+    /// Check the context before it goes into the merged kubeconfig, and issue the
+    /// vault credential, the same as switching to it.
+    pub fn prepare_merge(&self) -> Result<()> {
+        if !self.current {
+            self.check_protect()?;
+            self.check_tls()?;
+        }
+        if let Some((vault_cfg, vault_ctx)) = vault::lookup(self.cfg, &self.name) {
+            vault::ensure(self.cfg, vault_cfg, vault_ctx)?;
+        }
+        Ok(())
+    }
+
+    fn print_switch(&self, clean: bool, shims: Option<&Path>, merged: Option<(&Path, usize)>) {
+        let protocol = get_wrapper_protocol();
+        if protocol < PROTOCOL_VERSION {
+            warning!(
+                "your shell wrapper is outdated (protocol {protocol}, want {PROTOCOL_VERSION}), some features might not work, please restart your shell to load the new `init` script"
+            );
+        } else if protocol > PROTOCOL_VERSION {
+            warning!(
+                "your shell wrapper is newer than kubeswitch (protocol {protocol}, want {PROTOCOL_VERSION}), please upgrade kubeswitch"
+            );
+        }
+
+        println!("__switch__");
+        println!("{}", self.cfg.kube.cmd);
+
+        // The KUBECONFIG exported by merging is taken back by the later switches.
+        if self.cfg.kube.export_kubeconfig || merged.is_some() || Session::is_kubeconfig_recorded()
+        {
+            println!("1");
+        } else {
+            println!("0");
+        }
+
+        if clean {
+            println!("1");
+            if protocol < 4 {
+                return;
+            }
+            // The lines after the clean flag are the env to restore.
+            match Session::restore() {
+                Ok(lines) => lines.iter().for_each(|line| println!("{line}")),
+                Err(err) => warning!("restore session env: {err:#}"),
+            }
+            return;
+        }
+
+        if let Err(err) = Session::record(self.cfg, merged.is_some()) {
+            warning!("record session env: {err:#}");
+        }
+        println!("0");
+        println!("{}", self.name);
+        println!("{}", self.namespace);
+        let display = match merged {
+            Some((_, count)) => format!("{self} (+{})", count - 1),
+            None => self.to_string(),
+        };
+        println!("{display}");
+
+        // The merged kubeconfig has all the contexts, the current one is used.
+        let pass_context = match merged {
+            Some(_) => None,
+            None => match self.get_pass_context() {
+                Ok(pass_context) => pass_context,
+                Err(err) => {
+                    warning!("get context to pass for '{}': {err:#}", self.name);
+                    None
+                }
+            },
+        };
+        let pass_context = pass_context
+            .map(|name| format!(" --context {}", quote_shell(&name)))
+            .unwrap_or_default();
+
+        // The alias bypasses $PATH, point it to the block shim of GitOps context.
+        let exec_shim = shims
+            .map(|shims| shims.join(get_exec_name(&self.cfg.kube.exec)))
+            .filter(|shim| shim.is_file());
+        match exec_shim {
+            Some(shim) => println!("{}{pass_context}", shim.display()),
+            None => println!("{}{pass_context}", self.cfg.kube.exec),
+        }
+        match merged {
+            Some((path, _)) => println!("{}", path.display()),
+            None => println!("{}", self.get_path().display()),
+        }
+
+        if protocol >= 2 {
+            match shims {
+                Some(shims) => println!("{}", shims.display()),
+                None => println!("-"),
+            }
+        }
+
+        if protocol >= 3 {
+            let hint = self.get_prompt_hint();
+            let color = hint.and_then(|hint| hint.color);
+            println!("{}", color.map(|color| color.name()).unwrap_or("-"));
+            let emoji = hint.and_then(|hint| hint.emoji.as_deref());
+            println!("{}", emoji.unwrap_or("-"));
+        }
+
+        if self.cfg.k9s.is_none() {
+            println!("0");
+            return;
+        }
+
+        println!("1");
+
+        let k9s = self.cfg.k9s.as_ref().unwrap();
+        println!("{}{pass_context}", k9s.exec);
+        println!("{}", k9s.cmd);
+    }
+
+    /// The color and emoji hint to render in shell prompt, see `prompt_hint`.
+    pub fn get_prompt_hint(&self) -> Option<&PromptHint> {
+        match self.cfg.match_prompt_hint(&self.name) {
+            Ok(hint) => hint,
+            Err(err) => {
+                warning!("match prompt hint for '{}': {err:#}", self.name);
+                None
+            }
+        }
+    }
+
+    pub fn get_path(&self) -> PathBuf {
+        get_kubeconfig_path(self.cfg, &self.name)
+    }
+
+    /// The kubeconfig file to pass to kubectl, the encrypted context is decrypted
+    /// if the plaintext is stale.
+    pub fn get_decrypted_path(&self) -> Result<PathBuf> {
+        match encryption::find(self.cfg, &self.name) {
+            Some(encrypted) => encryption::materialize(self.cfg, &self.name, &encrypted),
+            None => Ok(self.get_path()),
+        }
+    }
+
+    /// Decrypt the kubeconfig of the encrypted context before switching to it, or
+    /// running commands and editing with it. Its namespace is unknown until it is
+    /// decrypted, so it is read again after decrypting.
+    pub fn materialize(&mut self) -> Result<()> {
+        let encrypted = match encryption::find(self.cfg, &self.name) {
+            Some(encrypted) => encrypted,
+            None => return Ok(()),
+        };
+        let fresh = encryption::get_fresh_plain_path(self.cfg, &self.name, &encrypted).is_some();
+        let path = encryption::materialize(self.cfg, &self.name, &encrypted)?;
+        // The namespace of the current context is held by the shell.
+        if fresh || self.current {
+            return Ok(());
+        }
+        let info = KubeconfigInfo::parse(self.cfg, &self.name, path)?;
+        self.namespace = info.namespace;
+        self.link = info.link;
+        Ok(())
+    }
+
+    /// Probe if the cluster can be reached with the credential, by a cheap
+    /// `kubectl version` request.
+    pub fn check_health(&self, timeout: u64) -> Health {
+        let path = match self.get_decrypted_path() {
+            Ok(path) => path,
+            Err(err) => return Health::AuthExpired(format!("{err:#}")),
+        };
+        if fs::metadata(&path).is_err() {
+            // The vault credential was not issued or has been purged.
+            return Health::AuthExpired(String::from("credential is not issued"));
+        }
+
+        let mut cmd = Command::new(&self.cfg.kube.exec);
+        cmd.args(["version", &format!("--request-timeout={timeout}s")]);
+        cmd.env("KUBECONFIG", &path);
+        cmd.stdin(Stdio::null());
+        cmd.stdout(Stdio::null());
+        cmd.stderr(Stdio::piped());
+
+        log::debug!(
+            "execute {}, KUBECONFIG='{}'",
+            process::format_command(&cmd),
+            path.display()
+        );
+        let start = Instant::now();
+        let output = match cmd.output() {
+            Ok(output) => output,
+            Err(err) => return Health::Unreachable(format!("execute kubectl: {err}")),
+        };
+        log::debug!(
+            "{} exited with {} in {:.2?}",
+            self.cfg.kube.exec,
+            output.status,
+            start.elapsed()
+        );
+        if output.status.success() {
+            return Health::Ok;
+        }
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let detail = stderr
+            .lines()
+            .rev()
+            .map(|line| line.trim())
+            .find(|line| !line.is_empty())
+            .unwrap_or("unknown error")
+            .to_string();
+        let lower = detail.to_lowercase();
+        if Health::AUTH_ERRORS.iter().any(|err| lower.contains(err)) {
+            return Health::AuthExpired(detail);
+        }
+        Health::Unreachable(detail)
+    }
+
+    /// Get the context name to pass by `--context`, see `kube.pass_context`.
+    fn get_pass_context(&self) -> Result<Option<String>> {
+        if !self.cfg.kube.pass_context || vault::lookup(self.cfg, &self.name).is_some() {
+            return Ok(None);
+        }
+        let kubeconfig = KubeConfig::read(self.get_path())?;
+        Ok(kubeconfig.current_context_in_multiple())
+    }
+
+    /// Get the namespace in the kubeconfig file, regardless of the one switched in
+    /// the shell.
+    pub fn get_kubeconfig_namespace(&self) -> Result<String> {
+        if let Some((_, vault_ctx)) = vault::lookup(self.cfg, &self.name) {
+            return Ok(vault_ctx.namespace.clone());
+        }
+        let namespace = get_kubeconfig_namespace(self.get_path())?;
+        Ok(namespace.into_owned())
+    }
+
+    /// Get the server URL and the identity fingerprint of the cluster of this
+    /// context. The fingerprint is the short SHA256 hash of the server URL and CA
+    /// certificate, so identically-named contexts on different clusters can be
+    /// distinguished.
+    pub fn fingerprint(&self) -> Result<Option<(String, String)>> {
+        use sha2::{Digest, Sha256};
+
+        let path = self.get_path();
+        let kubeconfig = KubeConfig::read(&path)?;
+        let cluster = match kubeconfig.current_cluster() {
+            Some(cluster) => cluster,
+            None => return Ok(None),
+        };
+        let server = match cluster.server.as_ref() {
+            Some(server) => server,
+            None => return Ok(None),
+        };
+
+        let mut hasher = Sha256::new();
+        hasher.update(server.as_bytes());
+        hasher.update(b"\n");
+        if let Some(ca) = cluster.read_ca(&path)? {
+            hasher.update(&ca);
+        }
+        let hash = hasher.finalize();
+
+        let fingerprint = hash[..6]
+            .chunks(2)
+            .map(|chunk| format!("{:02x}{:02x}", chunk[0], chunk[1]))
+            .collect::<Vec<_>>()
+            .join(":");
+        Ok(Some((server.clone(), fingerprint)))
+    }
+
+    /// Read the client and CA certificates (PEM) of the context, to check their
+    /// expiry. The vault contexts are skipped, their credentials are short-lived.
+    pub fn read_certs(&self) -> Result<Vec<Vec<u8>>> {
+        if vault::lookup(self.cfg, &self.name).is_some() {
+            return Ok(Vec::new());
+        }
+        let path = self.get_path();
+        let kubeconfig = KubeConfig::read(&path)?;
+        let mut certs = Vec::new();
+        if let Some((_, Some(user))) = kubeconfig.current_user() {
+            certs.extend(user.read_client_certificate(&path)?);
+        }
+        if let Some(cluster) = kubeconfig.current_cluster() {
+            certs.extend(cluster.read_ca(&path)?);
+        }
+        Ok(certs)
+    }
+
+    /// The details of the context, with `full`, the CA, symlink and recent usage
+    /// are included too. The continuation lines of an item have empty key.
+    pub fn describe(&self, full: bool) -> Vec<(&'static str, String)> {
+        let mut items = vec![
+            ("Name", self.name.clone()),
+            ("Namespace", self.namespace.to_string()),
+        ];
+        if let Some(link) = self.link.as_ref() {
+            items.push(("Link", link.clone()));
+        }
+        let path = self.get_path();
+        items.push(("Path", format!("{}", path.display())));
+
+        if let Some((_, vault_ctx)) = vault::lookup(self.cfg, &self.name) {
+            items.push(("Server", vault_ctx.server.clone()));
+            items.push(("User", String::from("vault issued")));
+        } else {
+            match KubeConfig::read(&path) {
+                Ok(kubeconfig) => {
+                    let ctx = kubeconfig.current_context();
+                    if let Some(cluster) = ctx.and_then(|ctx| ctx.cluster.as_ref()) {
+                        items.push(("Cluster", cluster.clone()));
+                    }
+                    if let Some(cluster) = kubeconfig.current_cluster() {
+                        if let Some(server) = cluster.server.as_ref() {
+                            items.push(("Server", server.clone()));
+                        }
+                        if full {
+                            items.push(("CA", cluster.ca_info()));
+                        }
+                    }
+                    if let Some((name, user)) = kubeconfig.current_user() {
+                        let auth = user.map(|user| user.auth_kind()).unwrap_or("unknown");
+                        items.push(("User", format!("{name} ({auth})")));
+                    }
+                }
+                Err(err) => items.push(("Error", format!("{err:#}"))),
+            }
+        }
+
+        // Checked by `ks daemon`, the cache is optional.
+        if let Some(expire) = CertCache::read()
+            .ok()
+            .and_then(|expires| expires.get(&self.name).copied())
+        {
+            items.push(("Cert", format_expire(expire)));
+        }
+
+        if full {
+            if let Some(symlink) = self.cfg.kube.symlink.as_ref() {
+                if get_default_symlink_name(self.cfg).as_deref() == Some(self.name.as_str()) {
+                    items.push(("Symlink", symlink.clone()));
+                }
+            }
+        }
+
+        if !full {
+            let last_used = history::last_used(self.cfg, std::slice::from_ref(self));
+            let last_used = match last_used.get(&self.name) {
+                Some(timestamp) => history::format_elapsed(*timestamp),
+                None => String::from("never"),
+            };
+            items.push(("Last used", last_used));
+            return items;
+        }
+
+        let mut used = 0;
+        let mut recent = Vec::new();
+        if let Ok(history) = History::open(self.cfg) {
+            for entry in history.flatten() {
+                if entry.name != self.name {
+                    continue;
+                }
+                used += 1;
+                if recent.len() < Self::DESCRIBE_RECENT {
+                    recent.push(format!(
+                        "{} -> {}",
+                        history::format_elapsed(entry.timestamp),
+                        entry.namespace
+                    ));
+                }
+            }
+        }
+        items.push(("Used", format!("{used} times")));
+        for (idx, line) in recent.into_iter().enumerate() {
+            items.push((if idx == 0 { "Recent" } else { "" }, line));
+        }
+        items
+    }
+
+    fn show_fingerprint(&self) {
+        let fingerprint_cfg = match self.cfg.fingerprint.as_ref() {
+            Some(fingerprint_cfg) if fingerprint_cfg.enable => fingerprint_cfg,
+            _ => return,
+        };
+        match self.fingerprint() {
+            Ok(Some((server, fingerprint))) => match fingerprint_cfg.labels.get(&fingerprint) {
+                Some(label) => info!("Cluster: {server} [{fingerprint}] {label}"),
+                None => info!("Cluster: {server} [{fingerprint}]"),
+            },
+            Ok(None) => warning!("no cluster server found for context '{}'", self.name),
+            Err(err) => warning!("get cluster fingerprint for '{}': {err:#}", self.name),
+        }
+    }
+
+    /// Generate shims for the pinned tools and the GitOps guard of this context,
+    /// return the shims dir, which should be prepended to `PATH`. Return `None` if
+    /// no shim is required.
+    fn write_shims(&self) -> Result<Option<PathBuf>> {
+        let versions = self.cfg.match_tools(&self.name);
+        let gitops = self
+            .cfg
+            .match_gitops(&self.name)
+            .filter(|gitops| !gitops.block.is_empty());
+        if versions.is_empty() && gitops.is_none() {
+            return Ok(None);
+        }
+
+        let dir = get_runtime_dir().join("shims").join(&self.name);
+        if dir.exists() {
+            fs::remove_dir_all(&dir)
+                .with_context(|| format!("remove old shims dir '{}'", dir.display()))?;
+        }
+        create_private_dir(&dir)?;
+
+        if let Some(tools) = self.cfg.tools.as_ref() {
+            for (tool, version) in versions {
+                let bin = tools
+                    .path
+                    .replace("{name}", tool)
+                    .replace("{version}", version);
+                if !Path::new(&bin).is_file() {
+                    warning!("{tool} {version} is not installed at '{bin}', skip its shim");
+                    continue;
+                }
+                write_script(
+                    &dir.join(tool),
+                    &format!("#!/bin/sh\nexec {} \"$@\"\n", quote_shell(&bin)),
+                )?;
+            }
+        }
+
+        if let Some(gitops) = gitops {
+            let exec_name = get_exec_name(&self.cfg.kube.exec);
+            let exec = match find_exec_with_shims(&self.cfg.kube.exec, Some(&dir)) {
+                Some(exec) => exec,
+                None => bail!("cannot find '{}' in your system", self.cfg.kube.exec),
+            };
+            // The verb is only known at runtime, quote the message around it.
+            let message = format!(
+                "{}\"$verb\"{}",
+                quote_shell(&format!("{}: '", self.cfg.cmd)),
+                quote_shell(&format!(
+                    "' is blocked for GitOps context '{}', changes via repo {}",
+                    self.name, gitops.repo
+                ))
+            );
+            let script = format!(
+                r#"#!/bin/sh
+verb=""
+skip=0
+for arg in "$@"; do
+	if [ $skip = 1 ]; then
+		skip=0
+		continue
+	fi
+	case "$arg" in
+		--kubeconfig|--namespace|-n|--context|--cluster|--user|--server|-s) skip=1 ;;
+		-*) ;;
+		*) verb="$arg"; break ;;
+	esac
+done
+case "$verb" in
+	{}) printf '%s\n' {message} >&2; exit 1 ;;
+esac
+exec {} "$@"
+"#,
+                gitops.block.join("|"),
+                quote_shell(&exec.display().to_string())
+            );
+            write_script(&dir.join(exec_name), &script)?;
+        }
+
+        Ok(Some(dir))
+    }
+
+    /// Spawn a subshell with `KUBECONFIG` and kubeswitch envs set for this context.
+    /// The parent shell is not touched, exiting the subshell cleans everything.
+    pub fn spawn_shell(&self) -> Result<()> {
+        if env::var_os(Self::SHELL_ENV).is_some() {
+            warning!("you are already in a kubeswitch shell, spawning a nested one");
+        }
+        self.check_protect()?;
+        if let Some((vault_cfg, vault_ctx)) = vault::lookup(self.cfg, &self.name) {
+            vault::ensure(self.cfg, vault_cfg, vault_ctx)?;
+        }
+        History::write(self)?;
+
+        let shell = env::var("SHELL").unwrap_or(String::from("/bin/sh"));
+        let shim_dir = get_runtime_dir().join(format!("shell-{}", std::process::id()));
+        create_private_dir(&shim_dir)?;
+        let result = self.prepare_command(&shell, &shim_dir).and_then(|mut cmd| {
+            cmd.env(Self::SHELL_ENV, "1");
+
+            // The stdout might be captured by the wrap script, so we redirect the
+            // subshell output to stderr, which should be the terminal.
+            cmd.stdin(Stdio::inherit());
+            cmd.stdout(io::stderr());
+            cmd.stderr(Stdio::inherit());
+
+            cmd.status()
+                .with_context(|| format!("spawn shell '{shell}' for context '{}'", self.name))
+        });
+        fs::remove_dir_all(&shim_dir)
+            .with_context(|| format!("remove shim dir '{}'", shim_dir.display()))?;
+        result?;
+        Ok(())
+    }
+
+    /// Run the command against this context, the current shell won't be touched.
+    /// Returns the exit code of the command.
+    pub fn exec(&self, args: &[String], history: bool) -> Result<i32> {
+        if args.is_empty() {
+            bail!("command to execute cannot be empty");
+        }
+        self.check_protect()?;
+        if let Some((vault_cfg, vault_ctx)) = vault::lookup(self.cfg, &self.name) {
+            vault::ensure(self.cfg, vault_cfg, vault_ctx)?;
+        }
+        if history {
+            History::write(self)?;
+        }
+
+        let shim_dir = get_runtime_dir().join(format!("exec-{}", std::process::id()));
+        create_private_dir(&shim_dir)?;
+        let result = self
+            .prepare_command(&args[0], &shim_dir)
+            .and_then(|mut cmd| {
+                cmd.args(&args[1..]);
+                cmd.stdin(Stdio::inherit());
+                cmd.stdout(Stdio::inherit());
+                cmd.stderr(Stdio::inherit());
+
+                cmd.status()
+                    .with_context(|| format!("execute '{}' for context '{}'", args[0], self.name))
+            });
+        fs::remove_dir_all(&shim_dir)
+            .with_context(|| format!("remove shim dir '{}'", shim_dir.display()))?;
+
+        // Killed by signal has no exit code, treat it as a general failure.
+        Ok(result?.code().unwrap_or(1))
+    }
+
+    /// Build the command with the environment of this context: the kubeconfig, the
+    /// namespace shims (written to `shim_dir`) and the pinned tools.
+    fn prepare_command(&self, program: &str, shim_dir: &Path) -> Result<Command> {
+        let ctx_shims = self.write_shims()?;
+        write_namespace_shim(shim_dir, &self.cfg.kube.exec, ctx_shims.as_deref())?;
+        if let Some(k9s) = self.cfg.k9s.as_ref() {
+            write_namespace_shim(shim_dir, &k9s.exec, ctx_shims.as_deref())?;
+        }
+
+        let mut paths = vec![shim_dir.to_path_buf()];
+        if let Some(ctx_shims) = ctx_shims {
+            paths.push(ctx_shims);
+        }
+        if let Some(path) = env::var_os("PATH") {
+            paths.extend(env::split_paths(&path));
+        }
+        let path = env::join_paths(paths).context("join PATH for command")?;
+
+        let mut cmd = Command::new(program);
+        cmd.env("PATH", path);
+        cmd.env("KUBECONFIG", self.get_path());
+        cmd.env(KubeContextBuilder::NAME_ENV, &self.name);
+        cmd.env(KubeContextBuilder::NAMESPACE_ENV, self.namespace.as_ref());
+        cmd.env("KUBESWITCH_DISPLAY", format!("{self}"));
+        if let Some(hint) = self.get_prompt_hint() {
+            if let Some(color) = hint.color {
+                cmd.env("KUBESWITCH_COLOR", color.name());
+            }
+            if let Some(emoji) = hint.emoji.as_ref() {
+                cmd.env("KUBESWITCH_EMOJI", emoji);
+            }
+        }
+        Ok(cmd)
+    }
+
+    pub fn edit(&mut self) -> Result<()> {
+        let raw_content = self.read_for_edit()?;
+        let mut edit_file = EditFile::create(self.cfg, &self.name, &raw_content)?;
+        let edit_path = edit_file.path.clone();
+
+        let edit_content = loop {
+            let mut cmd = get_editor_command(self.cfg, &edit_path);
+            cmd.stdin(Stdio::inherit());
+            cmd.stdout(io::stderr());
+            cmd.stderr(Stdio::inherit());
+
+            cmd.output().with_context(|| {
+                format!(
+                    "run edit command '{} {}'",
+                    self.cfg.editor,
+                    edit_path.display()
+                )
+            })?;
+
+            let edit_content = fs::read(&edit_path).context("read edit file")?;
+            if edit_content.is_empty() {
+                bail!("edit content cannot be empty");
+            }
+            if edit_content == raw_content {
+                bail!("edit content not changed");
+            }
+
+            let err = match self.verify_edit(&edit_path) {
+                Ok(()) => break edit_content,
+                Err(err) => err,
+            };
+            eprintln!("The edited kubeconfig is invalid: {err:#}");
+            // Never loop on the editor without a human.
+            if ASSUME_YES.load(Ordering::Relaxed) || !confirm("Re-open the editor to fix it")? {
+                edit_file.keep = true;
+                bail!(
+                    "invalid kubeconfig, the edited content is kept in '{}'",
+                    edit_path.display()
+                );
+            }
+        };
+
+        self.save_edit(&raw_content, &edit_content, edit_file)
+    }
+
+    /// Replace the kubeconfig content without the editor, such as from a file or
+    /// stdin in scripts. It goes through the same validation, diff and backup as
+    /// [`KubeContext::edit`].
+    pub fn replace(&mut self, content: &[u8]) -> Result<()> {
+        let raw_content = self.read_for_edit()?;
+        if content.is_empty() {
+            bail!("new content cannot be empty");
+        }
+        if content == raw_content {
+            info!("The content of {} is not changed", self.name);
+            return Ok(());
+        }
+
+        let edit_file = EditFile::create(self.cfg, &self.name, content)?;
+        self.verify_edit(&edit_file.path)
+            .context("the new kubeconfig is invalid")?;
+        self.save_edit(&raw_content, content, edit_file)
+    }
+
+    fn read_for_edit(&self) -> Result<Vec<u8>> {
+        if vault::lookup(self.cfg, &self.name).is_some() {
+            bail!("cannot edit vault context '{}'", self.name);
+        }
+
+        let path = self.get_decrypted_path()?;
+        match fs::read(&path) {
+            Ok(data) => Ok(data),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(err) => {
+                Err(err).with_context(|| format!("read kubeconfig file '{}'", path.display()))
+            }
+        }
+    }
+
+    /// Show the diff for confirmation, then write the verified content in
+    /// `edit_file` to the kubeconfig, the old one is backed up.
+    fn save_edit(
+        &mut self,
+        raw_content: &[u8],
+        edit_content: &[u8],
+        mut edit_file: EditFile,
+    ) -> Result<()> {
+        let edit_path = edit_file.path.clone();
+        self.namespace =
+            get_kubeconfig_namespace(&edit_path).context("get namespace from edited kubeconfig")?;
+        if self.cfg.confirm_edit && !raw_content.is_empty() {
+            let diff = diff::unified(
+                &self.name,
+                &String::from_utf8_lossy(raw_content),
+                &String::from_utf8_lossy(edit_content),
+                io::stderr().is_terminal(),
+            );
+            eprint!("{diff}");
+            if !confirm(format!("Save the changes to {}", self.name))? {
+                edit_file.keep = true;
+                bail!(
+                    "user aborted, the edited content is kept in '{}'",
+                    edit_path.display()
+                );
+            }
+        }
+
+        // Editing a context in the shared dir makes a copy in the user layer.
+        if let Some(encrypted) = encryption::find(self.cfg, &self.name) {
+            backup::save(self.cfg, &self.name, &encrypted)?;
+            let path = encryption::get_write_path(self.cfg, &self.name);
+            return encryption::write(self.cfg, &path, edit_content);
+        }
+        let path = get_kubeconfig_write_path(self.cfg, &self.name);
+        ensure_dir(&path)?;
+        backup::save(self.cfg, &self.name, &path)?;
+        fs::write(&path, edit_content).context("write edit content to kubeconfig")?;
+
+        Ok(())
+    }
+
+    fn verify_edit(&self, edit_path: &Path) -> Result<()> {
+        KubeConfig::read(edit_path)?.validate()?;
+        if self.cfg.verify_edit {
+            execute_kubectl(self.cfg, edit_path, ["config", "view"])?;
+        }
+        Ok(())
+    }
+
+    pub fn delete(self) -> Result<()> {
+        self.check_delete()?;
+
+        let confirm_msg = format!("Do you want to delete {}", self.name);
+        if !confirm(confirm_msg)? {
+            bail!("user aborted");
+        }
+
+        if self.delete_inner()? && self.current {
+            self.switch_inner(true, None)?;
+        }
+        Ok(())
+    }
+
+    /// Delete the contexts after one confirmation, such as the ones under a dir or
+    /// matching a glob pattern. The vault contexts and the ones in the shared dir
+    /// are skipped.
+    pub fn delete_many(ctxs: Vec<KubeContext>) -> Result<()> {
+        let ctxs: Vec<_> = ctxs
+            .into_iter()
+            .filter(|ctx| match ctx.check_delete() {
+                Ok(()) => true,
+                Err(err) => {
+                    warning!("skip: {err:#}");
+                    false
+                }
+            })
+            .collect();
+        if ctxs.is_empty() {
+            bail!("no context to delete");
+        }
+
+        for ctx in ctxs.iter() {
+            eprintln!("  {}", ctx.name);
+        }
+        if !confirm(format!(
+            "Do you want to delete these {} contexts",
+            ctxs.len()
+        ))? {
+            bail!("user aborted");
+        }
+
+        let mut current = None;
+        for ctx in ctxs.iter() {
+            if ctx.delete_inner()? && ctx.current {
+                current = Some(ctx);
+            }
+        }
+        // Unset the shell state after all, the switch protocol is printed once.
+        if let Some(ctx) = current {
+            ctx.switch_inner(true, None)?;
+        }
+        info!("Deleted {} contexts", ctxs.len());
+        Ok(())
+    }
+
+    fn check_delete(&self) -> Result<()> {
+        if vault::lookup(self.cfg, &self.name).is_some() {
+            bail!(
+                "cannot delete vault context '{}', please remove it from config",
+                self.name
+            );
+        }
+        if !self.is_in_write_dir() {
+            bail!(
+                "context '{}' is in the shared dir, cannot delete it",
+                self.name
+            );
+        }
+        Ok(())
+    }
+
+    /// Check if the kubeconfig file is in the write dir, rather than the shared one.
+    pub fn is_in_write_dir(&self) -> bool {
+        match encryption::find(self.cfg, &self.name) {
+            Some(encrypted) => encrypted == encryption::get_write_path(self.cfg, &self.name),
+            None => self.get_path() == get_kubeconfig_write_path(self.cfg, &self.name),
+        }
+    }
+
+    /// Remove the kubeconfig file, and the dirs left empty. Return false if the
+    /// context is still there, using the shared kubeconfig.
+    fn delete_inner(&self) -> Result<bool> {
+        let path = get_kubeconfig_store_path(self.cfg, &self.name);
+        if encryption::find(self.cfg, &self.name).is_some() {
+            encryption::remove_plain(self.cfg, &self.name)?;
+        }
+        fs::remove_file(&path)
+            .with_context(|| format!("remove the kubeconfig file '{}'", path.display()))?;
+        remove_empty_dirs(self.cfg, &path)?;
+
+        // The context in the shared dir is revealed after removing the user one.
+        let shared_path = get_kubeconfig_store_path(self.cfg, &self.name);
+        if fs::symlink_metadata(&shared_path).is_ok() {
+            info!("Context '{}' now uses the shared kubeconfig", self.name);
+            return Ok(false);
+        }
+
+        Tombstone::write(&self.name, None)?;
+        let mut tags = Tags::load()?;
+        tags.rename(&self.name, None);
+        tags.save()?;
+        Ok(true)
+    }
+
+    pub fn rename(self, new_name: &str) -> Result<()> {
+        if vault::lookup(self.cfg, &self.name).is_some() {
+            bail!(
+                "cannot rename vault context '{}', please change it in config",
+                self.name
+            );
+        }
+
+        if !self.is_in_write_dir() {
+            bail!(
+                "context '{}' is in the shared dir, cannot rename it",
+                self.name
+            );
+        }
+        let mut path = self.get_path();
+        let mut new_path = self.check_new_context(new_name)?;
+        if let Some(encrypted) = encryption::find(self.cfg, &self.name) {
+            encryption::remove_plain(self.cfg, &self.name)?;
+            path = encrypted;
+            new_path = encryption::get_write_path(self.cfg, new_name);
+        }
+
+        ensure_dir(&new_path)?;
+        match self.link.as_ref() {
+            // The relative symlink would be broken if it is moved to another dir.
+            Some(link) => {
+                create_symlink(self.cfg, link, new_name)?;
+                fs::remove_file(&path)
+                    .with_context(|| format!("remove the symlink '{}'", path.display()))?;
+            }
+            None => fs::rename(&path, &new_path).with_context(|| {
+                format!("rename '{}' to '{}'", path.display(), new_path.display())
+            })?,
+        }
+
+        self.relink(new_name)?;
+        History::rename(self.cfg, &self.name, new_name)?;
+        backup::rename(self.cfg, &self.name, new_name)?;
+        let mut tags = Tags::load()?;
+        tags.rename(&self.name, Some(new_name));
+        tags.save()?;
+        Tombstone::write(&self.name, Some(new_name))?;
+        info!("Renamed context '{}' to '{new_name}'", self.name);
+
+        if self.current {
+            // Keep the shell env consistent with the new name.
+            let ctx = KubeContext {
+                name: new_name.to_string(),
+                ..self
+            };
+            let shims = ctx.write_shims()?;
+            ctx.switch_inner(false, shims.as_deref())?;
+        }
+        Ok(())
+    }
+
+    pub fn copy(&self, dest: &str) -> Result<()> {
+        if vault::lookup(self.cfg, &self.name).is_some() {
+            bail!("cannot copy vault context '{}'", self.name);
+        }
+        let mut dest_path = self.check_new_context(dest)?;
+        let path = match encryption::find(self.cfg, &self.name) {
+            // Keep the copy encrypted too.
+            Some(encrypted) => {
+                dest_path = encryption::get_write_path(self.cfg, dest);
+                encrypted
+            }
+            None => self.get_path(),
+        };
+
+        ensure_dir(&dest_path)?;
+        fs::copy(&path, &dest_path)
+            .with_context(|| format!("copy '{}' to '{}'", path.display(), dest_path.display()))?;
+        info!("Copied context '{}' to '{dest}'", self.name);
+        Ok(())
+    }
+
+    /// Check the new context name is not in use, returns the path to write it.
+    fn check_new_context(&self, name: &str) -> Result<PathBuf> {
+        if name == self.name
+            || vault::lookup(self.cfg, name).is_some()
+            || fs::symlink_metadata(get_kubeconfig_store_path(self.cfg, name)).is_ok()
+        {
+            bail!("context '{name}' already exists");
+        }
+        Ok(get_kubeconfig_write_path(self.cfg, name))
+    }
+
+    /// Point the symlink contexts of this context to the new name. Only the ones
+    /// in the write dir can be fixed.
+    fn relink(&self, new_name: &str) -> Result<()> {
+        let mut links = Vec::new();
+        walk_kubeconfigs(self.cfg, "", |name, path| {
+            if get_kubeconfig_link(self.cfg, &path)?.as_deref() == Some(self.name.as_str()) {
+                links.push((name.to_string(), path));
+            }
+            Ok(())
+        })?;
+
+        for (name, path) in links {
+            if path != get_kubeconfig_write_path(self.cfg, &name) {
+                warning!("cannot fix the symlink context '{name}' in the shared dir");
+                continue;
+            }
+            fs::remove_file(&path)
+                .with_context(|| format!("remove the symlink '{}'", path.display()))?;
+            create_symlink(self.cfg, new_name, &name)?;
+        }
+        Ok(())
+    }
+
+    pub fn list_namespaces(&self) -> Result<Vec<Cow<'_, str>>> {
+        let mut namespaces = match self.cfg.match_ns_alias(&self.name) {
+            Some(alias) => alias,
+            None if self.cfg.offline => self.list_namespace_from_cache()?,
+            None => match self.read_fresh_namespace_cache() {
+                Some(namespaces) => namespaces,
+                None => {
+                    let wait = Duration::from_millis(self.cfg.namespace.refresh_wait_ms);
+                    self.list_namespace_from_command(Some(wait))?
+                }
+            },
+        };
+        self.cfg.sort_namespaces(&self.name, &mut namespaces);
+        Ok(namespaces)
+    }
+
+    /// The same as [`Self::list_namespaces`], but serves the cached namespaces of any
+    /// age, for completion which should not wait for the cluster.
+    pub fn list_namespaces_cached(&self) -> Result<Vec<Cow<'_, str>>> {
+        if self.cfg.match_ns_alias(&self.name).is_none() {
+            if let Ok(Some(cache)) = NamespaceCache::read(&self.name) {
+                let mut namespaces: Vec<_> = cache.namespaces.into_iter().map(Cow::Owned).collect();
+                self.cfg.sort_namespaces(&self.name, &mut namespaces);
+                return Ok(namespaces);
+            }
+        }
+        self.list_namespaces()
+    }
+
+    /// Read the cached namespaces younger than `namespace.cache_ttl`.
+    fn read_fresh_namespace_cache(&self) -> Option<Vec<Cow<'_, str>>> {
+        if self.cfg.namespace.cache_ttl == 0 {
+            return None;
+        }
+        let cache = NamespaceCache::read(&self.name).ok()??;
+        if history::now().saturating_sub(cache.timestamp) > self.cfg.namespace.cache_ttl {
+            return None;
+        }
+        Some(cache.namespaces.into_iter().map(Cow::Owned).collect())
+    }
+
+    fn list_namespace_from_cache(&self) -> Result<Vec<Cow<'_, str>>> {
+        let cache = match NamespaceCache::read(&self.name)? {
+            Some(cache) => cache,
+            None => bail!(
+                "no cached namespaces for context '{}' in offline mode",
+                self.name
+            ),
+        };
+        warning!(
+            "offline mode, using cached namespaces of '{}' from {}, they may be stale",
+            self.name,
+            history::format_elapsed(cache.timestamp)
+        );
+        Ok(cache.namespaces.into_iter().map(Cow::Owned).collect())
+    }
+
+    /// List the namespaces from the cluster, or take the result of another process
+    /// doing the same, see [`NamespaceCache::begin_refresh`].
+    fn list_namespace_from_command(&self, wait: Option<Duration>) -> Result<Vec<Cow<'_, str>>> {
+        let _lock = match NamespaceCache::begin_refresh(&self.name, wait)? {
+            Refresh::Owner(lock) => lock,
+            Refresh::Fresh(cache) => {
+                return Ok(cache.namespaces.into_iter().map(Cow::Owned).collect());
+            }
+            Refresh::Stale(cache) => {
+                warning!(
+                    "namespaces of '{}' are being refreshed by another process, using the cache from {}",
+                    self.name,
+                    history::format_elapsed(cache.timestamp)
+                );
+                return Ok(cache.namespaces.into_iter().map(Cow::Owned).collect());
+            }
+        };
+
+        if let Some((vault_cfg, vault_ctx)) = vault::lookup(self.cfg, &self.name) {
+            vault::ensure(self.cfg, vault_cfg, vault_ctx)?;
+        }
+
+        let mut args = vec![
+            "get",
+            "namespaces",
+            "-o",
+            "custom-columns=NAME:.metadata.name",
+            "--no-headers",
+        ];
+        args.extend(
+            self.cfg
+                .kube
+                .namespace_list_args
+                .iter()
+                .map(|arg| arg.as_str()),
+        );
+        let namespaces = execute_kubectl_lines(self.cfg, self.get_decrypted_path()?, args)?;
+        if let Err(err) = NamespaceCache::write(&self.name, &namespaces) {
+            warning!("write namespace cache for '{}': {err:#}", self.name);
+        }
+
+        Ok(namespaces.into_iter().map(Cow::Owned).collect())
+    }
+
+    /// Check if the namespace exists in the cluster, and create it if missing,
+    /// according to `kube.create_namespace`.
+    pub fn ensure_namespace(&self, namespace: &str) -> Result<()> {
+        let mode = self.cfg.kube.create_namespace;
+        if mode == CreateNamespace::Never {
+            return Ok(());
+        }
+        if self.cfg.offline {
+            warning!("offline mode, skip checking namespace '{namespace}'");
+            return Ok(());
+        }
+
+        // The alias might not be the real namespaces, and the cache might be stale,
+        // always ask the cluster.
+        if self.namespace_exists(namespace)? {
+            return Ok(());
+        }
+
+        if mode == CreateNamespace::Prompt {
+            eprintln!("Namespace '{namespace}' does not exist in '{}'", self.name);
+            if !confirm("Create it")? {
+                bail!("user aborted");
+            }
+        }
+        execute_kubectl(
+            self.cfg,
+            self.get_decrypted_path()?,
+            ["create", "namespace", namespace],
+        )?;
+        info!("Created namespace '{namespace}'");
+
+        let cache = match NamespaceCache::read(&self.name) {
+            Ok(cache) => cache,
+            Err(err) => {
+                warning!("read namespace cache for '{}': {err:#}", self.name);
+                None
+            }
+        };
+        if let Some(mut cache) = cache {
+            cache.namespaces.push(namespace.to_string());
+            if let Err(err) = NamespaceCache::write(&self.name, &cache.namespaces) {
+                warning!("write namespace cache for '{}': {err:#}", self.name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the namespace by name rather than listing, so that it works with the
+    /// users only allowed to get their own namespaces.
+    fn namespace_exists(&self, namespace: &str) -> Result<bool> {
+        if let Some((vault_cfg, vault_ctx)) = vault::lookup(self.cfg, &self.name) {
+            vault::ensure(self.cfg, vault_cfg, vault_ctx)?;
+        }
+        let args = [
+            "get",
+            "namespace",
+            namespace,
+            "-o",
+            "name",
+            "--ignore-not-found",
+        ];
+        let output = execute_kubectl(self.cfg, self.get_decrypted_path()?, args)?;
+        Ok(!output.trim().is_empty())
+    }
+
+    pub fn select_namespace(&self, namespace: &Option<String>) -> Result<String> {
+        if let Some(namespace) = namespace.as_ref() {
+            if namespace == "-" {
+                return self.select_namespace_history();
+            }
+
+            return Ok(namespace.clone());
         }
 
         let mut namespaces: Vec<_> = self
@@ -862,26 +3169,47 @@ impl KubeContext<'_> {
             .into_iter()
             .filter(|ns| ns != self.namespace.as_ref())
             .collect();
-        if namespaces.is_empty() {
-            bail!("no namespace to select");
+
+        let mut query = String::new();
+        loop {
+            let hint = "Select a namespace or type a new one";
+            match selector::select_or_input(&namespaces, hint, &query)? {
+                Selection::Index(idx) => return Ok(namespaces.remove(idx).into_owned()),
+                Selection::Input(namespace) => match validate_namespace_name(&namespace) {
+                    Ok(()) => return Ok(namespace),
+                    Err(err) => {
+                        // Let the user fix the typed name.
+                        warning!("{err:#}");
+                        query = namespace;
+                    }
+                },
+            }
         }
+    }
 
-        let idx = search_fzf(&namespaces)?;
+    /// Select from all the namespaces (including the current one), without the
+    /// option to type a new one.
+    pub fn pick_namespace(&self) -> Result<String> {
+        let mut namespaces = self.list_namespaces()?;
+        if namespaces.is_empty() {
+            bail!("no namespace to select for context '{}'", self.name);
+        }
+        let idx = selector::select(&namespaces)?;
         Ok(namespaces.remove(idx).into_owned())
     }
 
     pub fn select_namespace_history(&self) -> Result<String> {
-        let history = History::open()?;
+        let history = History::open(self.cfg)?;
 
-        for item in history {
-            let (name, namespace) = item?;
-            if name != self.name {
+        for entry in history {
+            let entry = entry?;
+            if entry.name != self.name {
                 continue;
             }
-            if namespace == self.namespace {
+            if entry.namespace == self.namespace {
                 continue;
             }
-            return Ok(namespace);
+            return Ok(entry.namespace);
         }
 
         bail!("no namespace history to select");
@@ -890,17 +3218,210 @@ impl KubeContext<'_> {
     pub fn set_namespace(&mut self, namespace: String) -> Result<()> {
         self.namespace = Cow::Owned(namespace);
 
-        if !self.cfg.kube.update_context {
+        // In symlink mode, there is no shell env to hold the namespace.
+        if !self.cfg.kube.update_context && self.cfg.kube.symlink.is_none() {
             return Ok(());
         }
 
+        backup::save(
+            self.cfg,
+            &self.name,
+            &get_kubeconfig_store_path(self.cfg, &self.name),
+        )?;
+        let encrypted = encryption::find(self.cfg, &self.name).is_some();
+        let path = if encrypted {
+            self.get_decrypted_path()?
+        } else {
+            // Never modify the shared kubeconfig, make a copy in the user layer.
+            let shared = self.get_path();
+            let path = get_kubeconfig_write_path(self.cfg, &self.name);
+            if path != shared {
+                ensure_dir(&path)?;
+                fs::copy(&shared, &path).with_context(|| {
+                    format!(
+                        "copy kubeconfig '{}' to '{}'",
+                        shared.display(),
+                        path.display()
+                    )
+                })?;
+            }
+            path
+        };
         let set = format!("--namespace={}", self.namespace);
         execute_kubectl(
             self.cfg,
-            self.get_path(),
+            path,
             ["config", "set-context", "--current", set.as_str()],
         )?;
+        if encrypted {
+            let path = encryption::get_write_path(self.cfg, &self.name);
+            encryption::save(self.cfg, &self.name, &path)?;
+        }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KUBECONFIG: &str = r#"apiVersion: v1
+kind: Config
+preferences: {}
+clusters:
+- name: a
+  cluster:
+    server: https://127.0.0.1:6443
+users:
+- name: a
+  user:
+    exec:
+      command: login
+      env:
+      - name: namespace
+        value: wrong
+      namespace: wrong
+contexts:
+- name: a
+  context:
+    cluster: a
+    user: a
+    namespace: 'team-a'
+- name: "b c"
+  context:
+    cluster: a
+    user: a
+    namespace: "team-b"
+current-context: "b c"
+extensions:
+- name: a
+  extension:
+    namespace: wrong
+"#;
+
+    /// Write the kubeconfig to a temporary file, and the config to parse it lazily
+    /// when it is larger than `lazy_parse_size`.
+    fn write_kubeconfig(name: &str, data: &str, lazy_parse_size: u64) -> (Config, PathBuf) {
+        let path = env::temp_dir().join(format!(
+            "kubeswitch-test-{name}-{}.yaml",
+            std::process::id()
+        ));
+        fs::write(&path, data).unwrap();
+        let config = format!("[kube]\nlazy_parse_size = {lazy_parse_size}\n");
+        (toml::from_str(&config).unwrap(), path)
+    }
+
+    #[test]
+    fn test_cut_head() {
+        let head = KubeConfig::cut_head(KUBECONFIG).unwrap();
+        assert_eq!(
+            head,
+            r#"contexts:
+- name: a
+  context:
+    cluster: a
+    user: a
+    namespace: 'team-a'
+- name: "b c"
+  context:
+    cluster: a
+    user: a
+    namespace: "team-b"
+current-context: "b c"
+"#
+        );
+
+        let kubeconfig: KubeConfig = serde_yaml::from_str(&head).unwrap();
+        assert_eq!(kubeconfig.contexts.as_ref().unwrap().len(), 2);
+        assert_eq!(
+            kubeconfig.current_context_in_multiple().as_deref(),
+            Some("b c")
+        );
+        let kubeconfig: KubeConfig = serde_yaml::from_str(&head).unwrap();
+        assert_eq!(kubeconfig.current_namespace().as_deref(), Some("team-b"));
+    }
+
+    #[test]
+    fn test_cut_head_missing() {
+        let data = KUBECONFIG.replace("current-context: \"b c\"\n", "");
+        assert!(KubeConfig::cut_head(&data).is_none());
+
+        let data = KUBECONFIG.replace("contexts:", "others:");
+        assert!(KubeConfig::cut_head(&data).is_none());
+    }
+
+    #[test]
+    fn test_read_lazy() {
+        for lazy_parse_size in [0, 1, 1 << 20] {
+            let (cfg, path) = write_kubeconfig("lazy", KUBECONFIG, lazy_parse_size);
+            let kubeconfig = KubeConfig::read_lazy(&cfg, &path).unwrap();
+            fs::remove_file(&path).unwrap();
+            // Only the head is parsed when the file is larger than the limit.
+            assert_eq!(kubeconfig.users.is_none(), lazy_parse_size == 1);
+            assert_eq!(kubeconfig.current_namespace().as_deref(), Some("team-b"));
+        }
+    }
+
+    #[test]
+    fn test_read_lazy_fallback() {
+        // The head refers to the anchor out of it, it cannot be parsed alone.
+        let data = KUBECONFIG
+            .replace("  user:\n    exec:", "  user: &user\n    exec:")
+            .replace(
+                "    namespace: 'team-a'\n",
+                "    namespace: 'team-a'\n  extra: *user\n",
+            );
+        let head = KubeConfig::cut_head(&data).unwrap();
+        assert!(serde_yaml::from_str::<KubeConfig>(&head).is_err());
+
+        let (cfg, path) = write_kubeconfig("fallback", &data, 1);
+        let kubeconfig = KubeConfig::read_lazy(&cfg, &path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(kubeconfig.users.is_some());
+        assert_eq!(kubeconfig.current_namespace().as_deref(), Some("team-b"));
+    }
+
+    fn new_contexts<'a>(cfg: &'a Config, names: &[&str]) -> Vec<KubeContext<'a>> {
+        names
+            .iter()
+            .map(|name| KubeContext {
+                name: name.to_string(),
+                namespace: Cow::Borrowed("default"),
+                cfg,
+                current: false,
+                link: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert!(fuzzy_match("prod-eu", "prd"));
+        assert!(fuzzy_match("prod-eu", "peu"));
+        assert!(fuzzy_match("prod-eu", ""));
+        assert!(!fuzzy_match("prod-eu", "dpr"));
+        assert!(!fuzzy_match("prod-eu", "prod-eu-1"));
+    }
+
+    #[test]
+    fn test_find_matches() {
+        let cfg: Config = toml::from_str("").unwrap();
+        let ctxs = new_contexts(&cfg, &["prod-eu", "Prod-US", "team/dev", "pre-rd"]);
+
+        // The substring matches beat the fuzzy ones, ignoring case.
+        assert_eq!(KubeContext::find_matches(&ctxs, "prod"), vec![0, 1]);
+        assert_eq!(KubeContext::find_matches(&ctxs, "PROD-us"), vec![1]);
+        assert_eq!(KubeContext::find_matches(&ctxs, "dev"), vec![2]);
+        // Only "pre-rd" contains "rd", the fuzzy matches of the others are ignored.
+        assert_eq!(KubeContext::find_matches(&ctxs, "rd"), vec![3]);
+
+        // No substring matches, fall back to fuzzy.
+        assert_eq!(KubeContext::find_matches(&ctxs, "prd"), vec![0, 1, 3]);
+        assert_eq!(KubeContext::find_matches(&ctxs, "PUS"), vec![1]);
+        assert_eq!(KubeContext::find_matches(&ctxs, "tmdv"), vec![2]);
+
+        assert!(KubeContext::find_matches(&ctxs, "staging").is_empty());
+        assert!(KubeContext::find_matches(&[], "prod").is_empty());
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use chrono::NaiveDateTime;
+
+use crate::cache::CertCache;
+use crate::config::{Config, ImportConflict};
+use crate::context::{self, KubeContext};
+use crate::history::now;
+use crate::import::{self, ImportOptions};
+
+#[derive(Debug, Clone, Copy)]
+enum Task {
+    Discover,
+    Namespaces,
+    Certs,
+}
+
+/// Run the background tasks periodically until killed, it runs in foreground and
+/// logs to stderr, so it can be managed by a systemd user service. The results
+/// are written to the caches, for the later commands to read instantly.
+pub fn run(cfg: &Config) -> Result<()> {
+    let daemon = &cfg.daemon;
+    let mut tasks: Vec<(Task, u64, u64)> = [
+        (Task::Discover, daemon.discover_interval),
+        (Task::Namespaces, daemon.namespaces_interval),
+        (Task::Certs, daemon.certs_interval),
+    ]
+    .into_iter()
+    .filter(|(task, interval)| {
+        *interval > 0 && !(matches!(task, Task::Discover) && daemon.discover.is_empty())
+    })
+    // The first runs are spread over the jitter too.
+    .map(|(task, interval)| {
+        let delay = interval as f64 * daemon.jitter * random();
+        (task, interval, now() + delay as u64)
+    })
+    .collect();
+    if tasks.is_empty() {
+        bail!("no daemon task is enabled, please check `daemon` config");
+    }
+    if cfg.offline {
+        bail!("cannot run daemon in offline mode");
+    }
+    // Nobody is there to answer.
+    context::set_assume_yes(true);
+    context::set_no_interactive(true);
+
+    loop {
+        let (task, interval, next) = tasks.iter_mut().min_by_key(|(_, _, next)| *next).unwrap();
+        let wait = next.saturating_sub(now());
+        if wait > 0 {
+            thread::sleep(Duration::from_secs(wait));
+        }
+
+        info!("[daemon] run {task:?}");
+        let result = match task {
+            Task::Discover => refresh_discovered(cfg),
+            Task::Namespaces => warm_namespaces(cfg),
+            Task::Certs => check_certs(cfg),
+        };
+        if let Err(err) = result {
+            warning!("[daemon] {task:?}: {err:#}");
+        }
+        *next = now() + jitter(*interval, daemon.jitter);
+    }
+}
+
+/// Import the providers again, the changed kubeconfigs are overwritten.
+fn refresh_discovered(cfg: &Config) -> Result<()> {
+    let opts = ImportOptions {
+        prefix: None,
+        conflict: ImportConflict::Overwrite,
+        dry_run: false,
+        interactive: false,
+    };
+    for provider in cfg.daemon.discover.iter() {
+        if let Err(err) = import::import_discovered(cfg, provider, &opts) {
+            warning!("[daemon] refresh {provider}: {err:#}");
+        }
+    }
+    Ok(())
+}
+
+/// List the namespaces of every context one by one, which writes the caches.
+fn warm_namespaces(cfg: &Config) -> Result<()> {
+    let delay = Duration::from_millis(cfg.daemon.delay_ms);
+    for ctx in KubeContext::list(cfg)? {
+        if let Err(err) = ctx.list_namespaces() {
+            warning!("[daemon] list namespaces of '{}': {err:#}", ctx.name);
+        }
+        thread::sleep(delay);
+    }
+    Ok(())
+}
+
+fn check_certs(cfg: &Config) -> Result<()> {
+    let mut expires = BTreeMap::new();
+    for ctx in KubeContext::list(cfg)? {
+        let certs = match ctx.read_certs() {
+            Ok(certs) => certs,
+            Err(err) => {
+                warning!("[daemon] read certificates of '{}': {err:#}", ctx.name);
+                continue;
+            }
+        };
+        let mut earliest = None;
+        for cert in certs {
+            match read_cert_expire(cfg, &cert) {
+                Ok(expire) => earliest = Some(earliest.map_or(expire, |e: u64| e.min(expire))),
+                Err(err) => warning!("[daemon] check certificate of '{}': {err:#}", ctx.name),
+            }
+        }
+        if let Some(expire) = earliest {
+            expires.insert(ctx.name, expire);
+        }
+    }
+    CertCache::write(&expires)
+}
+
+/// Read the `notAfter` of the PEM certificate with openssl.
+fn read_cert_expire(cfg: &Config, cert: &[u8]) -> Result<u64> {
+    let openssl = &cfg.daemon.openssl;
+    let mut child = Command::new(openssl)
+        .args(["x509", "-noout", "-enddate"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("execute {openssl}"))?;
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(cert)
+        .with_context(|| format!("write certificate to {openssl}"))?;
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("wait {openssl}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("{openssl} exited with {}: {}", output.status, stderr.trim());
+    }
+
+    // Such as "notAfter=Jun  1 12:00:00 2025 GMT".
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let date = match stdout.trim().strip_prefix("notAfter=") {
+        Some(date) => date.split_whitespace().collect::<Vec<_>>().join(" "),
+        None => bail!("unexpected output of {openssl}: '{}'", stdout.trim()),
+    };
+    let date = NaiveDateTime::parse_from_str(&date, "%b %d %H:%M:%S %Y GMT")
+        .with_context(|| format!("parse certificate end date '{date}'"))?;
+    Ok(date.and_utc().timestamp().max(0) as u64)
+}
+
+/// Randomly shorten or lengthen the interval by the jitter fraction.
+fn jitter(interval: u64, jitter: f64) -> u64 {
+    let delta = interval as f64 * jitter * (random() * 2.0 - 1.0);
+    (interval as f64 + delta).max(1.0) as u64
+}
+
+/// A random number in [0, 1], the std hasher is randomly seeded.
+fn random() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    random as f64 / u64::MAX as f64
+}
@@ -0,0 +1,120 @@
+use std::ops::Range;
+
+/// The lines of context around the changes in a hunk.
+const CONTEXT: usize = 3;
+
+enum Op<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+impl Op<'_> {
+    fn in_old(&self) -> bool {
+        !matches!(self, Self::Insert(_))
+    }
+
+    fn in_new(&self) -> bool {
+        !matches!(self, Self::Delete(_))
+    }
+}
+
+/// Render the unified diff between the old and new text, empty if they have the
+/// same lines. The changed lines are colored with ANSI codes if `color` is true.
+pub fn unified(name: &str, old: &str, new: &str, color: bool) -> String {
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+    let ops = diff_lines(&old, &new);
+
+    let hunks = get_hunks(&ops);
+    if hunks.is_empty() {
+        return String::new();
+    }
+
+    let paint = |code: &str, line: String| -> String {
+        if color {
+            format!("\x1b[{code}m{line}\x1b[0m\n")
+        } else {
+            format!("{line}\n")
+        }
+    };
+
+    let mut out = paint("1", format!("--- a/{name}"));
+    out.push_str(&paint("1", format!("+++ b/{name}")));
+    for hunk in hunks {
+        let old_start = ops[..hunk.start].iter().filter(|op| op.in_old()).count();
+        let new_start = ops[..hunk.start].iter().filter(|op| op.in_new()).count();
+        let old_len = ops[hunk.clone()].iter().filter(|op| op.in_old()).count();
+        let new_len = ops[hunk.clone()].iter().filter(|op| op.in_new()).count();
+        out.push_str(&paint(
+            "36",
+            format!(
+                "@@ -{},{old_len} +{},{new_len} @@",
+                old_start + 1,
+                new_start + 1
+            ),
+        ));
+
+        for op in ops[hunk].iter() {
+            match op {
+                Op::Equal(line) => out.push_str(&format!(" {line}\n")),
+                Op::Delete(line) => out.push_str(&paint("31", format!("-{line}"))),
+                Op::Insert(line) => out.push_str(&paint("32", format!("+{line}"))),
+            }
+        }
+    }
+    out
+}
+
+/// Diff the lines by the longest common subsequence, the kubeconfig files are
+/// small enough for the quadratic table.
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Op<'a>> {
+    let (n, m) = (old.len(), new.len());
+    // lcs[i][j] is the LCS length of old[i..] and new[j..].
+    let mut lcs = vec![vec![0_u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::with_capacity(n.max(m));
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old[i] == new[j] {
+            ops.push(Op::Equal(old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            ops.push(Op::Delete(old[i]));
+            i += 1;
+        } else {
+            ops.push(Op::Insert(new[j]));
+            j += 1;
+        }
+    }
+    ops.extend(old[i..].iter().map(|line| Op::Delete(line)));
+    ops.extend(new[j..].iter().map(|line| Op::Insert(line)));
+    ops
+}
+
+/// Group the changes into hunks, the nearby changes share one hunk.
+fn get_hunks(ops: &[Op]) -> Vec<Range<usize>> {
+    let mut hunks: Vec<Range<usize>> = Vec::new();
+    for (idx, op) in ops.iter().enumerate() {
+        if let Op::Equal(_) = op {
+            continue;
+        }
+        let start = idx.saturating_sub(CONTEXT);
+        let end = (idx + CONTEXT + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if last.end >= start => last.end = end,
+            _ => hunks.push(start..end),
+        }
+    }
+    hunks
+}
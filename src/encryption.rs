@@ -0,0 +1,169 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+
+use crate::config::{Config, EncryptionConfig};
+use crate::context::{create_private_dir, ensure_dir, write_private};
+
+/// The suffix of the encrypted kubeconfig files, it is not a part of the context
+/// name.
+pub const SUFFIX: &str = ".age";
+
+/// Strip the suffix of the encrypted file from the name walked in kube dir, return
+/// `None` if it is not encrypted or the encryption is not configured.
+pub fn strip_suffix<'a>(cfg: &Config, name: &'a str) -> Option<&'a str> {
+    cfg.encryption.as_ref()?;
+    name.strip_suffix(SUFFIX).filter(|name| !name.is_empty())
+}
+
+/// Find the encrypted kubeconfig file of the context in the kube dirs, the user
+/// layer comes first.
+pub fn find(cfg: &Config, name: &str) -> Option<PathBuf> {
+    cfg.encryption.as_ref()?;
+    cfg.kube
+        .user_dir
+        .iter()
+        .chain([&cfg.kube.dir])
+        .map(|dir| get_encrypted_path(Path::new(dir), name))
+        .find(|path| fs::metadata(path).is_ok())
+}
+
+/// The encrypted file path of the context under the dir.
+pub fn get_encrypted_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{name}{SUFFIX}"))
+}
+
+/// The encrypted file path to write the context, it is always in the user layer if
+/// `kube.user_dir` is configured.
+pub fn get_write_path(cfg: &Config, name: &str) -> PathBuf {
+    let dir = cfg.kube.user_dir.as_ref().unwrap_or(&cfg.kube.dir);
+    get_encrypted_path(Path::new(dir), name)
+}
+
+/// Check if the file walked in kube dir is an encrypted kubeconfig.
+pub fn is_encrypted(cfg: &Config, path: &Path) -> bool {
+    cfg.encryption.is_some() && path.to_string_lossy().ends_with(SUFFIX)
+}
+
+/// The plaintext kubeconfig path of the encrypted context, in the private dir.
+pub fn get_plain_path(cfg: &Config, name: &str) -> PathBuf {
+    let encryption = cfg.encryption.as_ref().unwrap();
+    PathBuf::from(&encryption.dir).join(name)
+}
+
+/// The plaintext kubeconfig of the encrypted context if it was decrypted after the
+/// encrypted file was changed.
+pub fn get_fresh_plain_path(cfg: &Config, name: &str, encrypted: &Path) -> Option<PathBuf> {
+    let path = get_plain_path(cfg, name);
+    let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified());
+    match (modified(&path), modified(encrypted)) {
+        (Ok(plain), Ok(encrypted)) if plain >= encrypted => Some(path),
+        _ => None,
+    }
+}
+
+/// Make sure that the plaintext kubeconfig of the encrypted context is up to date,
+/// it is decrypted again only if the encrypted file was changed.
+pub fn materialize(cfg: &Config, name: &str, encrypted: &Path) -> Result<PathBuf> {
+    let encryption = cfg.encryption.as_ref().unwrap();
+    if let Some(path) = get_fresh_plain_path(cfg, name, encrypted) {
+        return Ok(path);
+    }
+    let path = get_plain_path(cfg, name);
+
+    let data = execute_age(
+        encryption,
+        [
+            "--decrypt",
+            "-i",
+            encryption.identity.as_str(),
+            path_str(encrypted)?,
+        ],
+    )
+    .with_context(|| format!("decrypt kubeconfig '{}'", encrypted.display()))?;
+    if let Some(dir) = path.parent() {
+        create_private_dir(dir)?;
+    }
+    write_private(&path, &data)?;
+    Ok(path)
+}
+
+/// Encrypt the plaintext kubeconfig of the context back to the encrypted file,
+/// after it was changed, such as by editing.
+pub fn save(cfg: &Config, name: &str, encrypted: &Path) -> Result<()> {
+    let path = get_plain_path(cfg, name);
+    let data = fs::read(&path).with_context(|| format!("read kubeconfig '{}'", path.display()))?;
+    write(cfg, encrypted, &data)
+}
+
+/// Encrypt the kubeconfig content to the file.
+pub fn write(cfg: &Config, encrypted: &Path, data: &[u8]) -> Result<()> {
+    let encryption = cfg.encryption.as_ref().unwrap();
+    let mut args = vec!["--encrypt"];
+    for recipient in encryption.recipients.iter() {
+        args.extend(["-r", recipient.as_str()]);
+    }
+
+    // Encrypt from a private temp file, never pass the content by args.
+    let dir = Path::new(&encryption.dir);
+    create_private_dir(dir)?;
+    let tmp = dir.join(format!(".encrypt-{}", std::process::id()));
+    write_private(&tmp, data)?;
+    args.push(path_str(&tmp)?);
+    let result = execute_age(encryption, args);
+    let _ = fs::remove_file(&tmp);
+    let data = result.with_context(|| format!("encrypt kubeconfig '{}'", encrypted.display()))?;
+
+    ensure_dir(encrypted)?;
+    write_private(encrypted, &data)
+}
+
+/// Remove the plaintext kubeconfig of the encrypted context, such as after deleting
+/// or renaming it.
+pub fn remove_plain(cfg: &Config, name: &str) -> Result<()> {
+    let path = get_plain_path(cfg, name);
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err).with_context(|| format!("remove file '{}'", path.display())),
+    }
+}
+
+fn execute_age<'a, I>(encryption: &EncryptionConfig, args: I) -> Result<Vec<u8>>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let mut cmd = Command::new(&encryption.exec);
+    cmd.args(args);
+    cmd.stdin(Stdio::inherit());
+    cmd.stderr(Stdio::piped());
+    cmd.stdout(Stdio::piped());
+
+    let output = match cmd.output() {
+        Ok(output) => output,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            bail!("cannot find {} in your system", encryption.exec)
+        }
+        Err(err) => return Err(err).with_context(|| format!("execute {}", encryption.exec)),
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!(
+            "{} exited with bad code {}: {}",
+            encryption.exec,
+            output.status.code().unwrap_or(-1),
+            stderr.trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+fn path_str(path: &Path) -> Result<&str> {
+    match path.to_str() {
+        Some(path) => Ok(path),
+        None => bail!("path '{}' is not valid utf-8", path.display()),
+    }
+}
@@ -0,0 +1,97 @@
+/// Check if the text matches the wildcard pattern, `*` matches any sequence of
+/// characters (including empty), `?` matches exactly one character. Like the
+/// path globs, neither of them matches `/`, so `staging-*` does not match the
+/// contexts under the `staging-a/` dir.
+pub fn is_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == text[t] || (pattern[p] == '?' && text[t] != '/')) {
+            p += 1;
+            t += 1;
+            continue;
+        }
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+            continue;
+        }
+        match star {
+            Some((star_p, star_t)) if text[star_t] != '/' => {
+                // Backtrack, let the last star consume one more character.
+                p = star_p + 1;
+                t = star_t + 1;
+                star = Some((star_p, star_t + 1));
+            }
+            _ => return false,
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_match() {
+        let cases = [
+            ("staging", "staging", true),
+            ("staging", "staging-a", false),
+            ("staging-*", "staging-a", true),
+            ("staging-*", "staging-", true),
+            ("staging-*", "staging", false),
+            ("staging-*", "prod-a", false),
+            ("*-a", "staging-a", true),
+            ("*-a", "-a", true),
+            ("*-a", "staging-b", false),
+            ("*-a", "staging-a-b", false),
+            ("*", "", true),
+            ("*", "staging", true),
+            ("**", "staging", true),
+            ("*-*-*", "eu-staging-a", true),
+            ("*-*-*", "eu-staging", false),
+            ("*a*b*", "xaxxbx", true),
+            ("*a*b*", "xbxxax", false),
+            ("*ab", "aab", true),
+            ("*ab", "abab", true),
+            ("*ab", "abba", false),
+            ("?", "a", true),
+            ("?", "", false),
+            ("?", "ab", false),
+            ("staging-?", "staging-a", true),
+            ("staging-?", "staging-ab", false),
+            ("?-*", "a-staging", true),
+            ("?*?", "ab", true),
+            ("?*?", "a", false),
+        ];
+        for (pattern, text, expect) in cases {
+            assert_eq!(is_match(pattern, text), expect, "'{pattern}' '{text}'");
+        }
+    }
+
+    #[test]
+    fn test_is_match_slash() {
+        let cases = [
+            ("staging-*", "staging-a/b", false),
+            ("*", "team-a/dev", false),
+            ("*-a", "team/b-a", false),
+            ("team?dev", "team/dev", false),
+            ("*?", "a/", false),
+            ("team-a/*", "team-a/dev", true),
+            ("team-a/*", "team-a/dev/b", false),
+            ("*/*", "team-a/dev", true),
+            ("*/dev", "team-a/dev", true),
+            ("*/dev", "team-a/b/dev", false),
+            ("*/*/*", "team-a/b/dev", true),
+            ("team-?/d*", "team-a/dev", true),
+        ];
+        for (pattern, text, expect) in cases {
+            assert_eq!(is_match(pattern, text), expect, "'{pattern}' '{text}'");
+        }
+    }
+}
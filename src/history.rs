@@ -0,0 +1,604 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use chrono::{Days, Local, NaiveDate, NaiveTime, TimeZone};
+use rev_lines::RevLines;
+
+use crate::config::{Config, HistoryConfig};
+use crate::context::{confirm, ensure_dir, get_kubeconfig_store_path, KubeContext};
+use crate::selector;
+use crate::vault;
+
+pub struct HistoryEntry {
+    pub timestamp: u64,
+    pub name: String,
+    pub namespace: String,
+}
+
+pub struct History {
+    rev_file: RevLines<fs::File>,
+}
+
+impl History {
+    pub fn open(cfg: &Config) -> Result<History> {
+        let path = &cfg.history.path;
+        let file = fs::File::open(path)
+            .with_context(|| format!("open history file '{path}' for reading"))?;
+        let rev_file = RevLines::new(file);
+        Ok(History { rev_file })
+    }
+
+    /// Append the context to history. The file is compacted when needed: the
+    /// consecutive duplicate entry is collapsed (if `history.dedup`), and the oldest
+    /// entries beyond `history.max_entries` are dropped.
+    pub fn write(ctx: &KubeContext) -> Result<()> {
+        let cfg = &ctx.cfg.history;
+        let line = format!("{} {} {}", now(), ctx.name, ctx.namespace);
+
+        let _lock = Self::lock(cfg)?;
+        let data = Self::read_all(cfg)?;
+        let mut lines: Vec<&str> = data
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+        let mut compact = false;
+        if cfg.dedup {
+            if let Some(last) = lines.last() {
+                if same_entry(last, &line) {
+                    lines.pop();
+                    compact = true;
+                }
+            }
+        }
+        lines.push(&line);
+        if cfg.max_entries > 0 && lines.len() > cfg.max_entries {
+            lines.drain(..lines.len() - cfg.max_entries);
+            compact = true;
+        }
+        if compact && cfg.dedup {
+            // The file written by the old versions (or with dedup disabled) might
+            // have consecutive duplicates, collapse them to the latest one while
+            // rewriting.
+            let mut deduped: Vec<&str> = Vec::with_capacity(lines.len());
+            for line in lines {
+                if let Some(last) = deduped.last() {
+                    if same_entry(last, line) {
+                        deduped.pop();
+                    }
+                }
+                deduped.push(line);
+            }
+            lines = deduped;
+        }
+
+        if compact {
+            return Self::write_all(cfg, &lines);
+        }
+
+        let mut opts = fs::OpenOptions::new();
+        opts.create(true).write(true).append(true);
+        let mut file = opts
+            .open(&cfg.path)
+            .with_context(|| format!("open history file '{}' for writing", cfg.path))?;
+        file.write_all(format!("{line}\n").as_bytes())
+            .context("write content to history file")?;
+        file.flush().context("flush history file")?;
+
+        Ok(())
+    }
+
+    /// Rewrite the history entries of the renamed context to the new name.
+    pub fn rename(cfg: &Config, name: &str, new_name: &str) -> Result<()> {
+        let cfg = &cfg.history;
+        let _lock = Self::lock(cfg)?;
+        let data = Self::read_all(cfg)?;
+
+        let lines: Vec<_> = data
+            .lines()
+            .map(|line| {
+                let fields: Vec<_> = line.trim().split(' ').collect();
+                if fields.len() == 3 && fields[1] == name {
+                    return Cow::Owned(format!("{} {new_name} {}", fields[0], fields[2]));
+                }
+                Cow::Borrowed(line)
+            })
+            .collect();
+        Self::write_all(cfg, &lines)
+    }
+
+    /// Remove the history entries of the contexts.
+    pub fn remove(cfg: &Config, names: &HashSet<String>) -> Result<()> {
+        let cfg = &cfg.history;
+        let _lock = Self::lock(cfg)?;
+        let data = Self::read_all(cfg)?;
+        let lines: Vec<_> = data
+            .lines()
+            .filter(|line| {
+                let fields: Vec<_> = line.trim().split(' ').collect();
+                fields.len() != 3 || !names.contains(fields[1])
+            })
+            .collect();
+        Self::write_all(cfg, &lines)
+    }
+
+    /// Remove the history entries of the context and namespace pairs, return the
+    /// number of removed entries.
+    pub fn remove_pairs(cfg: &Config, pairs: &HashSet<(String, String)>) -> Result<usize> {
+        let cfg = &cfg.history;
+        let _lock = Self::lock(cfg)?;
+        let data = Self::read_all(cfg)?;
+        let mut removed = 0;
+        let lines: Vec<_> = data
+            .lines()
+            .filter(|line| {
+                let fields: Vec<_> = line.trim().split(' ').collect();
+                if fields.len() == 3
+                    && pairs.contains(&(fields[1].to_string(), fields[2].to_string()))
+                {
+                    removed += 1;
+                    return false;
+                }
+                true
+            })
+            .collect();
+        if removed > 0 {
+            Self::write_all(cfg, &lines)?;
+        }
+        Ok(removed)
+    }
+
+    /// Let user mark the history entries to delete in selector, such as the ones of
+    /// a decommissioned cluster. The entries with the same context and namespace
+    /// are shown as one item. 0 limit means all.
+    pub fn delete_interactive(cfg: &Config, limit: usize) -> Result<()> {
+        let mut pairs: Vec<(String, String)> = Vec::new();
+        let mut stats: HashMap<(String, String), (u64, usize)> = HashMap::new();
+        for entry in History::open(cfg)? {
+            let entry = entry?;
+            let pair = (entry.name, entry.namespace);
+            if let Some((_, count)) = stats.get_mut(&pair) {
+                *count += 1;
+                continue;
+            }
+            if limit > 0 && pairs.len() >= limit {
+                continue;
+            }
+            stats.insert(pair.clone(), (entry.timestamp, 1));
+            pairs.push(pair);
+        }
+        if pairs.is_empty() {
+            bail!("no history entry to delete");
+        }
+
+        let keys: Vec<_> = pairs.iter().map(|(name, _)| name.as_str()).collect();
+        let width = pairs
+            .iter()
+            .map(|(_, namespace)| namespace.chars().count())
+            .max()
+            .unwrap_or(0);
+        let infos: Vec<_> = pairs
+            .iter()
+            .map(|pair| {
+                let (timestamp, count) = stats[pair];
+                let mut info = format!(
+                    "{:width$}  {}, {count} entries",
+                    pair.1,
+                    format_elapsed(timestamp)
+                );
+                if vault::lookup(cfg, &pair.0).is_none()
+                    && fs::metadata(get_kubeconfig_store_path(cfg, &pair.0)).is_err()
+                {
+                    info.push_str(", removed");
+                }
+                info
+            })
+            .collect();
+        let selected = selector::select_many(&keys, &infos)?;
+
+        let count: usize = selected.iter().map(|idx| stats[&pairs[*idx]].1).sum();
+        if !confirm(format!(
+            "Delete {count} history entries of {} contexts and namespaces",
+            selected.len()
+        ))? {
+            return Ok(());
+        }
+        let selected: HashSet<_> = selected.into_iter().map(|idx| pairs[idx].clone()).collect();
+        let removed = Self::remove_pairs(cfg, &selected)?;
+        info!("Deleted {removed} history entries");
+        Ok(())
+    }
+
+    /// Hold the lock file while rewriting the history file, so that the concurrent
+    /// shells do not lose the entries of each other. It is released when the
+    /// returned file is dropped.
+    fn lock(cfg: &HistoryConfig) -> Result<fs::File> {
+        let path = format!("{}.lock", cfg.path);
+        ensure_dir(Path::new(&path))?;
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("open history lock file '{path}'"))?;
+        file.lock()
+            .with_context(|| format!("lock history lock file '{path}'"))?;
+        Ok(file)
+    }
+
+    fn read_all(cfg: &HistoryConfig) -> Result<String> {
+        match fs::read_to_string(&cfg.path) {
+            Ok(data) => Ok(data),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+            Err(err) => Err(err).with_context(|| format!("read history file '{}'", cfg.path)),
+        }
+    }
+
+    fn write_all<S: AsRef<str>>(cfg: &HistoryConfig, lines: &[S]) -> Result<()> {
+        let mut data = String::new();
+        for line in lines {
+            data.push_str(line.as_ref());
+            data.push('\n');
+        }
+        ensure_dir(Path::new(&cfg.path))?;
+        fs::write(&cfg.path, data).with_context(|| format!("write history file '{}'", cfg.path))
+    }
+}
+
+impl Iterator for History {
+    type Item = Result<HistoryEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let item = self.rev_file.next()?;
+            if let Err(err) = item {
+                return Some(Err(err).context("read history file"));
+            }
+            let line = item.unwrap();
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let fields: Vec<_> = line.split(' ').collect();
+            if fields.len() != 3 {
+                continue;
+            }
+
+            let mut iter = fields.into_iter();
+
+            let timestamp = match iter.next().unwrap().parse() {
+                Ok(timestamp) => timestamp,
+                Err(_) => continue,
+            };
+
+            let name = iter.next().unwrap();
+            if name.is_empty() {
+                continue;
+            }
+
+            let namespace = iter.next().unwrap();
+            if namespace.is_empty() {
+                continue;
+            }
+
+            return Some(Ok(HistoryEntry {
+                timestamp,
+                name: name.to_string(),
+                namespace: namespace.to_string(),
+            }));
+        }
+    }
+}
+
+/// Check if the two history lines are for the same context and namespace.
+fn same_entry(a: &str, b: &str) -> bool {
+    let a: Vec<_> = a.trim().split(' ').collect();
+    let b: Vec<_> = b.trim().split(' ').collect();
+    a.len() == 3 && b.len() == 3 && a[1..] == b[1..]
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backwards")
+        .as_secs()
+}
+
+/// The last used time of the contexts, the ones never used are absent.
+pub fn last_used(cfg: &Config, ctxs: &[KubeContext]) -> HashMap<String, u64> {
+    let names: HashSet<&str> = ctxs.iter().map(|ctx| ctx.name.as_str()).collect();
+    let mut last_used = HashMap::with_capacity(names.len());
+    // The history is optional here, ignore the error.
+    if let Ok(history) = History::open(cfg) {
+        for entry in history.flatten() {
+            if names.contains(entry.name.as_str()) {
+                last_used.entry(entry.name).or_insert(entry.timestamp);
+                if last_used.len() == names.len() {
+                    break;
+                }
+            }
+        }
+    }
+    last_used
+}
+
+/// How often and how recently a context was used, counted from history.
+#[derive(Debug, Default)]
+pub struct Usage {
+    pub count: usize,
+    pub last_used: u64,
+    /// The use count of each namespace of the context.
+    pub namespaces: BTreeMap<String, usize>,
+}
+
+/// Count the usage of all the contexts in history, the ones never used are absent.
+/// The counts are bounded by `history.max_entries`, and the consecutive switches
+/// to the same context are counted once with `history.dedup`.
+pub fn usage(cfg: &Config) -> HashMap<String, Usage> {
+    let mut usage: HashMap<String, Usage> = HashMap::new();
+    // The history is optional here, ignore the error.
+    if let Ok(history) = History::open(cfg) {
+        for entry in history.flatten() {
+            let item = usage.entry(entry.name).or_default();
+            item.count += 1;
+            // Newest first.
+            if item.last_used == 0 {
+                item.last_used = entry.timestamp;
+            }
+            *item.namespaces.entry(entry.namespace).or_default() += 1;
+        }
+    }
+    usage
+}
+
+/// Format the elapsed time since the timestamp in a human readable way, such as
+/// "3h ago".
+pub fn format_elapsed(timestamp: u64) -> String {
+    let secs = now().saturating_sub(timestamp);
+    match secs {
+        0..=59 => String::from("just now"),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+/// Parse the time query (without the leading '@') to unix timestamp, supported
+/// formats:
+///
+/// - Relative duration: `-30m`, `-3h`, `-1h30m`, `-2d`, `-1w`.
+/// - Clock time of today in local timezone: `14:30`. If it is in the future,
+///   use yesterday's.
+/// - Day name, optionally with clock time: `today`, `yesterday`, `yesterday:14:30`.
+///   Without clock time, `today` means now and `yesterday` means 24 hours ago.
+/// - Date, optionally with clock time: `2024-03-20`, `2024-03-20:14:30`. Without
+///   clock time, means the end of that day.
+pub fn parse_time(query: &str) -> Result<u64> {
+    let now = now();
+    if let Some(duration) = query.strip_prefix('-') {
+        let secs =
+            parse_duration(duration).with_context(|| format!("invalid relative time '{query}'"))?;
+        return Ok(now.saturating_sub(secs));
+    }
+
+    let (day, time) = match query.split_once(':') {
+        Some((day, time)) if day.len() > 2 => (day, Some(time)),
+        _ if query.contains(':') => ("", Some(query)),
+        _ => (query, None),
+    };
+    let time = match time {
+        Some(time) => Some(
+            NaiveTime::parse_from_str(time, "%H:%M")
+                .with_context(|| format!("invalid clock time '{time}', should be 'HH:MM'"))?,
+        ),
+        None => None,
+    };
+
+    let today = Local::now().date_naive();
+    let date = match day {
+        "" | "today" => today,
+        "yesterday" => today - Days::new(1),
+        date => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .with_context(|| format!("invalid time '{query}'"))?,
+    };
+
+    let time = match time {
+        Some(time) => time,
+        None if day.is_empty() || day == "today" => return Ok(now),
+        None if day == "yesterday" => return Ok(now.saturating_sub(24 * 60 * 60)),
+        None => NaiveTime::from_hms_opt(23, 59, 59).unwrap(),
+    };
+
+    let datetime = match Local.from_local_datetime(&date.and_time(time)).earliest() {
+        Some(datetime) => datetime,
+        None => bail!("invalid local time '{query}'"),
+    };
+    let mut timestamp = datetime.timestamp().max(0) as u64;
+    if day.is_empty() && timestamp > now {
+        // Clock time without a day in the future, it means yesterday.
+        timestamp = timestamp.saturating_sub(24 * 60 * 60);
+    }
+
+    Ok(timestamp)
+}
+
+fn parse_duration(s: &str) -> Result<u64> {
+    if s.is_empty() {
+        bail!("duration cannot be empty");
+    }
+
+    let mut secs = 0;
+    let mut num = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            num.push(c);
+            continue;
+        }
+        if num.is_empty() {
+            bail!("missing number before unit '{c}'");
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => bail!("unknown duration unit '{c}'"),
+        };
+        let value: u64 = num.parse().context("parse duration number")?;
+        secs = match value
+            .checked_mul(unit)
+            .and_then(|value| value.checked_add(secs))
+        {
+            Some(secs) => secs,
+            None => bail!("duration too large"),
+        };
+        num.clear();
+    }
+    if !num.is_empty() {
+        bail!("missing unit after number '{num}'");
+    }
+
+    Ok(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+
+    use super::*;
+
+    fn local_timestamp(datetime: &str) -> u64 {
+        let datetime = NaiveDateTime::parse_from_str(datetime, "%Y-%m-%d %H:%M:%S").unwrap();
+        Local
+            .from_local_datetime(&datetime)
+            .earliest()
+            .unwrap()
+            .timestamp() as u64
+    }
+
+    /// Parse the time and check it is between the expected range computed before
+    /// and after parsing, so that the clock ticking does not fail the test.
+    fn assert_relative(query: &str, secs: u64) {
+        let before = now().saturating_sub(secs);
+        let timestamp = parse_time(query).unwrap();
+        let after = now().saturating_sub(secs);
+        assert!(
+            (before..=after).contains(&timestamp),
+            "'{query}' parsed to {timestamp}, expect {before}..={after}"
+        );
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        let cases = [
+            ("30s", 30),
+            ("30m", 30 * 60),
+            ("3h", 3 * 60 * 60),
+            ("1h30m", 90 * 60),
+            ("2d", 2 * 24 * 60 * 60),
+            ("1w", 7 * 24 * 60 * 60),
+            ("1w1d1h1m1s", 8 * 24 * 60 * 60 + 60 * 60 + 60 + 1),
+        ];
+        for (duration, expect) in cases {
+            assert_eq!(parse_duration(duration).unwrap(), expect, "{duration}");
+        }
+
+        let cases = [
+            ("", "duration cannot be empty"),
+            ("h", "missing number before unit 'h'"),
+            ("30", "missing unit after number '30'"),
+            ("1h30", "missing unit after number '30'"),
+            ("3y", "unknown duration unit 'y'"),
+            ("99999999999999999w", "duration too large"),
+            ("18446744073709551615s1s", "duration too large"),
+        ];
+        for (duration, expect) in cases {
+            let err = parse_duration(duration).unwrap_err();
+            assert_eq!(err.to_string(), expect, "{duration}");
+        }
+    }
+
+    #[test]
+    fn test_parse_time_relative() {
+        assert_relative("-30m", 30 * 60);
+        assert_relative("-1h30m", 90 * 60);
+        assert_relative("-1w", 7 * 24 * 60 * 60);
+        assert_relative("today", 0);
+        assert_relative("yesterday", 24 * 60 * 60);
+        assert_eq!(parse_time("-9999999999w").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_parse_time_date() {
+        assert_eq!(
+            parse_time("2024-03-20").unwrap(),
+            local_timestamp("2024-03-20 23:59:59")
+        );
+        assert_eq!(
+            parse_time("2024-03-20:14:30").unwrap(),
+            local_timestamp("2024-03-20 14:30:00")
+        );
+
+        let yesterday = Local::now().date_naive() - Days::new(1);
+        assert_eq!(
+            parse_time("yesterday:14:30").unwrap(),
+            local_timestamp(&format!("{yesterday} 14:30:00"))
+        );
+        let today = Local::now().date_naive();
+        assert_eq!(
+            parse_time("today:00:00").unwrap(),
+            local_timestamp(&format!("{today} 00:00:00"))
+        );
+    }
+
+    #[test]
+    fn test_parse_time_clock() {
+        // The midnight of today is never in the future.
+        let today = Local::now().date_naive();
+        assert_eq!(
+            parse_time("00:00").unwrap(),
+            local_timestamp(&format!("{today} 00:00:00"))
+        );
+
+        // The clock time in the future is mapped to yesterday, so it is always in
+        // the last 24 hours.
+        for clock in ["06:00", "12:00", "18:00", "23:59"] {
+            let before = now();
+            let timestamp = parse_time(clock).unwrap();
+            assert!(timestamp <= before, "{clock} is in the future");
+            assert!(
+                before - timestamp <= 24 * 60 * 60,
+                "{clock} is earlier than 24 hours ago"
+            );
+            let time = Local.timestamp_opt(timestamp as i64, 0).unwrap().time();
+            assert_eq!(time.format("%H:%M").to_string(), clock);
+        }
+    }
+
+    #[test]
+    fn test_parse_time_invalid() {
+        let cases = [
+            "-",
+            "-30",
+            "-3y",
+            "25:00",
+            "14:3x",
+            "14",
+            "tomorrow",
+            "tomorrow:14:30",
+            "yesterday:25:00",
+            "2024-13-01",
+            "2024-03-20:14",
+            "2024/03/20",
+        ];
+        for query in cases {
+            assert!(parse_time(query).is_err(), "'{query}' should be invalid");
+        }
+    }
+}
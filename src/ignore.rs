@@ -0,0 +1,128 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use crate::glob;
+
+/// The rules in `.kubeswitchignore`, it supports a subset of gitignore syntax:
+/// `*`, `?`, `**`, the leading `/` to anchor, the trailing `/` to match only
+/// directories, and the leading `!` to negate.
+pub struct IgnoreRules {
+    rules: Vec<Rule>,
+}
+
+struct Rule {
+    components: Vec<String>,
+    negate: bool,
+    dir_only: bool,
+}
+
+impl IgnoreRules {
+    pub const FILE_NAME: &'static str = ".kubeswitchignore";
+
+    /// Load the rules from the ignore file under the dir, no rule if it does not
+    /// exist.
+    pub fn load(dir: &Path) -> Result<IgnoreRules> {
+        let path = dir.join(Self::FILE_NAME);
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("read ignore file '{}'", path.display()))
+            }
+        };
+
+        let rules = data.lines().filter_map(Rule::parse).collect();
+        Ok(IgnoreRules { rules })
+    }
+
+    /// Check if the file is ignored, the path is relative to the dir of the ignore
+    /// file. A file is also ignored if any of its parent dirs is ignored.
+    pub fn is_ignored(&self, path: &str) -> bool {
+        let components: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        if components.last() == Some(&Self::FILE_NAME) {
+            return true;
+        }
+        if self.rules.is_empty() {
+            return false;
+        }
+
+        for end in 1..=components.len() {
+            let is_dir = end < components.len();
+            if self.match_rules(&components[..end], is_dir) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The last matched rule wins, like gitignore.
+    fn match_rules(&self, path: &[&str], is_dir: bool) -> bool {
+        let mut ignored = false;
+        for rule in self.rules.iter() {
+            if rule.dir_only && !is_dir {
+                continue;
+            }
+            if match_components(&rule.components, path) {
+                ignored = !rule.negate;
+            }
+        }
+        ignored
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Rule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negate, line) = match line.strip_prefix('!') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+
+        // The pattern without slash matches in any level, otherwise it is relative
+        // to the dir of the ignore file.
+        let anchored = line.contains('/');
+        let mut components: Vec<String> = line
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect();
+        if components.is_empty() {
+            return None;
+        }
+        if !anchored {
+            components.insert(0, String::from("**"));
+        }
+
+        Some(Rule {
+            components,
+            negate,
+            dir_only,
+        })
+    }
+}
+
+fn match_components(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        Some(first) if first == "**" => {
+            (0..=path.len()).any(|skip| match_components(&pattern[1..], &path[skip..]))
+        }
+        Some(first) => match path.first() {
+            Some(name) if glob::is_match(first, name) => {
+                match_components(&pattern[1..], &path[1..])
+            }
+            _ => false,
+        },
+    }
+}
@@ -0,0 +1,817 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use serde_yaml::{Mapping, Value};
+
+use crate::backup;
+use crate::config::{
+    get_home_dir, get_runtime_dir, Config, DiscoverProvider, ImportConflict, ImportNaming,
+};
+use crate::context::{
+    confirm, create_private_dir, ensure_dir, get_kubeconfig_store_path, get_kubeconfig_write_path,
+    quote_shell, walk_files, write_private,
+};
+use crate::encryption;
+use crate::tags::{parse_tag, Tags};
+
+/// The parsed kubeconfig document, we keep the raw yaml value so that the unknown
+/// fields (such as `exec` auth and extensions) are preserved when importing.
+pub struct KubeconfigDoc {
+    path: PathBuf,
+    value: Mapping,
+}
+
+#[derive(Debug, Default)]
+struct ImportReport {
+    imported: usize,
+    skipped: usize,
+    failed: usize,
+}
+
+impl KubeconfigDoc {
+    pub fn read(path: &Path) -> Result<KubeconfigDoc> {
+        let data = fs::read(path).with_context(|| format!("read file '{}'", path.display()))?;
+        Self::parse(path, &data)
+    }
+
+    /// Parse the kubeconfig content read from the path by other means, such as
+    /// `sudo cat`.
+    pub fn parse(path: &Path, data: &[u8]) -> Result<KubeconfigDoc> {
+        let value: Value = serde_yaml::from_slice(data)
+            .with_context(|| format!("parse yaml file '{}'", path.display()))?;
+        let value = match value {
+            Value::Mapping(value) => value,
+            _ => bail!("kubeconfig '{}' should be a yaml mapping", path.display()),
+        };
+        Ok(KubeconfigDoc {
+            path: path.to_path_buf(),
+            value,
+        })
+    }
+
+    /// All the context names in the kubeconfig.
+    pub fn context_names(&self) -> Vec<String> {
+        self.list("contexts")
+            .iter()
+            .filter_map(|item| item.get("name").and_then(Value::as_str))
+            .map(String::from)
+            .collect()
+    }
+
+    /// The context to import, it is the `current-context`, or the only context if
+    /// `current-context` is absent.
+    pub fn main_context(&self) -> Result<String> {
+        if let Some(current) = self.value.get("current-context").and_then(Value::as_str) {
+            if !current.is_empty() {
+                return Ok(current.to_string());
+            }
+        }
+        let mut names = self.context_names();
+        match names.len() {
+            0 => bail!("no context in kubeconfig"),
+            1 => Ok(names.remove(0)),
+            _ => bail!("multiple contexts without current-context, please split it with the `import` command"),
+        }
+    }
+
+    /// Build a new standalone kubeconfig that only contains the context and its
+    /// cluster and user (like `kubectl config view --minify --flatten`). The
+    /// certificate files are embedded as data, so the result does not depend on
+    /// any other files.
+    pub fn minify(&self, context: &str) -> Result<String> {
+        let doc = self.minify_mapping(context)?;
+        serde_yaml::to_string(&doc).context("serialize kubeconfig")
+    }
+
+    /// The same as [`KubeconfigDoc::minify`], but returns the yaml mapping for
+    /// further processing.
+    pub fn minify_mapping(&self, context: &str) -> Result<Mapping> {
+        let ctx = self.find("contexts", context)?;
+        let ctx_entry = match ctx.get("context") {
+            Some(Value::Mapping(entry)) => entry,
+            _ => bail!("context '{context}' has no context entry"),
+        };
+
+        let cluster_name = match ctx_entry.get("cluster").and_then(Value::as_str) {
+            Some(name) => name,
+            None => bail!("context '{context}' has no cluster"),
+        };
+        let mut cluster = self.find("clusters", cluster_name)?.clone();
+        self.embed_files(
+            &mut cluster,
+            "cluster",
+            &[("certificate-authority", "certificate-authority-data")],
+        )?;
+
+        let mut users = Vec::new();
+        if let Some(user_name) = ctx_entry.get("user").and_then(Value::as_str) {
+            let mut user = self.find("users", user_name)?.clone();
+            self.embed_files(
+                &mut user,
+                "user",
+                &[
+                    ("client-certificate", "client-certificate-data"),
+                    ("client-key", "client-key-data"),
+                ],
+            )?;
+            users.push(user);
+        }
+
+        let mut doc = Mapping::new();
+        doc.insert("apiVersion".into(), "v1".into());
+        doc.insert("kind".into(), "Config".into());
+        doc.insert("clusters".into(), Value::Sequence(vec![cluster]));
+        doc.insert("contexts".into(), Value::Sequence(vec![ctx.clone()]));
+        doc.insert("users".into(), Value::Sequence(users));
+        doc.insert("current-context".into(), context.into());
+        if let Some(preferences) = self.value.get("preferences") {
+            doc.insert("preferences".into(), preferences.clone());
+        }
+        Ok(doc)
+    }
+
+    fn list(&self, key: &str) -> &[Value] {
+        match self.value.get(key) {
+            Some(Value::Sequence(items)) => items,
+            _ => &[],
+        }
+    }
+
+    fn find(&self, key: &str, name: &str) -> Result<&Value> {
+        match self
+            .list(key)
+            .iter()
+            .find(|item| item.get("name").and_then(Value::as_str) == Some(name))
+        {
+            Some(item) => Ok(item),
+            None => bail!("cannot find '{name}' in {key}"),
+        }
+    }
+
+    fn embed_files(&self, item: &mut Value, key: &str, fields: &[(&str, &str)]) -> Result<()> {
+        use base64::Engine;
+
+        let entry = match item.get_mut(key) {
+            Some(Value::Mapping(entry)) => entry,
+            _ => return Ok(()),
+        };
+        for (file_field, data_field) in fields {
+            let file = match entry.remove(*file_field) {
+                Some(Value::String(file)) => file,
+                _ => continue,
+            };
+            // The relative path is relative to the kubeconfig file.
+            let path = match self.path.parent() {
+                Some(dir) => dir.join(&file),
+                None => PathBuf::from(&file),
+            };
+            let data = fs::read(&path)
+                .with_context(|| format!("read {file_field} file '{}'", path.display()))?;
+            let data = base64::engine::general_purpose::STANDARD.encode(data);
+            entry.insert((*data_field).into(), data.into());
+        }
+        Ok(())
+    }
+}
+
+/// Convert the name to a valid context name, the invalid characters are replaced
+/// with '-'.
+pub fn sanitize_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '/' => c,
+            _ => '-',
+        })
+        .collect();
+    name.trim_matches(|c| c == '/' || c == '-').to_string()
+}
+
+/// Install the kubeconfig content as the context, handle the name collision with
+/// the policy. Return the installed name, or `None` if skipped.
+pub fn install(
+    cfg: &Config,
+    name: &str,
+    content: &str,
+    conflict: ImportConflict,
+) -> Result<Option<String>> {
+    let mut name = name.to_string();
+    let path = get_kubeconfig_store_path(cfg, &name);
+    if fs::symlink_metadata(&path).is_ok() {
+        let conflict = match conflict {
+            ImportConflict::Prompt => ask_conflict(&name)?,
+            conflict => conflict,
+        };
+        match conflict {
+            ImportConflict::Skip => return Ok(None),
+            ImportConflict::Overwrite => {
+                // Nothing changed, such as refreshing by `ks daemon`.
+                if encryption::find(cfg, &name).is_none()
+                    && fs::read(&path).ok().as_deref() == Some(content.as_bytes())
+                {
+                    return Ok(Some(name));
+                }
+                backup::save(cfg, &name, &get_kubeconfig_store_path(cfg, &name))?
+            }
+            ImportConflict::Rename => {
+                name = find_free_name(cfg, &name);
+            }
+            ImportConflict::Prompt => unreachable!(),
+        }
+    }
+
+    // Overwriting a context in the shared dir makes a copy in the user layer.
+    if encryption::find(cfg, &name).is_some() {
+        encryption::write(
+            cfg,
+            &encryption::get_write_path(cfg, &name),
+            content.as_bytes(),
+        )?;
+        return Ok(Some(name));
+    }
+    let path = get_kubeconfig_write_path(cfg, &name);
+    ensure_dir(&path)?;
+    write_private(&path, content.as_bytes())?;
+    Ok(Some(name))
+}
+
+/// The options shared by all the importers.
+pub struct ImportOptions<'a> {
+    /// The target directory under `kube.dir`.
+    pub prefix: Option<&'a str>,
+    pub conflict: ImportConflict,
+    /// Only print the resolved names and paths, do not write anything.
+    pub dry_run: bool,
+    /// Ask user to adjust the name of every context before importing.
+    pub interactive: bool,
+}
+
+/// A context resolved by the importer, waiting to be installed.
+struct ImportItem {
+    /// Where the context comes from, for display.
+    source: String,
+    name: String,
+    content: String,
+    /// Tagged to the installed context, to record where it comes from.
+    tags: BTreeMap<String, String>,
+}
+
+/// Import all the kubeconfig files under the dir into `kube.dir`, print a summary
+/// report at the end.
+pub fn import_dir(
+    cfg: &Config,
+    dir: &str,
+    naming: ImportNaming,
+    opts: &ImportOptions,
+) -> Result<()> {
+    let dir = PathBuf::from(dir);
+    if !dir.is_dir() {
+        bail!("import path '{}' is not a directory", dir.display());
+    }
+
+    let mut files = Vec::new();
+    walk_files(&dir, |path| {
+        files.push(path);
+        Ok(())
+    })?;
+    files.sort();
+
+    let mut report = ImportReport::default();
+    let mut items = Vec::with_capacity(files.len());
+    for path in files {
+        let source = path
+            .strip_prefix(&dir)
+            .unwrap_or(&path)
+            .display()
+            .to_string();
+        match resolve_file(&path, naming, opts.prefix) {
+            Ok((name, content)) => items.push(ImportItem {
+                source,
+                name,
+                content,
+                tags: BTreeMap::new(),
+            }),
+            Err(err) => {
+                warning!("failed to import {source}: {err:#}");
+                report.failed += 1;
+            }
+        }
+    }
+
+    run_import(cfg, items, opts, report)
+}
+
+/// Split a merged kubeconfig into per-context files under `kube.dir`, every
+/// context is named after itself.
+pub fn import_merged(cfg: &Config, path: &str, opts: &ImportOptions) -> Result<()> {
+    let doc = KubeconfigDoc::read(Path::new(path))?;
+    let contexts = doc.context_names();
+    if contexts.is_empty() {
+        bail!("no context in kubeconfig '{path}'");
+    }
+
+    let mut report = ImportReport::default();
+    let mut items = Vec::with_capacity(contexts.len());
+    for context in contexts {
+        let resolved = doc
+            .minify(&context)
+            .and_then(|content| Ok((with_prefix(sanitize_name(&context), opts.prefix)?, content)));
+        match resolved {
+            Ok((name, content)) => items.push(ImportItem {
+                source: context,
+                name,
+                content,
+                tags: BTreeMap::new(),
+            }),
+            Err(err) => {
+                warning!("failed to import {context}: {err:#}");
+                report.failed += 1;
+            }
+        }
+    }
+
+    run_import(cfg, items, opts, report)
+}
+
+/// A well-known kubeconfig of the local cluster.
+struct LocalSource {
+    name: &'static str,
+    path: PathBuf,
+    /// The context to import, default is the main context of the kubeconfig.
+    context: Option<&'static str>,
+}
+
+impl LocalSource {
+    /// The prefix of the imported local contexts, if not specified.
+    const DEFAULT_PREFIX: &'static str = "local";
+
+    fn list() -> Result<Vec<LocalSource>> {
+        let kubeconfig = get_home_dir()?.join(".kube").join("config");
+        Ok(vec![
+            LocalSource {
+                name: "k3s",
+                path: PathBuf::from("/etc/rancher/k3s/k3s.yaml"),
+                context: None,
+            },
+            LocalSource {
+                name: "microk8s",
+                path: PathBuf::from("/var/snap/microk8s/current/credentials/client.config"),
+                context: None,
+            },
+            LocalSource {
+                name: "docker-desktop",
+                path: kubeconfig.clone(),
+                context: Some("docker-desktop"),
+            },
+            LocalSource {
+                name: "rancher-desktop",
+                path: kubeconfig,
+                context: Some("rancher-desktop"),
+            },
+        ])
+    }
+
+    /// Read the kubeconfig, return `None` if it does not exist on this machine. The
+    /// root-only file (such as k3s) can be read with sudo after confirmation.
+    fn read(&self) -> Result<Option<KubeconfigDoc>> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+                eprintln!(
+                    "The {} kubeconfig '{}' is only readable by root",
+                    self.name,
+                    self.path.display()
+                );
+                if !confirm("Read it with sudo")? {
+                    return Ok(None);
+                }
+                read_with_sudo(&self.path)?
+            }
+            Err(err) => {
+                return Err(err).with_context(|| format!("read file '{}'", self.path.display()))
+            }
+        };
+        let doc = KubeconfigDoc::parse(&self.path, &data)?;
+        if let Some(context) = self.context {
+            if !doc.context_names().iter().any(|name| name == context) {
+                return Ok(None);
+            }
+        }
+        Ok(Some(doc))
+    }
+}
+
+/// Detect the kubeconfigs of the local clusters (k3s, microk8s, docker-desktop and
+/// rancher-desktop) on this machine, and import them after confirmation.
+pub fn import_local(cfg: &Config, opts: &ImportOptions) -> Result<()> {
+    let prefix = opts.prefix.unwrap_or(LocalSource::DEFAULT_PREFIX);
+
+    let mut report = ImportReport::default();
+    let mut items = Vec::new();
+    for source in LocalSource::list()? {
+        let resolved = source.read().and_then(|doc| match doc {
+            Some(doc) => {
+                let context = match source.context {
+                    Some(context) => context.to_string(),
+                    None => doc.main_context()?,
+                };
+                let content = doc.minify(&context)?;
+                Ok(Some((
+                    with_prefix(source.name.to_string(), Some(prefix))?,
+                    content,
+                )))
+            }
+            None => Ok(None),
+        });
+        match resolved {
+            Ok(Some((name, content))) => {
+                info!(
+                    "Found {} kubeconfig '{}'",
+                    source.name,
+                    source.path.display()
+                );
+                items.push(ImportItem {
+                    source: source.name.to_string(),
+                    name,
+                    content,
+                    tags: BTreeMap::new(),
+                });
+            }
+            Ok(None) => {}
+            Err(err) => {
+                warning!("failed to detect {}: {err:#}", source.name);
+                report.failed += 1;
+            }
+        }
+    }
+
+    if items.is_empty() {
+        info!("No local cluster found");
+        return Ok(());
+    }
+    if !opts.dry_run
+        && !opts.interactive
+        && !confirm(format!("Register {} local contexts", items.len()))?
+    {
+        return Ok(());
+    }
+    run_import(cfg, items, opts, report)
+}
+
+/// Discover the clusters of the cloud provider with the `list` command in
+/// `discover`, generate their kubeconfigs with the `kubeconfig` command, and import
+/// them after confirmation.
+pub fn import_discovered(cfg: &Config, provider_name: &str, opts: &ImportOptions) -> Result<()> {
+    if cfg.offline {
+        bail!("cannot discover contexts in offline mode");
+    }
+    let provider = match cfg.discover.get(provider_name) {
+        Some(provider) => provider,
+        None => bail!(
+            "unknown provider '{provider_name}', available: {}",
+            cfg.discover.keys().cloned().collect::<Vec<_>>().join(", ")
+        ),
+    };
+
+    let output = run_discover_command(&provider.list, &BTreeMap::new(), None)
+        .with_context(|| format!("list clusters of {provider_name}"))?;
+    let mut clusters = Vec::new();
+    let output = String::from_utf8_lossy(&output);
+    for line in output.lines() {
+        let values: Vec<&str> = line.split_whitespace().collect();
+        if values.is_empty() {
+            continue;
+        }
+        if values.len() != provider.fields.len() {
+            warning!(
+                "skip invalid cluster line '{line}' of {provider_name}, expect fields {:?}",
+                provider.fields
+            );
+            continue;
+        }
+        let vars: BTreeMap<&str, &str> = provider
+            .fields
+            .iter()
+            .map(|field| field.as_str())
+            .zip(values)
+            .collect();
+        clusters.push((line.trim().to_string(), vars));
+    }
+
+    if clusters.is_empty() {
+        info!("No cluster found in {provider_name}");
+        return Ok(());
+    }
+    if !opts.dry_run
+        && !opts.interactive
+        && !confirm(format!(
+            "Register {} clusters of {provider_name}",
+            clusters.len()
+        ))?
+    {
+        return Ok(());
+    }
+
+    let mut report = ImportReport::default();
+    let mut items = Vec::with_capacity(clusters.len());
+    for (source, vars) in clusters {
+        let tags = get_discover_tags(provider_name, &vars);
+        let name = render_discover_template(&provider.name, &vars);
+        let name = match with_prefix(sanitize_name(&name), opts.prefix) {
+            Ok(name) => name,
+            Err(err) => {
+                warning!("failed to import {source}: {err:#}");
+                report.failed += 1;
+                continue;
+            }
+        };
+        // The kubeconfig is only generated when it is going to be written.
+        if opts.dry_run {
+            items.push(ImportItem {
+                source,
+                name,
+                content: String::new(),
+                tags,
+            });
+            continue;
+        }
+        match generate_kubeconfig(provider, &vars) {
+            Ok(content) => items.push(ImportItem {
+                source,
+                name,
+                content,
+                tags,
+            }),
+            Err(err) => {
+                warning!("failed to import {source}: {err:#}");
+                report.failed += 1;
+            }
+        }
+    }
+
+    run_import(cfg, items, opts, report)
+}
+
+/// The tags of the discovered context, for `ks list` to group the contexts by
+/// provider, account and region. The values not valid for tags are skipped.
+fn get_discover_tags(provider_name: &str, vars: &BTreeMap<&str, &str>) -> BTreeMap<String, String> {
+    let mut tags = BTreeMap::new();
+    tags.insert(
+        String::from(DiscoverProvider::PROVIDER_TAG),
+        provider_name.to_string(),
+    );
+    for (field, value) in vars.iter() {
+        if *field == DiscoverProvider::CLUSTER_FIELD {
+            continue;
+        }
+        if let Ok((key, value)) = parse_tag(&format!("{field}={value}")) {
+            tags.insert(key, value);
+        }
+    }
+    tags
+}
+
+/// Generate the kubeconfig of the cluster into a private temp file, and minify it
+/// to the generated context.
+fn generate_kubeconfig(provider: &DiscoverProvider, vars: &BTreeMap<&str, &str>) -> Result<String> {
+    let dir = get_runtime_dir().join("discover");
+    create_private_dir(&dir)?;
+    let path = dir.join(format!("{}.yaml", std::process::id()));
+    // The provider command might merge into the existing file.
+    let _ = fs::remove_file(&path);
+
+    let path_str = format!("{}", path.display());
+    let mut vars = vars.clone();
+    vars.insert(DiscoverProvider::KUBECONFIG_FIELD, &path_str);
+    let result = run_discover_command(&provider.kubeconfig, &vars, Some(&path))
+        .and_then(|_| KubeconfigDoc::read(&path))
+        .and_then(|doc| doc.minify(&doc.main_context()?));
+    let _ = fs::remove_file(&path);
+    result
+}
+
+/// Run the command template with `sh`, the values are quoted since the cluster
+/// names come from the provider. Return the stdout.
+fn run_discover_command(
+    template: &str,
+    vars: &BTreeMap<&str, &str>,
+    kubeconfig: Option<&Path>,
+) -> Result<Vec<u8>> {
+    let quoted: BTreeMap<&str, String> = vars
+        .iter()
+        .map(|(key, value)| (*key, quote_shell(value)))
+        .collect();
+    let script = render_discover_template(template, &quoted);
+
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", &script]);
+    if let Some(kubeconfig) = kubeconfig {
+        cmd.env("KUBECONFIG", kubeconfig);
+    }
+    cmd.stdin(Stdio::inherit());
+    cmd.stderr(Stdio::inherit());
+    let output = cmd
+        .output()
+        .with_context(|| format!("execute command '{script}'"))?;
+    if !output.status.success() {
+        bail!("command '{script}' failed with {}", output.status);
+    }
+    Ok(output.stdout)
+}
+
+/// Replace the `{field}` placeholders in the template.
+fn render_discover_template<V: AsRef<str>>(template: &str, vars: &BTreeMap<&str, V>) -> String {
+    let mut result = template.to_string();
+    for (key, value) in vars.iter() {
+        result = result.replace(&format!("{{{key}}}"), value.as_ref());
+    }
+    result
+}
+
+fn read_with_sudo(path: &Path) -> Result<Vec<u8>> {
+    let output = Command::new("sudo")
+        .arg("cat")
+        .arg(path)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .context("execute sudo command")?;
+    if !output.status.success() {
+        bail!("read '{}' with sudo failed", path.display());
+    }
+    Ok(output.stdout)
+}
+
+fn run_import(
+    cfg: &Config,
+    items: Vec<ImportItem>,
+    opts: &ImportOptions,
+    mut report: ImportReport,
+) -> Result<()> {
+    let mut names = HashSet::new();
+    let mut tagged = Vec::new();
+    for mut item in items {
+        if opts.interactive {
+            item.name = match ask_name(&item.source, &item.name)? {
+                Some(name) => name,
+                None => {
+                    info!("Skipped {}", item.source);
+                    report.skipped += 1;
+                    continue;
+                }
+            };
+        }
+        if !names.insert(item.name.clone()) {
+            warning!(
+                "failed to import {}: name '{}' is duplicated with another context",
+                item.source,
+                item.name
+            );
+            report.failed += 1;
+            continue;
+        }
+
+        if opts.dry_run {
+            let plan = describe_plan(cfg, &item.name, opts.conflict);
+            println!("{} -> {} ({plan})", item.source, item.name);
+            continue;
+        }
+
+        match install(cfg, &item.name, &item.content, opts.conflict) {
+            Ok(Some(name)) => {
+                info!("Imported {} -> {name}", item.source);
+                report.imported += 1;
+                if !item.tags.is_empty() {
+                    tagged.push((name, item.tags));
+                }
+            }
+            Ok(None) => {
+                info!("Skipped {}, the context already exists", item.source);
+                report.skipped += 1;
+            }
+            Err(err) => {
+                warning!("failed to import {}: {err:#}", item.source);
+                report.failed += 1;
+            }
+        }
+    }
+
+    if opts.dry_run {
+        info!("Dry run, nothing was written");
+        return Ok(());
+    }
+    if !tagged.is_empty() {
+        let mut tags = Tags::load()?;
+        for (name, context_tags) in tagged {
+            for (key, value) in context_tags {
+                tags.set(&name, key, value);
+            }
+        }
+        tags.save()?;
+    }
+    info!(
+        "Imported {}, skipped {}, failed {}",
+        report.imported, report.skipped, report.failed
+    );
+    Ok(())
+}
+
+/// Describe what will happen when installing the context, for dry run.
+fn describe_plan(cfg: &Config, name: &str, conflict: ImportConflict) -> String {
+    if fs::symlink_metadata(get_kubeconfig_store_path(cfg, name)).is_err() {
+        return format!("new, {}", get_kubeconfig_write_path(cfg, name).display());
+    }
+    match conflict {
+        ImportConflict::Skip => String::from("skip, already exists"),
+        ImportConflict::Overwrite => format!(
+            "overwrite, {}",
+            get_kubeconfig_write_path(cfg, name).display()
+        ),
+        ImportConflict::Rename => {
+            let name = find_free_name(cfg, name);
+            let path = get_kubeconfig_write_path(cfg, &name);
+            format!("rename to {name}, {}", path.display())
+        }
+        ImportConflict::Prompt => String::from("already exists, will ask"),
+    }
+}
+
+fn with_prefix(name: String, prefix: Option<&str>) -> Result<String> {
+    if name.is_empty() {
+        bail!("cannot derive a valid context name");
+    }
+    Ok(match prefix {
+        Some(prefix) => format!("{}/{name}", prefix.trim_matches('/')),
+        None => name,
+    })
+}
+
+/// Resolve the context name and the minified content of the kubeconfig file.
+fn resolve_file(
+    path: &Path,
+    naming: ImportNaming,
+    prefix: Option<&str>,
+) -> Result<(String, String)> {
+    let doc = KubeconfigDoc::read(path)?;
+    let context = doc.main_context()?;
+    let content = doc.minify(&context)?;
+
+    let name = match naming {
+        ImportNaming::File => {
+            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+            let stem = match path.extension().and_then(|s| s.to_str()) {
+                Some("yaml" | "yml" | "conf" | "config" | "kubeconfig") => stem,
+                _ => path.file_name().and_then(|s| s.to_str()).unwrap_or(""),
+            };
+            sanitize_name(stem)
+        }
+        ImportNaming::Context => sanitize_name(&context),
+    };
+    Ok((with_prefix(name, prefix)?, content))
+}
+
+/// Ask user for the name of the imported context, return `None` to skip it.
+fn ask_name(source: &str, name: &str) -> Result<Option<String>> {
+    loop {
+        eprint!("Name for {source} [{name}] ('-' to skip): ");
+        let mut answer = String::new();
+        io::stdin()
+            .read_line(&mut answer)
+            .context("ask name: read terminal stdin")?;
+        let answer = answer.trim();
+        match answer {
+            "" => return Ok(Some(name.to_string())),
+            "-" => return Ok(None),
+            _ if sanitize_name(answer) == answer => return Ok(Some(answer.to_string())),
+            _ => warning!("invalid context name '{answer}', please try again"),
+        }
+    }
+}
+
+fn find_free_name(cfg: &Config, name: &str) -> String {
+    let mut idx = 2;
+    loop {
+        let candidate = format!("{name}-{idx}");
+        if fs::symlink_metadata(get_kubeconfig_store_path(cfg, &candidate)).is_err() {
+            return candidate;
+        }
+        idx += 1;
+    }
+}
+
+fn ask_conflict(name: &str) -> Result<ImportConflict> {
+    eprint!("Context '{name}' already exists, [o]verwrite, [r]ename or [s]kip? ");
+
+    let mut answer = String::new();
+    scanf::scanf!("{}", answer).context("ask conflict: scan terminal stdin")?;
+    match answer.trim().to_lowercase().as_str() {
+        "o" | "overwrite" => Ok(ImportConflict::Overwrite),
+        "r" | "rename" => Ok(ImportConflict::Rename),
+        _ => Ok(ImportConflict::Skip),
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+
+use crate::config::Config;
+use crate::context::{confirm, KubeContext};
+use crate::vault;
+
+/// Check the context names against the `naming` convention, and suggest the
+/// canonical names. With `fix`, the violating contexts are renamed to their
+/// suggested names after confirmation.
+pub fn lint_names(cfg: &Config, fix: bool) -> Result<()> {
+    let naming = match cfg.naming.as_ref() {
+        Some(naming) => naming,
+        None => bail!("`naming` is not configured"),
+    };
+
+    let ctxs = KubeContext::list(cfg)?;
+    let mut names: HashSet<String> = ctxs.iter().map(|ctx| ctx.name.clone()).collect();
+    let mut renames = Vec::new();
+    let mut violations = 0;
+    for ctx in ctxs {
+        if naming.is_match(&ctx.name) {
+            continue;
+        }
+        violations += 1;
+
+        // The report goes to stderr, the stdout is kept for the switch protocol
+        // when the current context is renamed.
+        let new_name = match naming.suggest(&ctx.name) {
+            Some(new_name) => new_name,
+            None => {
+                eprintln!("{}: no fix", ctx.name);
+                continue;
+            }
+        };
+        if vault::lookup(cfg, &ctx.name).is_some() {
+            eprintln!("{}: rename to '{new_name}' in vault config", ctx.name);
+            continue;
+        }
+        if !ctx.is_in_write_dir() {
+            eprintln!("{}: rename to '{new_name}' in the shared dir", ctx.name);
+            continue;
+        }
+        // Two contexts might be fixed to the same name.
+        if !names.insert(new_name.clone()) {
+            eprintln!("{}: no fix, '{new_name}' is taken", ctx.name);
+            continue;
+        }
+        eprintln!("{}: rename to '{new_name}'", ctx.name);
+        renames.push((ctx, new_name));
+    }
+
+    if violations == 0 {
+        info!("All the context names follow the naming convention");
+        return Ok(());
+    }
+    if !fix || renames.is_empty() {
+        bail!("{violations} context names violate the naming convention");
+    }
+    if !confirm(format!("Rename {} contexts", renames.len()))? {
+        return Ok(());
+    }
+
+    // The current context goes last, renaming it prints the switch protocol.
+    renames.sort_by_key(|(ctx, _)| ctx.current);
+    let mut failed = 0;
+    for (ctx, new_name) in renames {
+        let name = ctx.name.clone();
+        if let Err(err) = ctx.rename(&new_name) {
+            warning!("rename context '{name}': {err:#}");
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        bail!("failed to rename {failed} contexts");
+    }
+    Ok(())
+}
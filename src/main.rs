@@ -1,353 +1,1860 @@
+#[macro_use]
+mod output;
+
+mod backup;
+mod bench;
+mod cache;
+mod changes;
+mod complete;
 mod config;
 mod context;
+mod daemon;
+mod diff;
+mod encryption;
+mod glob;
+mod history;
+mod ignore;
+mod import;
+mod lint;
+mod merge;
+mod pin;
+mod process;
+mod prune;
+mod selector;
+mod selftest;
+mod session;
+mod share;
+mod sync;
+mod tags;
+mod tombstone;
+mod vault;
 
 use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
 
 use anyhow::{bail, Context, Result};
-use clap::{CommandFactory, Parser, ValueEnum};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum, ValueHint};
 use regex::Regex;
 use serde::Serialize;
 
-use crate::config::Config;
-use crate::context::{KubeContext, SelectOption};
+use crate::config::{
+    Config, ContextSort, DiscoverProvider, ImportConflict, ImportNaming, KubeDirIsFile,
+    NamespaceCheck, NAME_REGEX,
+};
+use crate::context::{KubeContext, Resolution, SelectOption};
+use crate::history::History;
 
 #[derive(Parser, Debug)]
 #[command(author, about)]
-#[command(disable_help_flag = true)]
 #[command(disable_version_flag = true)]
 struct Args {
-    /// The context or namespace name, respect to `-n` flag.
+    /// The context to switch to, the same as the `switch` command. Use `.` to
+    /// launch the selector.
     name: Option<String>,
 
-    /// Edit mode, edit context's kubeconfig file in editor.
-    #[clap(long, short)]
+    /// The namespace to switch to along with the context, the same as appending the
+    /// namespace separator and the namespace to the name. Without NAME, switch
+    /// namespace of the current context, the same as the `ns` command.
+    #[clap(long, short, num_args = 0..=1, default_missing_value = "")]
+    namespace: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// Offline mode, never reach the network, only serve cached and local data.
+    #[clap(long, global = true)]
+    offline: bool,
+
+    /// Suppress warnings, they are printed to stderr by default.
+    #[clap(long, short, global = true)]
+    quiet: bool,
+
+    /// Log the external commands, config resolution and kube dir walking to
+    /// stderr, repeat for more details. Can also be enabled by the `KUBESWITCH_LOG`
+    /// env, such as `KUBESWITCH_LOG=debug`.
+    #[clap(long, short, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Only list and select the contexts with the tag, in `key=value` format. Can be
+    /// repeated, all of them should match.
+    #[clap(long, short, global = true, value_parser = tags::parse_tag)]
+    tag: Vec<(String, String)>,
+
+    /// Answer yes to all the confirmations, such as deleting. The typing of
+    /// protected context name is still required. Can also be enabled by the
+    /// `KUBESWITCH_ASSUME_YES` env.
+    #[clap(long, short, global = true)]
+    yes: bool,
+
+    /// Never prompt, error out instead of launching fzf when the name does not
+    /// resolve to exactly one context, for automation.
+    #[clap(long, global = true)]
+    no_interactive: bool,
+
+    /// Keep all the state, such as history, tags, caches and sessions, in this dir
+    /// instead of the user's, for CI jobs and tests. Can also be set by the
+    /// `KUBESWITCH_STATE_DIR` env.
+    #[clap(long, global = true, value_hint = ValueHint::DirPath)]
+    state_dir: Option<PathBuf>,
+
+    /// Select the item at the index instead of launching fzf, for scripts and tests.
+    #[clap(long, hide = true, global = true)]
+    select_index: Option<usize>,
+
+    // The flags of the old releases, deprecated by the commands. They are kept so
+    // that the init lines in shell profiles and the scripts keep working, see
+    // `legacy_command`.
+    #[clap(long, hide = true)]
+    init: Option<Shell>,
+
+    #[clap(long, hide = true, default_value = "kubeswitch")]
+    wrap: String,
+
+    #[clap(long, hide = true)]
+    comp: bool,
+
+    #[clap(long, short, hide = true)]
+    list: bool,
+
+    #[clap(long, short, hide = true)]
     edit: bool,
 
-    /// Delete the context, its kubeconfig file will be deleted.
-    #[clap(long, short)]
+    #[clap(long, short, hide = true)]
     delete: bool,
 
+    #[clap(long, short, hide = true)]
+    show: bool,
+
+    #[clap(long, short, hide = true)]
+    unset: bool,
+
+    #[clap(long, hide = true)]
+    link: bool,
+
+    // The old `-v` is taken by `--verbose`, the bare `ks -v` (not `ks --verbose`)
+    // is still taken as `--version`.
+    #[clap(long, hide = true)]
+    version: bool,
+
+    #[clap(long, hide = true)]
+    build: bool,
+
+    #[clap(long, hide = true)]
+    show_config: bool,
+
+    #[clap(last = true, hide = true)]
+    comp_args: Vec<String>,
+}
+
+/// Map the deprecated flags to the commands, with a warning about the command to
+/// use instead.
+fn legacy_command(args: &mut Args) -> Result<Option<Commands>> {
+    if args.comp {
+        // Called by the completion of the init script from the old releases, it
+        // stays silent so as not to garble the command line.
+        let args = std::mem::take(&mut args.comp_args);
+        return Ok(Some(Commands::Complete { shell: None, args }));
+    }
+
+    let (flag, instead, command) = if let Some(shell) = args.init.take() {
+        let wrap = std::mem::take(&mut args.wrap);
+        ("--init", "init", Commands::Init { shell, wrap })
+    } else if args.list {
+        let command = Commands::List {
+            output: OutputFormat::Text,
+            sort: None,
+            no_trunc: false,
+        };
+        ("-l", "list", command)
+    } else if args.edit {
+        let command = Commands::Edit {
+            name: args.name.take(),
+            from_file: None,
+        };
+        ("-e", "edit", command)
+    } else if args.delete {
+        let name = args.name.take();
+        ("-d", "delete", Commands::Delete { name })
+    } else if args.show {
+        let command = Commands::Show {
+            output: OutputFormat::Text,
+        };
+        ("-s", "show", command)
+    } else if args.unset {
+        ("-u", "unset", Commands::Unset)
+    } else if args.link {
+        let name = args.name.take().unwrap_or_default();
+        let (source, dest) = match name.split_once(':') {
+            Some((source, dest)) => (source.to_string(), dest.to_string()),
+            None => bail!("invalid link '{name}', should be in '{{source}}:{{dest}}' format"),
+        };
+        ("--link", "link", Commands::Link { source, dest })
+    } else if args.namespace.as_deref() == Some("") {
+        // The bare `-n` was a switch, the NAME was the namespace.
+        args.namespace = None;
+        let name = args.name.take();
+        ("-n", "ns", Commands::Ns { name })
+    } else if args.version {
+        let command = Commands::Version {
+            build: false,
+            self_test: false,
+        };
+        ("--version", "version", command)
+    } else if args.verbose == 1
+        && args.name.is_none()
+        && args.namespace.is_none()
+        && args.command.is_none()
+        // The count cannot tell `-v` from `--verbose`, only the former was version.
+        && env::args().skip(1).any(|arg| arg == "-v")
+    {
+        args.verbose = 0;
+        let command = Commands::Version {
+            build: false,
+            self_test: false,
+        };
+        ("-v", "version", command)
+    } else if args.build {
+        let command = Commands::Version {
+            build: true,
+            self_test: false,
+        };
+        ("--build", "version --build", command)
+    } else if args.show_config {
+        ("--show-config", "config", Commands::Config)
+    } else {
+        return Ok(None);
+    };
+    if args.name.is_some() {
+        bail!("cannot use NAME with `{flag}`");
+    }
+    if args.namespace.is_some() {
+        bail!("cannot use `--namespace` with `{flag}`");
+    }
+
+    warning!("`{flag}` is deprecated, use `ks {instead}` instead");
+    Ok(Some(command))
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Switch to a context, this is the default command.
+    Switch {
+        /// The context name, select from all contexts if omitted.
+        name: Option<String>,
+
+        /// The namespace to switch to along with the context, the same as appending
+        /// the namespace separator and the namespace to the name.
+        #[clap(long, short)]
+        namespace: Option<String>,
+    },
+
+    /// Switch namespace of the current context.
+    Ns {
+        /// The namespace name, select from the namespaces if omitted.
+        name: Option<String>,
+    },
+
+    /// Select a context and print its name without switching, for scripts.
+    Pick {
+        /// Select from the contexts under the dir, in `dir/` format.
+        dir: Option<String>,
+    },
+
+    /// Select a namespace of the context and print it without switching, for
+    /// scripts.
+    PickNs {
+        /// The context name, use the current context if omitted.
+        name: Option<String>,
+    },
+
+    /// Select from the recent contexts and namespaces in history to switch to.
+    History {
+        /// The max number of the recent items to select from. Default is 10 for
+        /// switching and all for deleting.
+        #[clap(long, short)]
+        limit: Option<usize>,
+
+        /// Mark the entries to delete from history instead of switching.
+        #[clap(long, short)]
+        delete: bool,
+    },
+
     /// List contexts.
-    #[clap(long, short)]
-    list: bool,
+    List {
+        /// The output format.
+        #[clap(long, short, default_value = "text")]
+        output: OutputFormat,
+
+        /// The order of the contexts, default is the `sort` config.
+        #[clap(long, short)]
+        sort: Option<ContextSort>,
+
+        /// Do not truncate the names and namespaces to fit in the terminal width.
+        #[clap(long)]
+        no_trunc: bool,
+    },
+
+    /// Show how often each context and its namespaces were used, counted from
+    /// history.
+    Stats,
 
     /// Show current context.
-    #[clap(long, short)]
-    show: bool,
+    Show {
+        /// The output format.
+        #[clap(long, short, default_value = "text")]
+        output: OutputFormat,
+    },
 
-    /// Switch namespace rather than kubeconfig, if enabled, the meaning of NAME changes
-    /// to namespace.
-    #[clap(long, short)]
-    namespace: bool,
+    /// Show the details of the context, such as the cluster, auth type and recent
+    /// usage, without opening the kubeconfig.
+    Describe {
+        /// The context name, use the current context if omitted.
+        name: Option<String>,
+    },
 
-    /// Show help about the command.
-    #[clap(long, short)]
-    help: bool,
+    /// Show how the name resolves to the context without switching, such as the
+    /// history, a dir, a symlink context, a renamed context or a fuzzy match, in the
+    /// same order as the other commands.
+    Resolve {
+        /// The name to resolve, can be followed by the namespace separator and the
+        /// namespace.
+        query: String,
+    },
 
-    /// Show build info.
-    #[clap(long)]
-    build: bool,
+    /// Print the current context for shell prompt in `prompt_format`, colored by
+    /// `prompt_hint`. Print nothing if there is no current context.
+    Prompt {
+        /// Wrap the color codes with the non-printing marks of the shell, so that
+        /// the prompt width is calculated correctly.
+        #[clap(long, short)]
+        shell: Option<Shell>,
 
-    /// Create a symbol link context, the format is "{source}:{dest}".
-    #[clap(long)]
-    link: bool,
+        /// Do not color the output.
+        #[clap(long)]
+        no_color: bool,
+    },
 
-    /// Show version
-    #[clap(long, short)]
-    version: bool,
+    /// Edit context's kubeconfig file in editor, then switch to it.
+    Edit {
+        /// The context name, a new context will be created if it does not exist.
+        name: Option<String>,
+
+        /// Replace the kubeconfig with the content of the file instead of opening
+        /// the editor, `-` to read from stdin. For scripts and CI jobs.
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        from_file: Option<String>,
+    },
+
+    /// Delete the context, its kubeconfig file will be deleted.
+    Delete {
+        /// The context name. All the contexts under the dir are deleted if it is in
+        /// `dir/` format, or the ones matching it if it is a glob pattern, such as
+        /// `staging-*`, whose wildcards do not match `/`.
+        name: Option<String>,
+    },
+
+    /// Rename the context, its history and symlink contexts are updated too.
+    Rename {
+        /// The context name.
+        name: String,
+
+        /// The new context name.
+        new_name: String,
+    },
+
+    /// Restore the kubeconfig of the context from its latest backup. The backups
+    /// are made before editing, switching namespace and overwriting by import.
+    Revert {
+        /// The context name, use the current context if omitted.
+        name: Option<String>,
+    },
+
+    /// Copy the context to a new one.
+    Copy {
+        /// The source context name.
+        source: String,
+
+        /// The new context name.
+        dest: String,
+    },
+
+    /// Export the contexts as standalone kubeconfigs to send to teammates. A single
+    /// context is printed to stdout, multiple contexts require `--bundle`.
+    Share {
+        /// The context names.
+        #[clap(required = true)]
+        names: Vec<String>,
+
+        /// Remove the credentials, such as tokens and client keys. The teammate
+        /// logs in with their own credentials.
+        #[clap(long)]
+        redact: bool,
+
+        /// Write the contexts to the tar file.
+        #[clap(long, value_hint = ValueHint::FilePath)]
+        bundle: Option<String>,
+    },
+
+    /// Merge the contexts into one kubeconfig and export it as KUBECONFIG, for the
+    /// tools working across clusters. Switch to a single context to go back.
+    Merge {
+        /// The context names, can be separated by ','. The name ending with '/'
+        /// means all the contexts under the dir.
+        #[clap(required = true)]
+        names: Vec<String>,
+    },
+
+    /// Show or change the tags of the context. Use `key=value` to set a tag, and
+    /// `key-` to remove it.
+    Tag {
+        /// The context name.
+        name: String,
+
+        /// The tags to set or remove.
+        tags: Vec<String>,
+    },
+
+    /// Unset the current context.
+    Unset,
+
+    /// Create a symbol link context.
+    Link {
+        /// The source context name.
+        source: String,
+
+        /// The link context name.
+        dest: String,
+    },
+
+    /// Pin the context to the current dir by writing the `.kubeswitch` file, it is
+    /// switched to when entering the dir if `auto_switch` is enabled.
+    Pin {
+        /// The context name, can be followed by the namespace separator and the
+        /// namespace.
+        name: String,
+    },
+
+    /// Review and trust the `.kubeswitch` file of the current dir, only the trusted
+    /// ones are switched to automatically. Changing the file revokes the trust.
+    Trust,
+
+    /// Spawn a subshell for the context, the current shell won't be touched.
+    Shell {
+        /// The context name.
+        name: Option<String>,
+    },
+
+    /// Run a command against the context, the current shell won't be touched, such
+    /// as `ks exec prod -- kubectl get pods`.
+    Exec {
+        /// The context name, can be followed by the namespace separator and the
+        /// namespace.
+        name: Option<String>,
+
+        /// Record the context in history.
+        #[clap(long)]
+        history: bool,
+
+        /// The command to run.
+        #[clap(last = true, required = true)]
+        args: Vec<String>,
+    },
+
+    /// Split a merged kubeconfig (such as ~/.kube/config) into per-context files,
+    /// every context is named after itself.
+    Import {
+        /// The kubeconfig file to import.
+        #[clap(value_hint = ValueHint::FilePath)]
+        path: String,
+
+        #[command(flatten)]
+        opts: ImportArgs,
+    },
+
+    /// Import all the kubeconfig files under the directory.
+    ImportDir {
+        /// The directory to import.
+        #[clap(value_hint = ValueHint::DirPath)]
+        dir: String,
+
+        /// How to name the imported contexts, default is `import.naming` in config.
+        #[clap(long)]
+        naming: Option<ImportNaming>,
+
+        #[command(flatten)]
+        opts: ImportArgs,
+    },
+
+    /// Discover the clusters of the cloud provider (such as eks, gke and aks) with
+    /// its CLI, and import them by the commands in `discover` config.
+    Discover {
+        /// The provider name in `discover` config.
+        provider: String,
+
+        #[command(flatten)]
+        opts: ImportArgs,
+    },
+
+    /// Detect the local clusters (k3s, microk8s, docker-desktop and rancher-desktop)
+    /// on this machine, and import them under `local/` by default.
+    DetectLocal {
+        #[command(flatten)]
+        opts: ImportArgs,
+    },
+
+    /// Check if the contexts can reach their clusters, in parallel.
+    Check {
+        /// The context name, or the dir ending with '/', check all contexts if
+        /// omitted.
+        name: Option<String>,
+
+        /// The request timeout in seconds for each context.
+        #[clap(long, default_value = "5")]
+        timeout: u64,
+    },
+
+    /// Check the context names against the naming convention in config (`naming`),
+    /// and suggest the canonical names.
+    Lint {
+        /// Rename the violating contexts to the suggested names.
+        #[clap(long)]
+        fix: bool,
+    },
+
+    /// Remove the broken symlinks, empty dirs, and the history of removed contexts.
+    Prune {
+        /// Only show what would be removed.
+        #[clap(long)]
+        dry_run: bool,
+    },
+
+    /// Refresh the discovered contexts, warm the namespace caches and check the
+    /// certificate expiry periodically, according to `daemon` config. It runs in
+    /// foreground, suitable for a systemd user service.
+    Daemon,
+
+    /// Show the contexts changed since the last time you looked, such as imported,
+    /// refreshed or edited externally.
+    Changes,
+
+    /// Measure the timings of scanning the kube dirs, parsing the kubeconfig files,
+    /// reading history and completing, to tune the config and catch slowdowns.
+    Bench {
+        /// How many times to run each step.
+        #[clap(long, short, default_value = "5")]
+        runs: usize,
+
+        /// The output format.
+        #[clap(long, short, default_value = "text")]
+        output: OutputFormat,
+    },
+
+    /// Sync `kube.dir` with the git remote in `sync` config, to share the contexts
+    /// across machines.
+    Sync { action: SyncAction },
+
+    /// Print the init script, please add `eval "$(kubeswitch init <shell-type>)"` to
+    /// your shell profile (etc. ~/.zshrc), or `kubeswitch init fish | source` for fish.
+    Init {
+        shell: Shell,
+
+        /// The wrap target command, change it when your kubeswitch has a different
+        /// name or not placed in $PATH.
+        #[clap(long, default_value = "kubeswitch")]
+        wrap: String,
+    },
+
+    /// Write the standalone completion files for all the supported shells, so that
+    /// they can be loaded lazily by shell.
+    InstallCompletions {
+        /// The dir to write, default is the user completion dir of each shell.
+        #[clap(value_hint = ValueHint::DirPath)]
+        dir: Option<String>,
+    },
 
     /// Show applied config in json.
-    #[clap(long)]
-    show_config: bool,
+    Config,
+
+    /// Show version.
+    Version {
+        /// Show build info.
+        #[clap(long)]
+        build: bool,
+
+        /// Also verify the runtime assumptions, such as spawning commands, DNS
+        /// resolution and locale, to diagnose the static build in containers.
+        #[clap(long, requires = "build")]
+        self_test: bool,
+    },
+
+    /// Check if the current namespace still exists, according to the cached
+    /// namespaces. Emitted by `init` when `namespace.check` is enabled.
+    #[command(hide = true)]
+    CheckNamespace,
+
+    /// Switch to the context pinned to the current dir. Emitted by `init` when
+    /// `auto_switch` is enabled.
+    #[command(hide = true)]
+    Auto,
+
+    /// Show the details of the context for the selector preview.
+    #[command(hide = true)]
+    PreviewItem { name: String },
+
+    /// The git clean filter to redact the credentials, when `sync.secrets` is
+    /// `redact`.
+    #[command(hide = true)]
+    SyncFilter,
 
     /// Generate completion items. PLEASE DONOT USE DIRECTLY.
+    #[command(hide = true)]
+    Complete {
+        /// Print the items with descriptions in the format of the shell.
+        #[clap(long)]
+        shell: Option<Shell>,
+
+        /// The words to complete, the last one is the word under cursor.
+        #[clap(last = true)]
+        args: Vec<String>,
+    },
+}
+
+/// The args shared by all the import commands.
+#[derive(clap::Args, Debug)]
+struct ImportArgs {
+    /// The optional target directory under `kube.dir`.
+    prefix: Option<String>,
+
+    /// What to do when the imported context already exists, default is
+    /// `import.conflict` in config.
     #[clap(long)]
-    comp: bool,
+    on_conflict: Option<ImportConflict>,
 
-    /// Unset the current context.
+    /// Only show the resolved names and paths, do not write anything.
+    #[clap(long)]
+    dry_run: bool,
+
+    /// Adjust the name of every context before importing.
     #[clap(long, short)]
-    unset: bool,
+    interactive: bool,
+}
 
-    /// Print the init script, please add `kubeswitch --init <shell-type>` to your
-    /// shell profile (etc. ~/.zshrc).
-    #[clap(long)]
-    init: Option<Shell>,
+impl ImportArgs {
+    fn build<'a>(&'a self, cfg: &Config) -> import::ImportOptions<'a> {
+        import::ImportOptions {
+            prefix: self.prefix.as_deref(),
+            conflict: self.on_conflict.unwrap_or(cfg.import.conflict),
+            dry_run: self.dry_run,
+            interactive: self.interactive,
+        }
+    }
+}
 
-    /// The wrap target command, change it when your kubeswitch has a different name
-    /// or not placed in $PATH.
-    #[clap(long, default_value = "kubeswitch")]
-    wrap: String,
+#[derive(Debug, Clone, ValueEnum)]
+enum SyncAction {
+    /// Commit the local changes and push them to the remote.
+    Push,
+    /// Merge the remote changes and show the changed contexts.
+    Pull,
+}
 
-    /// The completion args. PLEASE DONOT USE DIRECTLY.
-    #[clap(last = true)]
-    comp_args: Option<Vec<String>>,
+#[derive(Debug, Clone, ValueEnum)]
+enum OutputFormat {
+    /// The human-oriented format.
+    Text,
+    Json,
+    Yaml,
+    /// Only the context names.
+    Name,
+}
+
+/// The context record for the structured output.
+#[derive(Serialize)]
+struct ContextRecord {
+    name: String,
+    namespace: String,
+    current: bool,
+    link: Option<String>,
+    path: String,
+    last_used: Option<u64>,
+    tags: BTreeMap<String, String>,
+    /// The earliest certificate expiry checked by `ks daemon`.
+    cert_expire: Option<u64>,
+}
+
+impl ContextRecord {
+    fn build(cfg: &Config, ctxs: &[KubeContext]) -> Vec<ContextRecord> {
+        let last_used = history::last_used(cfg, ctxs);
+        // The tags and cert cache are optional here, ignore the error.
+        let tags = tags::Tags::load().ok();
+        let cert_expires = cache::CertCache::read().unwrap_or_default();
+        ctxs.iter()
+            .map(|ctx| ContextRecord {
+                name: ctx.name.clone(),
+                namespace: ctx.namespace.to_string(),
+                current: ctx.current,
+                link: ctx.link.clone(),
+                path: format!("{}", ctx.get_path().display()),
+                last_used: last_used.get(&ctx.name).copied(),
+                tags: tags
+                    .as_ref()
+                    .and_then(|tags| tags.get(&ctx.name))
+                    .cloned()
+                    .unwrap_or_default(),
+                cert_expire: cert_expires.get(&ctx.name).copied(),
+            })
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, ValueEnum)]
 pub enum Shell {
     Bash,
     Zsh,
+    Fish,
 }
 
-impl Args {
+impl Commands {
     fn run(&self, cfg: &Config) -> Result<()> {
-        if self.edit {
-            return self.run_edit(cfg);
+        match self {
+            Self::Switch { name, namespace } => run_switch(cfg, name, namespace),
+            Self::Ns { name } => run_namespace(cfg, name),
+            Self::History {
+                limit,
+                delete: true,
+            } => History::delete_interactive(cfg, limit.unwrap_or(0)),
+            Self::History { limit, .. } => {
+                let mut ctx = KubeContext::select_from_history(cfg, limit.unwrap_or(10))?;
+                ctx.materialize()?;
+                ctx.switch()
+            }
+            Self::Pick { dir } => run_pick(cfg, dir),
+            Self::PickNs { name } => {
+                let ctx = KubeContext::select(cfg, name, SelectOption::GetRequired)?;
+                println!("{}", ctx.pick_namespace()?);
+                Ok(())
+            }
+            Self::List {
+                output,
+                sort,
+                no_trunc,
+            } => run_list(cfg, output, sort.unwrap_or(cfg.sort), *no_trunc),
+            Self::Stats => run_stats(cfg),
+            Self::Show { output } => run_show(cfg, output),
+            Self::Prompt { shell, no_color } => show_prompt(cfg, shell, *no_color),
+            Self::Describe { name } => {
+                let ctx = KubeContext::select(cfg, name, SelectOption::GetRequired)?;
+                print_details(&ctx.describe(true));
+                Ok(())
+            }
+            Self::Resolve { query } => run_resolve(cfg, query),
+            Self::Edit { name, from_file } => run_edit(cfg, name, from_file),
+            Self::Delete { name } => run_delete(cfg, name),
+            Self::Rename { name, new_name } => {
+                let ctx = KubeContext::select(cfg, &Some(name.clone()), SelectOption::GetRequired)?;
+                ctx.rename(new_name)
+            }
+            Self::Revert { name } => {
+                let ctx = KubeContext::select(cfg, name, SelectOption::GetRequired)?;
+                backup::revert(&ctx)
+            }
+            Self::Copy { source, dest } => {
+                let ctx =
+                    KubeContext::select(cfg, &Some(source.clone()), SelectOption::GetRequired)?;
+                ctx.copy(dest)
+            }
+            Self::Tag { name, tags } => run_tag(cfg, name, tags),
+            Self::Unset => {
+                let ctx = KubeContext::current(cfg)?;
+                ctx.unset()
+            }
+            Self::Link { source, dest } => context::create_symlink(cfg, source, dest),
+            Self::Pin { name } => pin::pin(cfg, name),
+            Self::Trust => pin::trust(cfg),
+            Self::Shell { name } => run_shell(cfg, name),
+            Self::Exec {
+                name,
+                history,
+                args,
+            } => run_exec(cfg, name, *history, args),
+            Self::Import { path, opts } => import::import_merged(cfg, path, &opts.build(cfg)),
+            Self::ImportDir { dir, naming, opts } => import::import_dir(
+                cfg,
+                dir,
+                naming.unwrap_or(cfg.import.naming),
+                &opts.build(cfg),
+            ),
+            Self::DetectLocal { opts } => import::import_local(cfg, &opts.build(cfg)),
+            Self::Discover { provider, opts } => {
+                import::import_discovered(cfg, provider, &opts.build(cfg))
+            }
+            Self::Check { name, timeout } => run_check(cfg, name, *timeout),
+            Self::Merge { names } => merge::merge(cfg, names),
+            Self::Share {
+                names,
+                redact,
+                bundle,
+            } => share::share(cfg, names, *redact, bundle),
+            Self::Lint { fix } => lint::lint_names(cfg, *fix),
+            Self::Prune { dry_run } => prune::prune(cfg, *dry_run),
+            Self::Changes => run_changes(cfg),
+            Self::Bench { runs, output } => run_bench(cfg, *runs, output),
+            Self::Sync { action } => run_sync(cfg, action),
+            Self::Daemon => daemon::run(cfg),
+            Self::Init { shell, wrap } => {
+                if wrap.is_empty() {
+                    bail!("wrap target cannot be empty");
+                }
+                show_init(cfg, shell, wrap);
+                Ok(())
+            }
+            Self::InstallCompletions { dir } => install_completions(cfg, dir.as_deref()),
+            Self::Config => show_config(cfg),
+            Self::Version { build, self_test } => {
+                if !*build {
+                    show_version(cfg);
+                    return Ok(());
+                }
+                show_build_info(cfg);
+                if *self_test {
+                    run_self_test(cfg)?;
+                }
+                Ok(())
+            }
+            Self::CheckNamespace => run_check_namespace(cfg),
+            Self::Auto => pin::auto(cfg),
+            Self::PreviewItem { name } => show_preview(cfg, name),
+            Self::SyncFilter => sync::filter(),
+            Self::Complete { shell, args } => complete::complete(cfg, shell.as_ref(), args),
         }
-        if self.list {
-            return self.run_list(cfg);
+    }
+
+    /// The context or namespace names from user input, which should be validated.
+    fn names(&self, cfg: &Config) -> Vec<&str> {
+        match self {
+            // The glob pattern is matched against the existing names.
+            Self::Delete { name: Some(name) } if is_glob(name) => vec![],
+            Self::Switch {
+                name,
+                namespace: Some(namespace),
+            } => {
+                let mut names = match name.as_ref().map(|name| (name, cfg.namespace.split(name))) {
+                    Some((_, Some((name, namespace)))) => vec![name, namespace],
+                    Some((name, None)) => vec![name.as_str()],
+                    None => vec![],
+                };
+                names.push(namespace);
+                names
+            }
+            Self::Switch {
+                name: Some(name), ..
+            }
+            | Self::Pin { name }
+            | Self::Exec {
+                name: Some(name), ..
+            } => match cfg.namespace.split(name) {
+                Some((name, namespace)) => vec![name, namespace],
+                None => vec![name],
+            },
+            Self::Switch { name, .. }
+            | Self::Ns { name }
+            | Self::Edit { name, .. }
+            | Self::Delete { name }
+            | Self::Shell { name }
+            | Self::Pick { dir: name }
+            | Self::PickNs { name }
+            | Self::Revert { name }
+            | Self::Describe { name }
+            | Self::Check { name, .. } => name.iter().map(|name| name.as_str()).collect(),
+            Self::Link { source, dest } | Self::Copy { source, dest } => vec![source, dest],
+            Self::Tag { name, .. } => vec![name],
+            Self::Share { names, .. } => names.iter().map(|name| name.as_str()).collect(),
+            Self::Merge { names } => names
+                .iter()
+                .flat_map(|name| name.split(','))
+                .filter(|name| !name.is_empty())
+                .collect(),
+            Self::Rename { name, new_name } => vec![name, new_name],
+            _ => vec![],
         }
-        if self.show {
-            let ctx = KubeContext::current(cfg)?;
-            eprintln!("{ctx}");
-            return Ok(());
+    }
+}
+
+fn run_edit(cfg: &Config, name: &Option<String>, from_file: &Option<String>) -> Result<()> {
+    use std::fs;
+    use std::io::{self, Read};
+
+    let content = match from_file.as_deref() {
+        Some("-") => {
+            let mut content = Vec::new();
+            io::stdin()
+                .read_to_end(&mut content)
+                .context("read kubeconfig from stdin")?;
+            Some(content)
         }
-        if self.delete {
-            return self.run_delete(cfg);
+        Some(path) => {
+            let content =
+                fs::read(path).with_context(|| format!("read kubeconfig file '{path}'"))?;
+            Some(content)
         }
-        if self.unset {
-            let ctx = KubeContext::current(cfg)?;
-            ctx.unset();
-            return Ok(());
+        None => None,
+    };
+
+    let mut ctx = KubeContext::select(cfg, name, SelectOption::GetNotRequired)?;
+    ctx.materialize()?;
+    match content {
+        Some(content) => ctx.replace(&content)?,
+        None => ctx.edit()?,
+    }
+    ctx.switch()
+}
+
+fn run_list(cfg: &Config, output: &OutputFormat, sort: ContextSort, no_trunc: bool) -> Result<()> {
+    let mut ctxs = KubeContext::list(cfg)?;
+    context::sort_contexts(cfg, &mut ctxs, sort);
+    match output {
+        OutputFormat::Text => {
+            let layout = ListLayout::new(no_trunc);
+            // The contexts imported by `ks discover` are grouped under their provider,
+            // account and region, after the other ones. The tags are optional here.
+            let tags = tags::Tags::load().ok();
+            let mut ungrouped: Vec<&KubeContext> = Vec::new();
+            let mut groups: Vec<(String, Vec<&KubeContext>)> = Vec::new();
+            for ctx in ctxs.iter() {
+                let group = tags
+                    .as_ref()
+                    .and_then(|tags| tags.get(&ctx.name))
+                    .and_then(|tags| get_discover_group(cfg, tags));
+                let group = match group {
+                    Some(group) => group,
+                    None => {
+                        ungrouped.push(ctx);
+                        continue;
+                    }
+                };
+                match groups.iter_mut().find(|(name, _)| *name == group) {
+                    Some((_, ctxs)) => ctxs.push(ctx),
+                    None => groups.push((group, vec![ctx])),
+                }
+            }
+            layout.print(&ungrouped, "");
+            for (group, ctxs) in groups {
+                println!("{group}:");
+                layout.print(&ctxs, "  ");
+            }
         }
-        if self.link {
-            return self.run_link(cfg);
+        OutputFormat::Name => {
+            for ctx in ctxs {
+                println!("{}", ctx.name);
+            }
         }
-        if self.namespace {
-            return self.run_namespace(cfg);
+        OutputFormat::Json | OutputFormat::Yaml => {
+            show_records(&ContextRecord::build(cfg, &ctxs), output)?;
         }
+    }
+    Ok(())
+}
+
+/// How to print the contexts of `ks list`. On terminal, the names and namespaces
+/// are aligned in columns, and truncated to fit in its width, the names first. The
+/// piped output keeps one plain context per line for scripts.
+struct ListLayout {
+    terminal: bool,
+    width: Option<usize>,
+}
 
-        self.run_switch(cfg)
+impl ListLayout {
+    /// The marker of the current context and the arrow before the namespace.
+    const DECORATION_WIDTH: usize = 6;
+
+    fn new(no_trunc: bool) -> Self {
+        ListLayout {
+            terminal: io::stdout().is_terminal(),
+            width: if no_trunc {
+                None
+            } else {
+                output::terminal_width()
+            },
+        }
     }
 
-    fn run_edit(&self, cfg: &Config) -> Result<()> {
-        let mut ctx = KubeContext::select(cfg, &self.name, SelectOption::GetNotRequired)?;
-        ctx.edit()?;
-        ctx.switch()
+    fn print(&self, ctxs: &[&KubeContext], indent: &str) {
+        if !self.terminal {
+            for ctx in ctxs {
+                if ctx.current {
+                    println!("{indent}* {ctx}");
+                } else {
+                    println!("{indent}{ctx}");
+                }
+            }
+            return;
+        }
+
+        let names: Vec<_> = ctxs
+            .iter()
+            .map(|ctx| match ctx.link.as_ref() {
+                Some(link) => format!("{} ({link})", ctx.name),
+                None => ctx.name.clone(),
+            })
+            .collect();
+        let mut name_width = names
+            .iter()
+            .map(|name| name.chars().count())
+            .max()
+            .unwrap_or(0);
+        let mut ns_width = ctxs
+            .iter()
+            .map(|ctx| ctx.namespace.chars().count())
+            .max()
+            .unwrap_or(0);
+        if let Some(width) = self.width {
+            let avail = width.saturating_sub(indent.len() + Self::DECORATION_WIDTH);
+            if name_width + ns_width > avail {
+                // Keep the namespaces whole if the names can still take half.
+                name_width = name_width.min(avail.saturating_sub(ns_width).max(avail / 2));
+                ns_width = avail.saturating_sub(name_width);
+            }
+        }
+
+        for (ctx, name) in ctxs.iter().zip(names) {
+            let marker = if ctx.current { "* " } else { "  " };
+            let name = output::truncate(&name, name_width);
+            let namespace = output::truncate(&ctx.namespace, ns_width);
+            println!("{indent}{marker}{name:<name_width$} -> {namespace}");
+        }
     }
+}
 
-    fn run_list(&self, cfg: &Config) -> Result<()> {
-        let ctxs = KubeContext::list(cfg)?;
-        for ctx in ctxs {
-            if ctx.current {
-                println!("* {ctx}");
+/// The group of the context imported by `ks discover`, such as
+/// `eks/123456789012/us-east-1`, built from its tags in the order of the provider
+/// fields.
+fn get_discover_group(cfg: &Config, tags: &BTreeMap<String, String>) -> Option<String> {
+    let provider = tags.get(DiscoverProvider::PROVIDER_TAG)?;
+    let mut parts = vec![provider.as_str()];
+    if let Some(discover) = cfg.discover.get(provider) {
+        for field in discover.fields.iter() {
+            if field == DiscoverProvider::CLUSTER_FIELD {
                 continue;
             }
-            println!("{ctx}");
+            if let Some(value) = tags.get(field) {
+                parts.push(value);
+            }
+        }
+    }
+    Some(parts.join("/"))
+}
+
+fn run_stats(cfg: &Config) -> Result<()> {
+    let mut ctxs = KubeContext::list(cfg)?;
+    context::sort_contexts(cfg, &mut ctxs, ContextSort::Usage);
+    let mut usage = history::usage(cfg);
+    // The deleted contexts are left in history until they are used again.
+    let stats: Vec<_> = ctxs
+        .into_iter()
+        .filter_map(|ctx| usage.remove(&ctx.name).map(|usage| (ctx.name, usage)))
+        .collect();
+    if stats.is_empty() {
+        info!("No context was used");
+        return Ok(());
+    }
+
+    let width = stats
+        .iter()
+        .flat_map(|(name, usage)| {
+            let namespaces = usage.namespaces.keys().map(|ns| ns.chars().count() + 2);
+            namespaces.chain([name.chars().count()])
+        })
+        .max()
+        .unwrap_or(0);
+    for (name, usage) in stats {
+        let elapsed = history::format_elapsed(usage.last_used);
+        println!("{name:<width$}  {:>5}  {elapsed}", usage.count);
+        let mut namespaces: Vec<_> = usage.namespaces.into_iter().collect();
+        namespaces.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        for (namespace, count) in namespaces {
+            println!("  {namespace:<0$}  {count:>5}", width - 2);
+        }
+    }
+    Ok(())
+}
+
+fn run_show(cfg: &Config, output: &OutputFormat) -> Result<()> {
+    let ctx = KubeContext::current(cfg)?;
+    match output {
+        OutputFormat::Text => println!("{ctx}"),
+        OutputFormat::Name => println!("{}", ctx.name),
+        OutputFormat::Json | OutputFormat::Yaml => {
+            let mut records = ContextRecord::build(cfg, &[ctx]);
+            show_records(&records.remove(0), output)?;
         }
-        Ok(())
     }
+    Ok(())
+}
+
+fn show_records<T: Serialize>(records: &T, output: &OutputFormat) -> Result<()> {
+    let data = match output {
+        OutputFormat::Json => serde_json::to_string_pretty(records).context("serialize json")?,
+        OutputFormat::Yaml => serde_yaml::to_string(records).context("serialize yaml")?,
+        _ => unreachable!(),
+    };
+    println!("{}", data.trim_end());
+    Ok(())
+}
 
-    fn run_delete(&self, cfg: &Config) -> Result<()> {
-        let ctx = KubeContext::select(cfg, &self.name, SelectOption::GetRequired)?;
-        ctx.delete()
+fn run_pick(cfg: &Config, dir: &Option<String>) -> Result<()> {
+    if let Some(dir) = dir.as_ref() {
+        if !dir.ends_with('/') {
+            bail!("invalid dir '{dir}', should be in 'dir/' format");
+        }
     }
+    let ctx = KubeContext::select(cfg, dir, SelectOption::Pick)?;
+    println!("{}", ctx.name);
+    Ok(())
+}
 
-    fn run_switch(&self, cfg: &Config) -> Result<()> {
-        let ctx = KubeContext::select(cfg, &self.name, SelectOption::Switch)?;
-        ctx.switch()
+fn run_delete(cfg: &Config, name: &Option<String>) -> Result<()> {
+    let ctxs = match name.as_deref() {
+        Some(dir) if dir.ends_with('/') => KubeContext::list_dir(cfg, dir)?,
+        Some(pattern) if is_glob(pattern) => {
+            let mut ctxs = KubeContext::list(cfg)?;
+            ctxs.retain(|ctx| glob::is_match(pattern, &ctx.name));
+            ctxs
+        }
+        _ => {
+            let ctx = KubeContext::select(cfg, name, SelectOption::GetRequired)?;
+            return ctx.delete();
+        }
+    };
+    if ctxs.is_empty() {
+        bail!("no context matches '{}'", name.as_deref().unwrap_or(""));
     }
+    KubeContext::delete_many(ctxs)
+}
+
+fn is_glob(name: &str) -> bool {
+    name.contains(['*', '?'])
+}
+
+fn run_switch(cfg: &Config, name: &Option<String>, namespace: &Option<String>) -> Result<()> {
+    // The name can be "{context}{separator}{namespace}".
+    let split = name.as_ref().and_then(|name| cfg.namespace.split(name));
+    let (name, namespace) = match (split, namespace) {
+        (Some(_), Some(_)) => bail!("the namespace is given by both the name and `--namespace`"),
+        (Some((name, namespace)), None) => (Some(name.to_string()), Some(namespace.to_string())),
+        (None, namespace) => (name.clone(), namespace.clone()),
+    };
 
-    fn run_namespace(&self, cfg: &Config) -> Result<()> {
-        let mut ctx = KubeContext::current(cfg)?;
-        let namespace = ctx.select_namespace(&self.name)?;
+    let mut ctx = KubeContext::select(cfg, &name, SelectOption::Switch)?;
+    ctx.materialize()?;
+    if namespace.is_some() {
+        let namespace = ctx.select_namespace(&namespace)?;
+        ctx.ensure_namespace(&namespace)?;
         ctx.set_namespace(namespace)?;
-        ctx.switch()
     }
+    ctx.switch()
+}
+
+fn run_shell(cfg: &Config, name: &Option<String>) -> Result<()> {
+    let mut ctx = KubeContext::select(cfg, name, SelectOption::GetRequired)?;
+    ctx.materialize()?;
+    ctx.spawn_shell()
+}
+
+fn run_exec(cfg: &Config, name: &Option<String>, history: bool, args: &[String]) -> Result<()> {
+    let (name, namespace) = match name.as_ref().and_then(|name| cfg.namespace.split(name)) {
+        Some((name, namespace)) => (Some(name.to_string()), Some(namespace.to_string())),
+        None => (name.clone(), None),
+    };
+
+    let mut ctx = KubeContext::select(cfg, &name, SelectOption::GetRequired)?;
+    ctx.materialize()?;
+    if let Some(namespace) = namespace {
+        // Only for this command, do not update the kubeconfig.
+        ctx.namespace = Cow::Owned(ctx.select_namespace(&Some(namespace))?);
+    }
+    let code = ctx.exec(args, history)?;
+    if code != 0 {
+        std::process::exit(code);
+    }
+    Ok(())
+}
+
+fn run_check(cfg: &Config, name: &Option<String>, timeout: u64) -> Result<()> {
+    use crate::context::Health;
 
-    fn run_link(&self, cfg: &Config) -> Result<()> {
-        use crate::context::create_symlink;
+    if cfg.offline {
+        bail!("cannot check contexts in offline mode");
+    }
+    let ctxs = match name {
+        Some(name) if name.ends_with('/') => KubeContext::list_dir(cfg, name)?,
+        Some(_) => vec![KubeContext::select(cfg, name, SelectOption::GetRequired)?],
+        None => KubeContext::list(cfg)?,
+    };
+    if ctxs.is_empty() {
+        bail!("no context to check");
+    }
 
-        if self.name.is_none() {
-            bail!("missing link target");
+    let results = Health::check_all(&ctxs, timeout);
+    let width = ctxs.iter().map(|ctx| ctx.name.len()).max().unwrap_or(0);
+    let mut failed = 0;
+    for (ctx, health) in ctxs.iter().zip(results.iter()) {
+        let tls = ctx.tls_issue();
+        if !matches!(health, Health::Ok) || matches!(tls, Some((_, config::TlsSeverity::Deny))) {
+            failed += 1;
         }
+        let detail = match tls {
+            Some((issue, _)) => Cow::Owned(format!("[tls: {}] {}", issue.short(), health.detail())),
+            None => Cow::Borrowed(health.detail()),
+        };
+        let line = format!("{:width$}  {:12}  {}", ctx.name, health.status(), detail);
+        println!("{}", line.trim_end());
+    }
 
-        create_symlink(cfg, self.name.as_ref().unwrap())
+    if failed > 0 {
+        bail!("{failed} of {} contexts are not healthy", ctxs.len());
     }
+    Ok(())
 }
 
-const NAME_REGEX: &str = "^[a-zA-Z-_0-9/:]+$";
+fn run_tag(cfg: &Config, name: &str, args: &[String]) -> Result<()> {
+    use crate::tags::{parse_tag, Tags};
 
-fn main() -> Result<()> {
-    let cfg = Config::load().context("load config")?;
+    let ctx = KubeContext::select(cfg, &Some(name.to_string()), SelectOption::GetRequired)?;
+    let mut tags = Tags::load()?;
+    if args.is_empty() {
+        if let Some(tags) = tags.get(&ctx.name) {
+            for (key, value) in tags.iter() {
+                println!("{key}={value}");
+            }
+        }
+        return Ok(());
+    }
+
+    for arg in args {
+        match arg.strip_suffix('-') {
+            Some(key) if !arg.contains('=') => tags.remove(&ctx.name, key),
+            _ => {
+                let (key, value) = parse_tag(arg)?;
+                tags.set(&ctx.name, key, value);
+            }
+        }
+    }
+    tags.save()
+}
 
-    let args = Args::try_parse()?;
-    if args.help {
-        let mut cmd = Args::command().name(get_cmd_name(&cfg));
-        let help = cmd.render_help();
-        eprintln!("{help}");
+fn run_changes(cfg: &Config) -> Result<()> {
+    let changes = match changes::check(cfg)? {
+        Some(changes) => changes,
+        None => {
+            info!("Recorded the current contexts, the changes will be shown next time");
+            return Ok(());
+        }
+    };
+    if changes.is_empty() {
+        info!("No context changed since last time");
         return Ok(());
     }
 
-    if args.version {
-        show_version(&cfg);
+    for change in changes {
+        print_change(&change);
+    }
+    Ok(())
+}
+
+fn run_bench(cfg: &Config, runs: usize, output: &OutputFormat) -> Result<()> {
+    let steps = bench::bench(cfg, runs)?;
+    match output {
+        OutputFormat::Text => {
+            println!(
+                "{:<10}{:>8}{:>12}{:>12}{:>12}",
+                "STEP", "ITEMS", "MIN", "MEAN", "MAX"
+            );
+            for step in steps {
+                println!(
+                    "{:<10}{:>8}{:>10.2}ms{:>10.2}ms{:>10.2}ms",
+                    step.name, step.items, step.min, step.mean, step.max
+                );
+            }
+        }
+        OutputFormat::Name => {
+            for step in steps {
+                println!("{}", step.name);
+            }
+        }
+        OutputFormat::Json | OutputFormat::Yaml => show_records(&steps, output)?,
+    }
+    Ok(())
+}
+
+fn run_sync(cfg: &Config, action: &SyncAction) -> Result<()> {
+    let changes = match action {
+        SyncAction::Push => return sync::push(cfg),
+        SyncAction::Pull => sync::pull(cfg)?,
+    };
+    if changes.is_empty() {
+        info!("Already up to date");
         return Ok(());
     }
+    for change in changes {
+        print_change(&change);
+    }
+    Ok(())
+}
+
+fn print_change(change: &changes::Change) {
+    use crate::changes::ChangeKind;
+    use crate::history::format_elapsed;
 
-    if args.show_config {
-        use serde_json::ser::PrettyFormatter;
-        use serde_json::Serializer;
+    let kind = match change.kind {
+        ChangeKind::Added => "added",
+        ChangeKind::Updated => "updated",
+        ChangeKind::Removed => "removed",
+    };
+    match change.modified {
+        Some(modified) => println!("{kind:<8}{} ({})", change.name, format_elapsed(modified)),
+        None => println!("{kind:<8}{}", change.name),
+    }
+}
 
-        let formatter = PrettyFormatter::with_indent(b"    ");
-        let mut buf = Vec::new();
-        let mut ser = Serializer::with_formatter(&mut buf, formatter);
-        cfg.serialize(&mut ser).context("serialize config")?;
+fn run_namespace(cfg: &Config, name: &Option<String>) -> Result<()> {
+    let mut ctx = match KubeContext::try_current(cfg)? {
+        Some(ctx) => ctx,
+        None if cfg.namespace.auto_pick_context => {
+            info!("No context is switched to yet, select one first");
+            KubeContext::select(cfg, &None, SelectOption::Pick)?
+        }
+        None => bail!(
+            "you have not switched to any context yet, switch to one first by `ks <name>`, or enable `namespace.auto_pick_context` to select one here"
+        ),
+    };
+    ctx.materialize()?;
+    let namespace = ctx.select_namespace(name)?;
+    ctx.ensure_namespace(&namespace)?;
+    ctx.set_namespace(namespace)?;
+    ctx.switch()
+}
 
-        let json = String::from_utf8(buf).context("encode json utf8")?;
-        println!("{json}");
+fn run_check_namespace(cfg: &Config) -> Result<()> {
+    use crate::cache::NamespaceCache;
+    use crate::history::format_elapsed;
 
+    if cfg.namespace.check == NamespaceCheck::Off {
+        return Ok(());
+    }
+    // This runs on every shell startup, keep quiet if there is nothing to check.
+    let mut ctx = match KubeContext::current(cfg) {
+        Ok(ctx) => ctx,
+        Err(_) => return Ok(()),
+    };
+    let cache = match NamespaceCache::read(&ctx.name)? {
+        Some(cache) => cache,
+        None => return Ok(()),
+    };
+    if cache
+        .namespaces
+        .iter()
+        .any(|ns| ns == ctx.namespace.as_ref())
+    {
         return Ok(());
     }
 
-    if args.build {
-        show_build_info(&cfg);
+    warning!(
+        "namespace '{}' of context '{}' no longer exists, according to the namespaces cached {}",
+        ctx.namespace,
+        ctx.name,
+        format_elapsed(cache.timestamp)
+    );
+    if cfg.namespace.check != NamespaceCheck::Reset {
         return Ok(());
     }
 
-    if args.comp {
-        return complete(&cfg, args);
+    ctx.materialize()?;
+    let mut namespace = ctx.get_kubeconfig_namespace()?;
+    if namespace == ctx.namespace {
+        // The kubeconfig was updated to the deleted namespace too.
+        namespace = String::from("default");
+    }
+    info!("reset namespace to '{namespace}'");
+    ctx.set_namespace(namespace)?;
+    ctx.switch()
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        bail!("invalid input name, should not be empty");
+    }
+    let re = Regex::new(NAME_REGEX).unwrap();
+    if !re.is_match(name) {
+        bail!("invalid input name, should not contain special character");
+    }
+
+    if name.contains(':') && !name.starts_with('@') {
+        bail!("invalid input name, should not contain ':'");
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    output::init_logger();
+    let mut cfg = load_config()?;
+
+    let cmd_name = get_cmd_name(&cfg);
+    let matches = Args::command()
+        .name(cmd_name)
+        .bin_name(cmd_name)
+        .get_matches();
+    let mut args = Args::from_arg_matches(&matches)?;
+    output::set_quiet(args.quiet);
+    let legacy = legacy_command(&mut args)?;
+    output::set_verbose(args.verbose);
+    if let Some(dir) = args.state_dir.as_ref() {
+        // Export it to the commands calling back, such as the preview of selector
+        // and the hooks, and reload the config to relocate its paths.
+        let dir = std::path::absolute(dir)
+            .with_context(|| format!("get absolute path of state dir '{}'", dir.display()))?;
+        env::set_var(config::STATE_DIR_ENV, dir);
+        cfg = load_config()?;
     }
+    match cfg.path.as_ref() {
+        Some(path) => log::debug!("use config file '{}'", path.display()),
+        None => log::debug!("config file not found, use the default config"),
+    }
+    if args.offline {
+        cfg.offline = true;
+    }
+    cfg.tag_filter = args.tag;
+    context::set_assume_yes(args.yes || env_enabled("KUBESWITCH_ASSUME_YES"));
 
-    if args.init.is_some() {
-        if args.wrap.is_empty() {
-            bail!("wrap target cannot be empty");
+    // The bare `ks <name>` is the fast path of `ks switch <name>`.
+    let command = match (args.command, legacy) {
+        (Some(_), Some(_)) => bail!("cannot use the deprecated flags with a command"),
+        (Some(_), None) if args.name.is_some() => bail!("cannot use NAME with a command"),
+        (Some(_), None) if args.namespace.is_some() => {
+            bail!("cannot use `--namespace` with a command")
         }
-        show_init(&cfg, args);
-        return Ok(());
+        (Some(command), None) | (None, Some(command)) => command,
+        // The bare `ks -n <namespace>` switches namespace of the current context.
+        (None, None) if args.name.is_none() && args.namespace.is_some() => Commands::Ns {
+            name: args.namespace,
+        },
+        (None, None) => {
+            // `ks .` always launches the selector, while the bare `ks` might go to
+            // the previous context, see `previous_by_default`.
+            let name = match args.name {
+                Some(name) if name == "." => None,
+                None if cfg.previous_by_default => Some(String::from("-")),
+                name => name,
+            };
+            Commands::Switch {
+                name,
+                namespace: args.namespace,
+            }
+        }
+    };
+    for name in command.names(&cfg) {
+        validate_name(name)?;
+    }
+
+    // The completion runs in the line editor of shell, it resolves the exact names
+    // only and never launches fzf.
+    let no_interactive = args.no_interactive || matches!(command, Commands::Complete { .. });
+    context::set_no_interactive(no_interactive);
+    match args.select_index {
+        Some(idx) => selector::set(Box::new(selector::IndexSelector(idx))),
+        None if no_interactive => selector::set(Box::new(selector::NoInteractiveSelector)),
+        None => selector::set(Box::new(selector::CommandSelector::new(&cfg.selector))),
+    }
+
+    command.run(&cfg)
+}
+
+/// Load the config, offer to migrate `kube.dir` if it is a file.
+fn load_config() -> Result<Config> {
+    use std::io::{self, IsTerminal};
+
+    let err = match Config::load() {
+        Ok(cfg) => return Ok(cfg),
+        Err(err) => err,
+    };
+    let file = match err.downcast_ref::<KubeDirIsFile>() {
+        // Never prompt in completion and scripts.
+        Some(is_file) if io::stdin().is_terminal() && io::stderr().is_terminal() => {
+            is_file.dir.clone()
+        }
+        _ => return Err(err).context("load config"),
+    };
+
+    eprintln!("{err:#}");
+    let msg = format!(
+        "Migrate to '{}', the contexts in '{file}' will be imported into it and the config file will be rewritten",
+        KubeDirIsFile::MIGRATE_DIR
+    );
+    if !context::confirm(msg)? {
+        bail!("user aborted");
     }
+    let path = Config::save_kube_dir(KubeDirIsFile::MIGRATE_DIR)?;
+    let cfg = Config::load().context("load migrated config")?;
+    let opts = import::ImportOptions {
+        prefix: None,
+        conflict: ImportConflict::Skip,
+        dry_run: false,
+        interactive: false,
+    };
+    import::import_merged(&cfg, &file, &opts)?;
+    info!(
+        "Migrated `kube.dir` to '{}' in config file '{}', '{file}' is left untouched",
+        cfg.kube.dir,
+        path.display()
+    );
+    Ok(cfg)
+}
+
+/// Check if the env is set to a true value, such as `1` or `true`.
+fn env_enabled(key: &str) -> bool {
+    match env::var(key) {
+        Ok(value) => matches!(value.to_lowercase().as_str(), "1" | "true" | "yes"),
+        Err(_) => false,
+    }
+}
 
-    if let Some(name) = args.name.as_ref() {
-        if name.is_empty() {
-            bail!("invalid input name, should not be empty");
+fn show_preview(cfg: &Config, name: &str) -> Result<()> {
+    // The field from the selector might carry the delimiter.
+    let name = name.trim();
+    let ctx = KubeContext::select(cfg, &Some(name.to_string()), SelectOption::GetRequired)?;
+    print_details(&ctx.describe(false));
+    Ok(())
+}
+
+fn show_prompt(cfg: &Config, shell: &Option<Shell>, no_color: bool) -> Result<()> {
+    use crate::config::{NamespaceSource, PromptColor};
+
+    // The prompt is rendered frequently, read the env exported by the switch only.
+    // Resolve the context when there is no env, such as in symlink mode, or the
+    // namespace might come from the kubeconfig file.
+    let name = env::var("KUBESWITCH_NAME").ok();
+    let namespace = env::var("KUBESWITCH_NAMESPACE").ok();
+    let (prompt, color) = match (name, namespace) {
+        (Some(name), Some(namespace)) if cfg.kube.namespace_source == NamespaceSource::Env => {
+            let emoji = env::var("KUBESWITCH_EMOJI").ok();
+            let color = env::var("KUBESWITCH_COLOR").ok();
+            (
+                cfg.render_prompt(&name, &namespace, emoji.as_deref()),
+                color.as_deref().and_then(PromptColor::parse),
+            )
+        }
+        _ => {
+            // Keep quiet if there is no current context.
+            let ctx = match KubeContext::current(cfg) {
+                Ok(ctx) => ctx,
+                Err(_) => return Ok(()),
+            };
+            let hint = ctx.get_prompt_hint();
+            let emoji = hint.and_then(|hint| hint.emoji.as_deref());
+            (
+                cfg.render_prompt(&ctx.name, &ctx.namespace, emoji),
+                hint.and_then(|hint| hint.color),
+            )
         }
-        let re = Regex::new(NAME_REGEX).unwrap();
-        if !re.is_match(name) {
-            bail!("invalid input name, should not contain special character");
+    };
+    let color = match color {
+        Some(color) if !no_color => color,
+        _ => {
+            println!("{prompt}");
+            return Ok(());
         }
+    };
+
+    let (start, end) = match shell {
+        Some(Shell::Bash) => ("\\[", "\\]"),
+        Some(Shell::Zsh) => ("%{", "%}"),
+        Some(Shell::Fish) | None => ("", ""),
+    };
+    println!(
+        "{start}\x1b[{}m{end}{prompt}{start}\x1b[0m{end}",
+        color.code()
+    );
+    Ok(())
+}
 
-        if name.contains(':') && !args.link {
-            bail!("invalid input name, should not contain ':'");
+fn run_resolve(cfg: &Config, query: &str) -> Result<()> {
+    let mut details = vec![("Query", query.to_string())];
+    let query = match cfg.namespace.split(query) {
+        Some((name, namespace)) => {
+            details.push(("Namespace", namespace.to_string()));
+            name
         }
+        None => query,
+    };
+    if is_glob(query) {
+        details.push((
+            "Glob",
+            String::from("matched against the names by `delete`"),
+        ));
+    }
+
+    let mut query = query.to_string();
+    loop {
+        let resolved = match KubeContext::resolve(cfg, &query)? {
+            Resolution::History => String::from("the previous context in history"),
+            Resolution::Time(time) => format!("the context in use at '{time}' in history"),
+            Resolution::Dir(dir) => format!("select from the contexts under '{dir}'"),
+            Resolution::Vault => String::from("vault context"),
+            Resolution::File { path, link } => {
+                let resolved = match link {
+                    Some(link) => format!("symlink context of '{link}'"),
+                    None => String::from("kubeconfig file"),
+                };
+                details.push(("Resolved", resolved));
+                details.push(("Context", query));
+                details.push(("Path", path.display().to_string()));
+                break;
+            }
+            Resolution::Tombstone(tombstone) => {
+                let elapsed = history::format_elapsed(tombstone.timestamp);
+                let successor = match tombstone.successor {
+                    Some(successor) => successor,
+                    None => {
+                        details.push(("Resolved", format!("'{query}' was deleted {elapsed}")));
+                        break;
+                    }
+                };
+                details.push((
+                    "Resolved",
+                    format!("'{query}' was renamed to '{successor}' {elapsed}"),
+                ));
+                if !cfg.kube.follow_renamed {
+                    details.push((
+                        "",
+                        String::from("not followed, `kube.follow_renamed` is off"),
+                    ));
+                    break;
+                }
+                details.push(("", String::from("only followed when switching")));
+                query = successor;
+                continue;
+            }
+            Resolution::Match => {
+                let ctxs = KubeContext::list(cfg)?;
+                let matched = KubeContext::find_matches(&ctxs, &query);
+                details.push((
+                    "Resolved",
+                    format!("{} contexts matching '{query}'", matched.len()),
+                ));
+                for idx in matched {
+                    details.push(("", ctxs[idx].name.clone()));
+                }
+                details.push((
+                    "",
+                    String::from("only matched when switching, other commands need the exact name"),
+                ));
+                break;
+            }
+        };
+        details.push(("Resolved", resolved));
+        break;
+    }
+
+    print_details(&details);
+    Ok(())
+}
+
+fn print_details(items: &[(&str, String)]) {
+    for (key, value) in items {
+        let key = if key.is_empty() {
+            String::new()
+        } else {
+            format!("{key}:")
+        };
+        println!("{key:11}{value}");
     }
+}
+
+fn show_config(cfg: &Config) -> Result<()> {
+    use serde_json::ser::PrettyFormatter;
+    use serde_json::Serializer;
 
-    args.run(&cfg)
+    let formatter = PrettyFormatter::with_indent(b"    ");
+    let mut buf = Vec::new();
+    let mut ser = Serializer::with_formatter(&mut buf, formatter);
+    cfg.serialize(&mut ser).context("serialize config")?;
+
+    let json = String::from_utf8(buf).context("encode json utf8")?;
+    println!("{json}");
+
+    Ok(())
 }
 
 fn show_version(cfg: &Config) {
-    eprintln!("{} {}", get_cmd_name(cfg), env!("BUILD_VERSION"));
+    println!("{} {}", get_cmd_name(cfg), env!("BUILD_VERSION"));
 }
 
 fn show_build_info(cfg: &Config) {
     show_version(cfg);
-    eprintln!(
+    println!(
         "rustc {}-{}-{}",
         env!("VERGEN_RUSTC_SEMVER"),
         env!("VERGEN_RUSTC_LLVM_VERSION"),
         env!("VERGEN_RUSTC_CHANNEL")
     );
 
-    eprintln!();
-    eprintln!("Build type:   {}", env!("BUILD_TYPE"));
-    eprintln!("Build target: {}", env!("BUILD_TARGET"));
-    eprintln!("Commit SHA:   {}", env!("BUILD_SHA"));
-    eprintln!("Build time:   {}", env!("VERGEN_BUILD_TIMESTAMP"));
+    println!();
+    println!("Build type:   {}", env!("BUILD_TYPE"));
+    println!("Build target: {}", env!("BUILD_TARGET"));
+    println!("Commit SHA:   {}", env!("BUILD_SHA"));
+    println!("Build time:   {}", env!("VERGEN_BUILD_TIMESTAMP"));
+    println!("Protocol:     {}", context::PROTOCOL_VERSION);
+    let linkage = if cfg!(target_feature = "crt-static") {
+        "static"
+    } else {
+        "dynamic"
+    };
+    println!("Linkage:      {linkage}");
 
-    eprintln!();
+    println!();
     let path = match cfg.path.as_ref() {
         Some(path) => Cow::Owned(format!("{}", path.display())),
         None => Cow::Borrowed("N/A"),
     };
-    eprintln!("Config path: {path}");
+    println!("Config path: {path}");
+}
+
+fn run_self_test(cfg: &Config) -> Result<()> {
+    use crate::selftest::Status;
+
+    println!();
+    let checks = selftest::self_test(cfg);
+    let width = checks
+        .iter()
+        .map(|check| check.name.len())
+        .max()
+        .unwrap_or(0);
+    let mut failed = 0;
+    for check in checks.iter() {
+        if check.status == Status::Failed {
+            failed += 1;
+        }
+        println!(
+            "{:width$}  {:8}  {}",
+            check.name,
+            check.status.as_str(),
+            check.detail
+        );
+    }
+
+    if failed > 0 {
+        bail!("{failed} of {} self-test checks failed", checks.len());
+    }
+    Ok(())
 }
 
 fn get_cmd_name(cfg: &Config) -> &'static str {
     Box::leak(cfg.cmd.clone().into_boxed_str())
 }
 
-fn show_init(cfg: &Config, args: Args) {
-    let wrap = include_bytes!("../scripts/wrap.sh");
+fn show_init(cfg: &Config, shell: &Shell, wrap_cmd: &str) {
+    // Fish cannot source the POSIX shell functions, it has its own wrapper.
+    let wrap = match shell {
+        Shell::Bash | Shell::Zsh => include_bytes!("../scripts/wrap.sh").as_slice(),
+        Shell::Fish => include_bytes!("../scripts/wrap.fish").as_slice(),
+    };
     let wrap = String::from_utf8_lossy(wrap).to_string();
 
     let wrap = wrap.replace("__kubeswitch_cmd", &cfg.cmd);
-    let wrap = wrap.replace("__wrap_cmd", &args.wrap);
+    let wrap = wrap.replace("__wrap_cmd", wrap_cmd);
+    let wrap = wrap.replace("__protocol_version", &context::PROTOCOL_VERSION.to_string());
 
     println!("{wrap}");
     println!();
 
-    let comp = match args.init.unwrap() {
+    let comp = get_completion(cfg, shell);
+    println!("{comp}");
+
+    if cfg.namespace.check != NamespaceCheck::Off {
+        println!();
+        println!("{} check-namespace", cfg.cmd);
+    }
+
+    if cfg.auto_switch {
+        let auto = match shell {
+            Shell::Bash | Shell::Zsh => include_bytes!("../scripts/auto.sh").as_slice(),
+            Shell::Fish => include_bytes!("../scripts/auto.fish").as_slice(),
+        };
+        let auto = String::from_utf8_lossy(auto).to_string();
+        println!();
+        println!("{}", auto.replace("__kubeswitch_cmd", &cfg.cmd));
+    }
+}
+
+fn get_completion(cfg: &Config, shell: &Shell) -> String {
+    let comp = match shell {
         Shell::Bash => include_bytes!("../scripts/comp-bash.sh").as_slice(),
         Shell::Zsh => include_bytes!("../scripts/comp-zsh.zsh").as_slice(),
+        Shell::Fish => include_bytes!("../scripts/comp-fish.fish").as_slice(),
     };
     let comp = String::from_utf8_lossy(comp).to_string();
     let comp = comp.replace("__kubeswitch_cmd", &cfg.cmd);
-    let comp = comp.replace("__kubeswitch_comp", &format!("_{}", cfg.cmd));
-
-    println!("{comp}");
+    comp.replace("__kubeswitch_comp", &format!("_{}", cfg.cmd))
 }
 
-fn complete(cfg: &Config, args: Args) -> Result<()> {
-    let args = args.comp_args.unwrap_or_default();
-
-    let mut is_namespace = false;
-    let mut count = 0;
-    let mut to_complete = None;
-    for arg in args {
-        if !arg.starts_with('-') {
-            count += 1;
-            to_complete = Some(arg);
-            continue;
-        }
-        let flag = arg.trim_start_matches('-');
-        if flag.contains('n') {
-            is_namespace = true;
-            continue;
-        }
-        if flag == "namespace" {
-            is_namespace = true;
-            continue;
-        }
-    }
-    if count > 1 {
-        return Ok(());
-    }
-    let to_complete = to_complete.unwrap_or(String::new());
+fn install_completions(cfg: &Config, dir: Option<&str>) -> Result<()> {
+    use std::fs;
+    use std::path::PathBuf;
 
-    let mut items = Vec::new();
-    if is_namespace {
-        let ctx =
-            KubeContext::current(cfg).context("get current context for completing namespace")?;
-        let namespaces = ctx
-            .list_namespaces()
-            .context("list namespaces for completion")?;
+    use crate::context::ensure_dir;
 
-        for ns in namespaces {
-            if ns == to_complete {
-                return Ok(());
-            }
-            if ns == ctx.namespace {
-                continue;
-            }
-            if ns.starts_with(&to_complete) {
-                items.push(format!("{ns}"));
-            }
-        }
-    } else {
-        let ctxs = KubeContext::list(cfg).context("list contexts for completion")?;
-        for ctx in ctxs {
-            if ctx.name == to_complete {
-                return Ok(());
+    for shell in Shell::value_variants() {
+        let (path, content) = match shell {
+            Shell::Bash => {
+                let dir = match dir {
+                    Some(dir) => PathBuf::from(dir),
+                    None => config::get_bash_completion_dir()?,
+                };
+                (dir.join(&cfg.cmd), get_completion(cfg, shell))
             }
-            if ctx.current {
-                continue;
+            Shell::Zsh => {
+                let dir = match dir {
+                    Some(dir) => PathBuf::from(dir),
+                    None => config::get_home_dir()?.join(".zfunc"),
+                };
+                // The zsh completion file is autoloaded from fpath, its first line
+                // must be the `#compdef` tag, and the `compdef` call is not needed.
+                let comp = get_completion(cfg, shell);
+                let comp: Vec<&str> = comp
+                    .lines()
+                    .filter(|line| !line.starts_with("compdef "))
+                    .collect();
+                let content = format!(
+                    "#compdef {cmd}\n\n{comp}\n\n_{cmd} \"$@\"\n",
+                    cmd = cfg.cmd,
+                    comp = comp.join("\n").trim_end(),
+                );
+                (dir.join(format!("_{}", cfg.cmd)), content)
             }
-            if ctx.name.starts_with(&to_complete) {
-                items.push(ctx.name);
+            Shell::Fish => {
+                let dir = match dir {
+                    Some(dir) => PathBuf::from(dir),
+                    None => config::get_fish_completion_dir()?,
+                };
+                let path = dir.join(format!("{}.fish", cfg.cmd));
+                (path, get_completion(cfg, shell))
             }
-        }
-    }
+        };
+
+        ensure_dir(&path)?;
+        fs::write(&path, content)
+            .with_context(|| format!("write completion file '{}'", path.display()))?;
+        info!("Installed {shell:?} completion to '{}'", path.display());
 
-    for item in items {
-        println!("{item}");
+        if let Shell::Zsh = shell {
+            let dir = path.parent().unwrap();
+            info!(
+                "Please make sure that '{}' is in your zsh fpath, and call `compinit` after it",
+                dir.display()
+            );
+        }
     }
 
     Ok(())
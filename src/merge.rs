@@ -0,0 +1,129 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+use anyhow::{bail, Context, Result};
+use serde_yaml::{Mapping, Value};
+
+use crate::config::{get_runtime_dir, Config};
+use crate::context::{create_private_dir, write_private, KubeContext, SelectOption};
+use crate::import::KubeconfigDoc;
+
+/// Merge the contexts into one kubeconfig and switch to it, for the tools working
+/// across clusters, such as k9s and `helmfile diff`. The names can be separated by
+/// ',', and the name ending with '/' means all the contexts under the dir.
+///
+/// The clusters, users and contexts in the merged kubeconfig are all named after
+/// the contexts, so that they do not collide. The first context is the current
+/// one. Switching to a single context goes back.
+pub fn merge(cfg: &Config, names: &[String]) -> Result<()> {
+    let mut ctxs = Vec::new();
+    let mut seen = HashSet::new();
+    for name in names.iter().flat_map(|name| name.split(',')) {
+        let name = name.trim();
+        if name.is_empty() {
+            continue;
+        }
+        let found = match name.strip_suffix('/') {
+            Some(dir) => {
+                let found = KubeContext::list_dir(cfg, dir)?;
+                if found.is_empty() {
+                    bail!("no context under '{dir}'");
+                }
+                found
+            }
+            None => vec![KubeContext::select(
+                cfg,
+                &Some(name.to_string()),
+                SelectOption::GetRequired,
+            )?],
+        };
+        for ctx in found {
+            if seen.insert(ctx.name.clone()) {
+                ctxs.push(ctx);
+            }
+        }
+    }
+    if ctxs.len() < 2 {
+        bail!("merging requires at least 2 contexts");
+    }
+
+    let mut clusters = Vec::with_capacity(ctxs.len());
+    let mut users = Vec::with_capacity(ctxs.len());
+    let mut contexts = Vec::with_capacity(ctxs.len());
+    for ctx in ctxs.iter() {
+        ctx.prepare_merge()?;
+        let mut doc = minify(ctx).with_context(|| format!("merge context '{}'", ctx.name))?;
+        clusters.extend(take_items(&mut doc, "clusters"));
+        users.extend(take_items(&mut doc, "users"));
+        contexts.extend(take_items(&mut doc, "contexts"));
+    }
+
+    let mut doc = Mapping::new();
+    doc.insert("apiVersion".into(), "v1".into());
+    doc.insert("kind".into(), "Config".into());
+    doc.insert("clusters".into(), Value::Sequence(clusters));
+    doc.insert("contexts".into(), Value::Sequence(contexts));
+    doc.insert("users".into(), Value::Sequence(users));
+    doc.insert("current-context".into(), ctxs[0].name.as_str().into());
+    let data = serde_yaml::to_string(&doc).context("serialize merged kubeconfig")?;
+
+    // The same contexts reuse the same file.
+    let mut hasher = DefaultHasher::new();
+    ctxs.iter()
+        .map(|ctx| ctx.name.as_str())
+        .collect::<Vec<_>>()
+        .hash(&mut hasher);
+    let dir = get_runtime_dir().join("merge");
+    create_private_dir(&dir)?;
+    let path = dir.join(format!("{:016x}.yaml", hasher.finish()));
+    write_private(&path, data.as_bytes())?;
+
+    info!(
+        "Merged {} contexts: {}",
+        ctxs.len(),
+        ctxs.iter()
+            .map(|ctx| ctx.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    ctxs[0].switch_merged(&path, ctxs.len())
+}
+
+/// The standalone kubeconfig of the context, whose cluster, user and context are
+/// renamed to the context name. The namespace switched in the shell is kept.
+fn minify(ctx: &KubeContext) -> Result<Mapping> {
+    let doc = KubeconfigDoc::read(&ctx.get_decrypted_path()?)?;
+    let context = doc.main_context()?;
+    let mut doc = doc.minify_mapping(&context)?;
+
+    let name = Value::from(ctx.name.as_str());
+    for key in ["clusters", "users", "contexts"] {
+        if let Some(Value::Sequence(items)) = doc.get_mut(key) {
+            for item in items.iter_mut() {
+                if let Value::Mapping(item) = item {
+                    item.insert("name".into(), name.clone());
+                }
+            }
+        }
+    }
+    if let Some(Value::Sequence(contexts)) = doc.get_mut("contexts") {
+        for context in contexts.iter_mut() {
+            if let Some(Value::Mapping(entry)) = context.get_mut("context") {
+                entry.insert("cluster".into(), name.clone());
+                if entry.contains_key("user") {
+                    entry.insert("user".into(), name.clone());
+                }
+                entry.insert("namespace".into(), ctx.namespace.as_ref().into());
+            }
+        }
+    }
+    Ok(doc)
+}
+
+fn take_items(doc: &mut Mapping, key: &str) -> Vec<Value> {
+    match doc.remove(key) {
+        Some(Value::Sequence(items)) => items,
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,123 @@
+use std::borrow::Cow;
+use std::env;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Suppress all the warnings, see [`warning`].
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Print a human warning to stderr. The stdout is reserved for machine data (list
+/// items, completion items, switch protocol), so the warnings should never go there,
+/// otherwise the wrap script might parse them as data.
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_quiet() {
+            eprintln!("warning: {}", format_args!($($arg)*));
+        }
+    };
+}
+
+/// Print a human notice to stderr, such as reminders after switching. It can be
+/// suppressed by quiet mode, the same as [`warning`].
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_quiet() {
+            eprintln!("{}", format_args!($($arg)*));
+        }
+    };
+}
+
+/// The env to enable the debug logs, the value is the log level, such as `debug`
+/// and `trace`. The `-v` flag can only raise the level.
+const LOG_ENV: &str = "KUBESWITCH_LOG";
+
+/// The debug logger, it writes the records to stderr with the elapsed time since
+/// startup. It is not affected by quiet mode, the user asked for it explicitly.
+struct Logger {
+    start: Instant,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let elapsed = self.start.elapsed().as_secs_f64() * 1000.0;
+        let level = record.level().as_str().to_lowercase();
+        // Ignore the error, there is nowhere to report a broken stderr.
+        let _ = writeln!(io::stderr(), "[{level} {elapsed:>8.2}ms] {}", record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+/// Install the debug logger, the level comes from the `KUBESWITCH_LOG` env. It
+/// should be called before loading the config, so that the config resolution can
+/// be logged too.
+pub fn init_logger() {
+    let level = match env::var(LOG_ENV) {
+        Ok(value) if !value.is_empty() => match LevelFilter::from_str(&value) {
+            Ok(level) => level,
+            Err(_) => {
+                warning!("invalid log level '{value}' in env {LOG_ENV}, ignore it");
+                LevelFilter::Off
+            }
+        },
+        _ => LevelFilter::Off,
+    };
+    let logger = Logger {
+        start: Instant::now(),
+    };
+    if log::set_boxed_logger(Box::new(logger)).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// Raise the log level by the count of `-v` flags, `-v` for debug and `-vv` for
+/// trace.
+pub fn set_verbose(count: u8) {
+    let level = match count {
+        0 => return,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+    if level > log::max_level() {
+        log::set_max_level(level);
+    }
+}
+
+/// The width of the terminal that stdout goes to, `None` if stdout is not a
+/// terminal, such as being piped to scripts, which should get the full output.
+pub fn terminal_width() -> Option<usize> {
+    let (width, _) = terminal_size::terminal_size_of(io::stdout())?;
+    Some(width.0 as usize)
+}
+
+/// Cut the text to the width in chars, ending with an ellipsis if it is cut.
+pub fn truncate(text: &str, width: usize) -> Cow<'_, str> {
+    if text.chars().count() <= width {
+        return Cow::Borrowed(text);
+    }
+    if width == 0 {
+        return Cow::Borrowed("");
+    }
+    let mut cut: String = text.chars().take(width - 1).collect();
+    cut.push('…');
+    Cow::Owned(cut)
+}
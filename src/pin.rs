@@ -0,0 +1,177 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::{get_state_path, Config};
+use crate::context::{confirm, KubeContext, SelectOption};
+use crate::session::Session;
+
+/// The context and namespace pinned to a project dir by the `.kubeswitch` file,
+/// it is switched to automatically when entering the dir, see `auto_switch`.
+#[derive(Debug, Deserialize, Serialize)]
+struct Pin {
+    context: String,
+
+    namespace: Option<String>,
+}
+
+const PIN_NAME: &str = ".kubeswitch";
+
+/// The pin files trusted to switch automatically, every line is the SHA256 hash of
+/// the file content followed by its path. Changing the file revokes the trust, so
+/// that an untrusted repo cannot silently point the shell to another cluster.
+const TRUSTED_NAME: &str = ".kubeswitch_trusted";
+
+/// Switch to the context pinned by the nearest `.kubeswitch` file of the current
+/// dir. Emitted by `init` when `auto_switch` is enabled, it is called whenever the
+/// shell changes dir, and only switches when entering another pinned project.
+pub fn auto(cfg: &Config) -> Result<()> {
+    let path = find()?;
+    if !Session::swap_pin(path.as_deref())? {
+        return Ok(());
+    }
+    let path = match path {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let data = read(&path)?;
+    if !is_trusted(&path, &data)? {
+        warning!(
+            "'{}' is not trusted, please review it and run `{} trust` to allow it",
+            path.display(),
+            cfg.cmd
+        );
+        return Ok(());
+    }
+    switch(cfg, &path, &data)
+}
+
+/// Write the `.kubeswitch` file in the current dir, the name can be followed by the
+/// namespace separator and the namespace. The written file is trusted.
+pub fn pin(cfg: &Config, name: &str) -> Result<()> {
+    let pin = match cfg.namespace.split(name) {
+        Some((name, namespace)) => Pin {
+            context: name.to_string(),
+            namespace: Some(namespace.to_string()),
+        },
+        None => Pin {
+            context: name.to_string(),
+            namespace: None,
+        },
+    };
+    // Make sure the context exists before pinning.
+    KubeContext::select(cfg, &Some(pin.context.clone()), SelectOption::GetRequired)?;
+
+    let path = get_current_dir()?.join(PIN_NAME);
+    let data = toml::to_string(&pin).context("serialize pin file")?;
+    fs::write(&path, &data).with_context(|| format!("write pin file '{}'", path.display()))?;
+    trust_data(&path, &data)?;
+    info!("Pinned context '{name}' to '{}'", path.display());
+
+    Session::swap_pin(Some(&path))?;
+    switch(cfg, &path, &data)
+}
+
+/// Trust the nearest `.kubeswitch` file of the current dir after reviewing its
+/// content, and switch to it.
+pub fn trust(cfg: &Config) -> Result<()> {
+    let path = match find()? {
+        Some(path) => path,
+        None => bail!("cannot find {PIN_NAME} file in the current dir tree"),
+    };
+    let data = read(&path)?;
+    if is_trusted(&path, &data)? {
+        info!("'{}' is already trusted", path.display());
+        return Ok(());
+    }
+
+    eprintln!("{}:", path.display());
+    eprintln!("{}", data.trim_end());
+    if !confirm("Trust it to switch automatically")? {
+        return Ok(());
+    }
+    trust_data(&path, &data)?;
+
+    Session::swap_pin(Some(&path))?;
+    switch(cfg, &path, &data)
+}
+
+fn switch(cfg: &Config, path: &Path, data: &str) -> Result<()> {
+    let pin: Pin =
+        toml::from_str(data).with_context(|| format!("parse pin file '{}'", path.display()))?;
+
+    // The protected contexts are still checked by switching.
+    let mut ctx = KubeContext::select(cfg, &Some(pin.context), SelectOption::GetRequired)?;
+    ctx.materialize()?;
+    if let Some(namespace) = pin.namespace {
+        if ctx.namespace != namespace {
+            ctx.ensure_namespace(&namespace)?;
+            ctx.set_namespace(namespace)?;
+        }
+    }
+    ctx.switch()
+}
+
+/// Find the nearest pin file from the current dir up to the root.
+fn find() -> Result<Option<PathBuf>> {
+    let dir = get_current_dir()?;
+    Ok(dir.ancestors().map(|dir| dir.join(PIN_NAME)).find(|path| {
+        fs::metadata(path)
+            .map(|meta| meta.is_file())
+            .unwrap_or(false)
+    }))
+}
+
+fn read(path: &Path) -> Result<String> {
+    fs::read_to_string(path).with_context(|| format!("read pin file '{}'", path.display()))
+}
+
+fn is_trusted(path: &Path, data: &str) -> Result<bool> {
+    let line = format!("{} {}", hash(data), path.display());
+    Ok(read_trusted()?.contains(&line))
+}
+
+/// Trust the content of the pin file, replacing the old trust of the path.
+fn trust_data(path: &Path, data: &str) -> Result<()> {
+    let path_str = format!("{}", path.display());
+    let mut lines: Vec<String> = read_trusted()?
+        .into_iter()
+        .filter(|line| line.split_once(' ').map(|(_, path)| path) != Some(path_str.as_str()))
+        .collect();
+    lines.push(format!("{} {path_str}", hash(data)));
+
+    let mut data = lines.join("\n");
+    data.push('\n');
+    let trusted_path = get_state_path(TRUSTED_NAME)?;
+    fs::write(&trusted_path, data)
+        .with_context(|| format!("write trusted file '{}'", trusted_path.display()))
+}
+
+fn read_trusted() -> Result<Vec<String>> {
+    let path = get_state_path(TRUSTED_NAME)?;
+    match fs::read_to_string(&path) {
+        Ok(data) => Ok(data.lines().map(String::from).collect()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err).with_context(|| format!("read trusted file '{}'", path.display())),
+    }
+}
+
+fn hash(data: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(data.as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn get_current_dir() -> Result<PathBuf> {
+    env::current_dir().context("get current dir")
+}
@@ -0,0 +1,113 @@
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+
+/// A running kubectl process that keeps connections to a cluster.
+pub struct KubeProcess {
+    pub pid: u32,
+    pub args: String,
+}
+
+/// The kubectl verbs that keep running and hold connections to the cluster.
+const LONG_RUNNING_VERBS: [&str; 4] = ["port-forward", "exec", "attach", "proxy"];
+
+/// Find the long-running kubectl processes using the kubeconfig, by scanning the
+/// cmdline and env of the processes in `/proc`. The processes of other users are
+/// skipped since their env cannot be read.
+pub fn find_kubectl(exec_name: &str, kubeconfig: &Path) -> Result<Vec<KubeProcess>> {
+    let dir = match fs::read_dir("/proc") {
+        Ok(dir) => dir,
+        // Not a Linux system, there is no way to scan processes.
+        Err(_) => return Ok(Vec::new()),
+    };
+
+    let mut procs = Vec::new();
+    for ent in dir {
+        let ent = ent.context("read sub entry for '/proc'")?;
+        let pid: u32 = match ent.file_name().to_str().and_then(|s| s.parse().ok()) {
+            Some(pid) => pid,
+            None => continue,
+        };
+        let args = match read_nul_list(&ent.path().join("cmdline")) {
+            Some(args) if !args.is_empty() => args,
+            _ => continue,
+        };
+
+        let exec = Path::new(OsStr::from_bytes(&args[0]))
+            .file_name()
+            .unwrap_or_default();
+        if exec.as_bytes() != exec_name.as_bytes() {
+            continue;
+        }
+        let args: Vec<_> = args
+            .iter()
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect();
+        if !args
+            .iter()
+            .any(|arg| LONG_RUNNING_VERBS.contains(&arg.as_str()))
+        {
+            continue;
+        }
+
+        if !uses_kubeconfig(&args, &ent.path().join("environ"), kubeconfig) {
+            continue;
+        }
+        procs.push(KubeProcess {
+            pid,
+            args: args.join(" "),
+        });
+    }
+    procs.sort_unstable_by_key(|p| p.pid);
+
+    Ok(procs)
+}
+
+fn uses_kubeconfig(args: &[String], environ: &Path, kubeconfig: &Path) -> bool {
+    let kubeconfig = kubeconfig.to_str().unwrap_or("");
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--kubeconfig" {
+            return iter.next().map(|s| s.as_str()) == Some(kubeconfig);
+        }
+        if let Some(path) = arg.strip_prefix("--kubeconfig=") {
+            return path == kubeconfig;
+        }
+    }
+
+    let env = match read_nul_list(environ) {
+        Some(env) => env,
+        None => return false,
+    };
+    let expect = format!("KUBECONFIG={kubeconfig}");
+    env.iter().any(|item| item == expect.as_bytes())
+}
+
+fn read_nul_list(path: &Path) -> Option<Vec<Vec<u8>>> {
+    let data = fs::read(path).ok()?;
+    Some(
+        data.split(|b| *b == 0)
+            .filter(|item| !item.is_empty())
+            .map(|item| item.to_vec())
+            .collect(),
+    )
+}
+
+/// Format the command line for the logs, the arguments with spaces are quoted.
+pub fn format_command(cmd: &Command) -> String {
+    let mut line = cmd.get_program().to_string_lossy().into_owned();
+    for arg in cmd.get_args() {
+        let arg = arg.to_string_lossy();
+        if arg.is_empty() || arg.contains(char::is_whitespace) {
+            line.push_str(&format!(" '{arg}'"));
+        } else {
+            line.push(' ');
+            line.push_str(&arg);
+        }
+    }
+    line
+}
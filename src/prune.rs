@@ -0,0 +1,114 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::config::Config;
+use crate::context::{confirm, get_kubeconfig_store_path};
+use crate::history::History;
+use crate::vault;
+
+/// The leftovers to remove.
+#[derive(Default)]
+struct Prunable {
+    links: Vec<PathBuf>,
+    dirs: Vec<PathBuf>,
+    /// The history entry count of every removed context.
+    history: BTreeMap<String, usize>,
+}
+
+/// Remove the broken symlinks and empty dirs in the kube dir (only the one we can
+/// write to), and the history entries of the removed contexts, after confirmation.
+pub fn prune(cfg: &Config, dry_run: bool) -> Result<()> {
+    let dir = cfg.kube.user_dir.as_ref().unwrap_or(&cfg.kube.dir);
+    let mut prunable = Prunable::default();
+    scan_dir(Path::new(dir), &mut prunable)?;
+    // The root dir is kept even if it is empty.
+    prunable.dirs.retain(|path| path != Path::new(dir));
+
+    // The history file is optional here.
+    if let Ok(history) = History::open(cfg) {
+        for entry in history.flatten() {
+            if let Some(count) = prunable.history.get_mut(&entry.name) {
+                *count += 1;
+                continue;
+            }
+            if !exists(cfg, &entry.name, &prunable.links) {
+                prunable.history.insert(entry.name, 1);
+            }
+        }
+    }
+
+    if prunable.links.is_empty() && prunable.dirs.is_empty() && prunable.history.is_empty() {
+        info!("Nothing to prune");
+        return Ok(());
+    }
+
+    for link in prunable.links.iter() {
+        println!("broken symlink: {}", link.display());
+    }
+    for dir in prunable.dirs.iter() {
+        println!("empty dir: {}", dir.display());
+    }
+    for (name, count) in prunable.history.iter() {
+        println!("history of removed context: {name} ({count} entries)");
+    }
+
+    if dry_run {
+        info!("Dry run, nothing was removed");
+        return Ok(());
+    }
+    if !confirm("Remove them")? {
+        return Ok(());
+    }
+
+    for link in prunable.links.iter() {
+        fs::remove_file(link)
+            .with_context(|| format!("remove broken symlink '{}'", link.display()))?;
+    }
+    // The dirs were collected bottom up, so the children go first.
+    for dir in prunable.dirs.iter() {
+        fs::remove_dir(dir).with_context(|| format!("remove empty dir '{}'", dir.display()))?;
+    }
+    if !prunable.history.is_empty() {
+        let names: HashSet<String> = prunable.history.into_keys().collect();
+        History::remove(cfg, &names)?;
+    }
+    Ok(())
+}
+
+/// Scan the dir recursively, returns true if it would be empty after pruning.
+fn scan_dir(dir: &Path, prunable: &mut Prunable) -> Result<bool> {
+    let mut empty = true;
+    for ent in fs::read_dir(dir).with_context(|| format!("read dir '{}'", dir.display()))? {
+        let ent = ent.with_context(|| format!("read sub entry for dir '{}'", dir.display()))?;
+        let path = ent.path();
+        let meta = fs::symlink_metadata(&path)
+            .with_context(|| format!("stat metadata for '{}'", path.display()))?;
+        if meta.is_dir() {
+            if !scan_dir(&path, prunable)? {
+                empty = false;
+            }
+            continue;
+        }
+        if meta.is_symlink() && fs::metadata(&path).is_err() {
+            prunable.links.push(path);
+            continue;
+        }
+        empty = false;
+    }
+    if empty {
+        prunable.dirs.push(dir.to_path_buf());
+    }
+    Ok(empty)
+}
+
+fn exists(cfg: &Config, name: &str, removed_links: &[PathBuf]) -> bool {
+    if vault::lookup(cfg, name).is_some() {
+        return true;
+    }
+    // The stored file, the encrypted context exists even if it cannot be decrypted.
+    let path = get_kubeconfig_store_path(cfg, name);
+    fs::metadata(&path).is_ok() && !removed_links.contains(&path)
+}
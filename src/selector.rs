@@ -0,0 +1,587 @@
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+use anyhow::{bail, Context, Result};
+
+use crate::config::SelectorConfig;
+use crate::process;
+
+/// Selector lets user select one item from a list, and returns its index.
+pub trait Selector: Send {
+    fn select(&self, keys: &[&str]) -> Result<usize>;
+
+    /// Like [`Selector::select`], but the user can also type a new item which is not
+    /// in the list. The `query` is the initial input. The selectors not supporting
+    /// input fall back to select.
+    fn select_or_input(&self, keys: &[&str], hint: &str, query: &str) -> Result<Selection> {
+        let _ = (hint, query);
+        Ok(Selection::Index(self.select(keys)?))
+    }
+
+    /// Like [`Selector::select`], but shows the auxiliary info aligned after each
+    /// key, and the output of the preview command for the focused key, `{2}` in the
+    /// command is replaced with the key. The selectors not supporting them fall back
+    /// to select.
+    fn select_with_info(
+        &self,
+        keys: &[&str],
+        infos: &[&str],
+        preview: Option<&str>,
+    ) -> Result<usize> {
+        let _ = (infos, preview);
+        self.select(keys)
+    }
+
+    /// Like [`Selector::select_with_info`], but the user can mark multiple items.
+    /// The selectors not supporting it fall back to select one.
+    fn select_many(&self, keys: &[&str], infos: &[&str]) -> Result<Vec<usize>> {
+        Ok(vec![self.select_with_info(keys, infos, None)?])
+    }
+}
+
+pub enum Selection {
+    /// Select an item in the list.
+    Index(usize),
+    /// Type a new item.
+    Input(String),
+}
+
+/// The default selector, launches fzf (or the configured command, such as sk and
+/// peco) to let user select interactively.
+pub struct CommandSelector {
+    exec: String,
+    args: Vec<String>,
+}
+
+/// The builtin selector prints a numbered list and reads user input from stdin, it
+/// is the fallback when the selector command is not installed.
+pub struct PromptSelector;
+
+/// The deterministic selector always selects the item at the index, useful for
+/// running non-interactively in scripts and tests.
+pub struct IndexSelector(pub usize);
+
+/// The selector always fails, used in non-interactive mode, so that the ambiguous
+/// names error out instead of blocking on a terminal.
+pub struct NoInteractiveSelector;
+
+static SELECTOR: OnceLock<Mutex<Box<dyn Selector>>> = OnceLock::new();
+
+fn get() -> &'static Mutex<Box<dyn Selector>> {
+    SELECTOR.get_or_init(|| Mutex::new(Box::new(CommandSelector::default())))
+}
+
+/// Replace the selector used by all the selections, the default is
+/// [`CommandSelector`] launching fzf.
+pub fn set(selector: Box<dyn Selector>) {
+    *get().lock().unwrap() = selector;
+}
+
+/// Select one item from the keys using the current selector.
+pub fn select<S: AsRef<str>>(keys: &[S]) -> Result<usize> {
+    let keys: Vec<&str> = keys.iter().map(|key| key.as_ref()).collect();
+    get().lock().unwrap().select(&keys)
+}
+
+/// Select one item from the keys using the current selector, the infos are shown
+/// after the keys, see [`Selector::select_with_info`].
+pub fn select_with_info<S: AsRef<str>>(
+    keys: &[S],
+    infos: &[String],
+    preview: Option<&str>,
+) -> Result<usize> {
+    let keys: Vec<&str> = keys.iter().map(|key| key.as_ref()).collect();
+    let infos: Vec<&str> = infos.iter().map(|info| info.as_str()).collect();
+    get()
+        .lock()
+        .unwrap()
+        .select_with_info(&keys, &infos, preview)
+}
+
+/// Select multiple items from the keys using the current selector, see
+/// [`Selector::select_many`].
+pub fn select_many<S: AsRef<str>>(keys: &[S], infos: &[String]) -> Result<Vec<usize>> {
+    let keys: Vec<&str> = keys.iter().map(|key| key.as_ref()).collect();
+    let infos: Vec<&str> = infos.iter().map(|info| info.as_str()).collect();
+    get().lock().unwrap().select_many(&keys, &infos)
+}
+
+/// Select one item from the keys or type a new one using the current selector.
+pub fn select_or_input<S: AsRef<str>>(keys: &[S], hint: &str, query: &str) -> Result<Selection> {
+    let keys: Vec<&str> = keys.iter().map(|key| key.as_ref()).collect();
+    get().lock().unwrap().select_or_input(&keys, hint, query)
+}
+
+impl Selector for NoInteractiveSelector {
+    fn select(&self, keys: &[&str]) -> Result<usize> {
+        bail!(
+            "cannot select from {} items in non-interactive mode, please specify the exact name",
+            keys.len()
+        );
+    }
+}
+
+impl Selector for IndexSelector {
+    fn select(&self, keys: &[&str]) -> Result<usize> {
+        if self.0 >= keys.len() {
+            bail!(
+                "select index {} out of range, there are only {} items",
+                self.0,
+                keys.len()
+            );
+        }
+        Ok(self.0)
+    }
+}
+
+impl CommandSelector {
+    /// The key to accept the typed query as a new item.
+    const INPUT_KEY: &'static str = "alt-enter";
+
+    pub fn new(cfg: &SelectorConfig) -> CommandSelector {
+        CommandSelector {
+            exec: cfg.exec.clone(),
+            args: cfg.args.clone(),
+        }
+    }
+
+    /// Run the selector command, return `None` if it is not installed.
+    fn run(&self, keys: &[&str], args: &[&str]) -> Result<Option<(i32, String)>> {
+        let mut input = String::with_capacity(keys.len());
+        for key in keys {
+            input.push_str(key);
+            input.push('\n');
+        }
+
+        let mut cmd = Command::new(&self.exec);
+        cmd.args(&self.args);
+        cmd.args(args);
+        cmd.stdin(Stdio::piped());
+        cmd.stderr(Stdio::inherit());
+        cmd.stdout(Stdio::piped());
+
+        let exec = &self.exec;
+        log::debug!(
+            "execute {} with {} items",
+            process::format_command(&cmd),
+            keys.len()
+        );
+        let start = Instant::now();
+        let mut child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to launch {exec}"));
+            }
+        };
+
+        let handle = child.stdin.as_mut().unwrap();
+        write!(handle, "{input}").with_context(|| format!("write input to {exec}"))?;
+        drop(child.stdin.take());
+
+        let mut stdout = child.stdout.take();
+
+        let status = child.wait().with_context(|| format!("wait {exec} done"))?;
+        log::debug!("{exec} exited with {status} in {:.2?}", start.elapsed());
+
+        let mut out = String::new();
+        if let Some(stdout) = stdout.as_mut() {
+            stdout
+                .read_to_string(&mut out)
+                .with_context(|| format!("read {exec} output"))?;
+        }
+
+        match status.code() {
+            Some(code @ (0 | 1)) => Ok(Some((code, out))),
+            Some(2) => bail!("{exec} returned an error"),
+            Some(130) => bail!("{exec} canceled"),
+            Some(128..=254) | None => bail!("{exec} was terminated"),
+            _ => bail!("{exec} returned an unknown error"),
+        }
+    }
+
+    fn warn_fallback(&self) {
+        warning!(
+            "cannot find {} in your system, fall back to the builtin selector, please install it for better experience",
+            self.exec
+        );
+    }
+
+    /// Only fzf and its clone sk support the flags to type a new item or show
+    /// auxiliary columns.
+    fn support_fzf_flags(&self) -> bool {
+        let name = Path::new(&self.exec)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        matches!(name, "fzf" | "sk")
+    }
+
+    fn parse_index_row(&self, keys: &[&str], row: &str) -> Result<usize> {
+        let idx = row
+            .split_once('\t')
+            .and_then(|(idx, _)| idx.parse::<usize>().ok());
+        match idx {
+            Some(idx) if idx < keys.len() => Ok(idx),
+            _ => bail!("cannot parse row '{row}' from {} output", self.exec),
+        }
+    }
+
+    fn find_key(&self, keys: &[&str], result: &str) -> Result<usize> {
+        match keys.iter().position(|s| *s == result) {
+            Some(idx) => Ok(idx),
+            None => bail!("cannot find key '{result}' from {} output", self.exec),
+        }
+    }
+}
+
+impl Default for CommandSelector {
+    fn default() -> Self {
+        Self::new(&SelectorConfig::default())
+    }
+}
+
+impl Selector for CommandSelector {
+    fn select(&self, keys: &[&str]) -> Result<usize> {
+        let (code, out) = match self.run(keys, &[])? {
+            Some(result) => result,
+            None => {
+                self.warn_fallback();
+                return PromptSelector.select(keys);
+            }
+        };
+        if code == 1 {
+            bail!("{} no match found", self.exec);
+        }
+
+        let result = out.trim();
+        if result.is_empty() {
+            bail!("{} did not output anything", self.exec);
+        }
+        self.find_key(keys, result)
+    }
+
+    fn select_or_input(&self, keys: &[&str], hint: &str, query: &str) -> Result<Selection> {
+        if !self.support_fzf_flags() {
+            return Ok(Selection::Index(self.select(keys)?));
+        }
+
+        let header = format!("{hint}, press {} to use the typed name", Self::INPUT_KEY);
+        let expect = format!("--expect={}", Self::INPUT_KEY);
+        let query_arg = format!("--query={query}");
+        let args = ["--print-query", &expect, "--header", &header, &query_arg];
+        let (code, out) = match self.run(keys, &args)? {
+            Some(result) => result,
+            None => {
+                self.warn_fallback();
+                return PromptSelector.select_or_input(keys, hint, query);
+            }
+        };
+
+        // The output lines are: query, the pressed expect key, selected item.
+        let mut lines = out.lines();
+        let query = lines.next().unwrap_or("").trim();
+        let key = lines.next().unwrap_or("").trim();
+        let result = lines.next().unwrap_or("").trim();
+
+        if code == 1 || key == Self::INPUT_KEY || result.is_empty() {
+            if query.is_empty() {
+                bail!("{} no match found", self.exec);
+            }
+            return Ok(Selection::Input(query.to_string()));
+        }
+        Ok(Selection::Index(self.find_key(keys, result)?))
+    }
+
+    fn select_with_info(
+        &self,
+        keys: &[&str],
+        infos: &[&str],
+        preview: Option<&str>,
+    ) -> Result<usize> {
+        if !self.support_fzf_flags() {
+            return self.select(keys);
+        }
+
+        let rows = format_index_rows(keys, infos);
+        let rows: Vec<&str> = rows.iter().map(|row| row.as_str()).collect();
+
+        let mut args = vec!["--delimiter=\t", "--with-nth=3.."];
+        if let Some(preview) = preview {
+            args.extend(["--preview", preview]);
+        }
+        let (code, out) = match self.run(&rows, &args)? {
+            Some(result) => result,
+            None => {
+                self.warn_fallback();
+                return PromptSelector.select_with_info(keys, infos, preview);
+            }
+        };
+        if code == 1 {
+            bail!("{} no match found", self.exec);
+        }
+
+        self.parse_index_row(keys, out.trim())
+    }
+
+    fn select_many(&self, keys: &[&str], infos: &[&str]) -> Result<Vec<usize>> {
+        if !self.support_fzf_flags() {
+            return Ok(vec![self.select(keys)?]);
+        }
+
+        let rows = format_index_rows(keys, infos);
+        let rows: Vec<&str> = rows.iter().map(|row| row.as_str()).collect();
+        let args = [
+            "--multi",
+            "--delimiter=\t",
+            "--with-nth=3..",
+            "--bind=ctrl-a:select-all",
+            "--header",
+            "Press TAB to mark items, ctrl-a to mark all",
+        ];
+        let (code, out) = match self.run(&rows, &args)? {
+            Some(result) => result,
+            None => {
+                self.warn_fallback();
+                return PromptSelector.select_many(keys, infos);
+            }
+        };
+        if code == 1 {
+            bail!("{} no match found", self.exec);
+        }
+
+        let mut selected = Vec::new();
+        for line in out.lines() {
+            let line = line.trim();
+            if !line.is_empty() {
+                selected.push(self.parse_index_row(keys, line)?);
+            }
+        }
+        if selected.is_empty() {
+            bail!("{} did not output anything", self.exec);
+        }
+        Ok(selected)
+    }
+}
+
+/// Every row is "{index}\t{key}\t{key} {info}", only the last part is shown, and
+/// the selected row is mapped back by index rather than searching key. The bare
+/// key is kept for the preview command.
+fn format_index_rows(keys: &[&str], infos: &[&str]) -> Vec<String> {
+    format_rows(keys, infos)
+        .into_iter()
+        .enumerate()
+        .map(|(idx, row)| format!("{idx}\t{}\t{row}", keys[idx]))
+        .collect()
+}
+
+/// Pad the keys to the same width and append the infos, so that the infos are
+/// aligned as a column.
+fn format_rows(keys: &[&str], infos: &[&str]) -> Vec<String> {
+    let width = keys
+        .iter()
+        .map(|key| key.chars().count())
+        .max()
+        .unwrap_or(0);
+    keys.iter()
+        .enumerate()
+        .map(|(idx, key)| match infos.get(idx) {
+            Some(info) if !info.is_empty() => format!("{key:<width$}  {info}"),
+            _ => key.to_string(),
+        })
+        .collect()
+}
+
+impl PromptSelector {
+    /// Print the items and read user input. A number selects the item, a text
+    /// filters the items. Return the selection, or the text if nothing matches and
+    /// input is allowed.
+    fn prompt(
+        &self,
+        keys: &[&str],
+        infos: &[&str],
+        hint: Option<&str>,
+        query: &str,
+    ) -> Result<Selection> {
+        let rows = format_rows(keys, infos);
+        let mut filter = query.to_string();
+        // The initial query was not typed here, returning it as input directly
+        // would make the caller retrying with it loop forever.
+        let mut typed = false;
+        loop {
+            let items: Vec<usize> = (0..keys.len())
+                .filter(|idx| keys[*idx].contains(filter.as_str()))
+                .collect();
+            if items.is_empty() {
+                if typed && hint.is_some() && !filter.is_empty() {
+                    return Ok(Selection::Input(filter));
+                }
+                if filter.is_empty() {
+                    bail!("no item to select");
+                }
+                warning!("no item matches '{filter}'");
+                filter.clear();
+                continue;
+            }
+
+            for (num, idx) in items.iter().enumerate() {
+                eprintln!("{:>3}) {}", num + 1, rows[*idx]);
+            }
+            if let Some(hint) = hint {
+                eprintln!("{hint}");
+            }
+            eprint!("Enter number or text to filter: ");
+
+            let mut answer = String::new();
+            io::stdin()
+                .read_line(&mut answer)
+                .context("read selection from stdin")?;
+            let answer = answer.trim();
+            if answer.is_empty() {
+                bail!("selection canceled");
+            }
+            typed = true;
+
+            if let Ok(num) = answer.parse::<usize>() {
+                if num >= 1 && num <= items.len() {
+                    return Ok(Selection::Index(items[num - 1]));
+                }
+                warning!("number {num} is out of range");
+                continue;
+            }
+
+            if let Some(idx) = keys.iter().position(|key| *key == answer) {
+                return Ok(Selection::Index(idx));
+            }
+            filter = answer.to_string();
+        }
+    }
+}
+
+impl Selector for PromptSelector {
+    fn select(&self, keys: &[&str]) -> Result<usize> {
+        self.select_with_info(keys, &[], None)
+    }
+
+    fn select_or_input(&self, keys: &[&str], hint: &str, query: &str) -> Result<Selection> {
+        self.prompt(keys, &[], Some(hint), query)
+    }
+
+    fn select_with_info(
+        &self,
+        keys: &[&str],
+        infos: &[&str],
+        _preview: Option<&str>,
+    ) -> Result<usize> {
+        match self.prompt(keys, infos, None, "")? {
+            Selection::Index(idx) => Ok(idx),
+            Selection::Input(_) => unreachable!(),
+        }
+    }
+
+    fn select_many(&self, keys: &[&str], infos: &[&str]) -> Result<Vec<usize>> {
+        if keys.is_empty() {
+            bail!("no item to select");
+        }
+        let rows = format_rows(keys, infos);
+        'prompt: loop {
+            for (idx, row) in rows.iter().enumerate() {
+                eprintln!("{:>3}) {row}", idx + 1);
+            }
+            eprint!("Enter numbers separated by space, or 'all': ");
+
+            let mut answer = String::new();
+            io::stdin()
+                .read_line(&mut answer)
+                .context("read selection from stdin")?;
+            let answer = answer.trim();
+            if answer.is_empty() {
+                bail!("selection canceled");
+            }
+            if answer == "all" {
+                return Ok((0..keys.len()).collect());
+            }
+
+            let mut selected = Vec::new();
+            for field in answer.split([' ', ',']).filter(|field| !field.is_empty()) {
+                match field.parse::<usize>() {
+                    Ok(num) if num >= 1 && num <= keys.len() => selected.push(num - 1),
+                    _ => {
+                        warning!("invalid number '{field}'");
+                        continue 'prompt;
+                    }
+                }
+            }
+            selected.sort_unstable();
+            selected.dedup();
+            return Ok(selected);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYS: [&str; 3] = ["team/a", "team/bb", "prod"];
+
+    #[test]
+    fn test_index_selector() {
+        let selector = IndexSelector(1);
+        assert_eq!(selector.select(&KEYS).unwrap(), 1);
+        assert_eq!(selector.select_with_info(&KEYS, &[], None).unwrap(), 1);
+        assert_eq!(selector.select_many(&KEYS, &[]).unwrap(), vec![1]);
+        match selector.select_or_input(&KEYS, "", "").unwrap() {
+            Selection::Index(idx) => assert_eq!(idx, 1),
+            Selection::Input(input) => panic!("unexpected input '{input}'"),
+        }
+
+        let err = IndexSelector(3).select(&KEYS).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "select index 3 out of range, there are only 3 items"
+        );
+    }
+
+    #[test]
+    fn test_no_interactive_selector() {
+        let err = NoInteractiveSelector.select(&KEYS).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cannot select from 3 items in non-interactive mode, please specify the exact name"
+        );
+        assert!(NoInteractiveSelector
+            .select_with_info(&KEYS, &[], None)
+            .is_err());
+        assert!(NoInteractiveSelector.select_many(&KEYS, &[]).is_err());
+    }
+
+    #[test]
+    fn test_format_index_rows() {
+        let rows = format_index_rows(&KEYS, &["payments", "", "default"]);
+        assert_eq!(
+            rows,
+            vec![
+                "0\tteam/a\tteam/a   payments",
+                "1\tteam/bb\tteam/bb",
+                "2\tprod\tprod     default",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_index_row() {
+        let selector = CommandSelector::default();
+        let rows = format_index_rows(&KEYS, &["payments", "", "default"]);
+        for (idx, row) in rows.iter().enumerate() {
+            assert_eq!(selector.parse_index_row(&KEYS, row).unwrap(), idx);
+        }
+
+        for row in ["3\tteam/a\tteam/a", "x\tteam/a\tteam/a", "team/a"] {
+            assert!(selector.parse_index_row(&KEYS, row).is_err(), "{row}");
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::env;
+use std::fs;
+use std::net::ToSocketAddrs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+
+use crate::config::{get_cache_dir, get_home_dir, get_runtime_dir, Config};
+use crate::context::{self, KubeContext};
+use crate::process::format_command;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    /// Works, but some features might not.
+    Warn,
+    Failed,
+    Skipped,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Warn => "warn",
+            Self::Failed => "failed",
+            Self::Skipped => "skipped",
+        }
+    }
+}
+
+/// The result of one check of the self-test, the detail tells what to do if it
+/// is not ok.
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub detail: String,
+}
+
+/// Verify the runtime assumptions of the binary: spawning commands, resolving the
+/// cluster server, the locale and the writable dirs. They are usually broken when
+/// running the static (musl) build in a minimal container.
+pub fn self_test(cfg: &Config) -> Vec<Check> {
+    vec![
+        check_spawn(),
+        check_kubectl(cfg),
+        check_dns(cfg),
+        check_locale(),
+        check_home(),
+        check_dir("runtime dir", &get_runtime_dir(), "XDG_RUNTIME_DIR"),
+        check_dir("cache dir", &get_cache_dir(), "XDG_CACHE_HOME"),
+    ]
+}
+
+fn check_spawn() -> Check {
+    let mut cmd = Command::new("sh");
+    cmd.args(["-c", "exit 0"]);
+    match run(&mut cmd) {
+        Ok(()) => ok("spawn", "spawned 'sh'"),
+        Err(err) => failed(
+            "spawn",
+            format!("{err:#}, the hooks and the discover commands run by 'sh'"),
+        ),
+    }
+}
+
+fn check_kubectl(cfg: &Config) -> Check {
+    let exec = &cfg.kube.exec;
+    let path = match context::find_exec(exec) {
+        Some(path) => path,
+        None => {
+            return failed(
+                "kubectl",
+                format!("'{exec}' is not found in $PATH, install it or set `kube.exec`"),
+            )
+        }
+    };
+    let mut cmd = Command::new(&path);
+    cmd.args(["version", "--client"]);
+    match run(&mut cmd) {
+        Ok(()) => ok("kubectl", format!("ran '{}'", path.display())),
+        Err(err) => failed("kubectl", format!("{err:#}")),
+    }
+}
+
+/// Resolve the server host of the current context, the same as the kubectl
+/// probes of `ks check` would.
+fn check_dns(cfg: &Config) -> Check {
+    if cfg.offline {
+        return skipped("dns", "offline mode");
+    }
+    let server = match KubeContext::try_current(cfg)
+        .ok()
+        .flatten()
+        .and_then(|ctx| ctx.fingerprint().ok().flatten())
+    {
+        Some((server, _)) => server,
+        None => return skipped("dns", "no current context with a cluster server"),
+    };
+    let (host, port) = match parse_host(&server) {
+        Some(host) => host,
+        None => return skipped("dns", format!("cannot parse the host of '{server}'")),
+    };
+
+    match (host, port).to_socket_addrs() {
+        Ok(mut addrs) => match addrs.next() {
+            Some(addr) => ok("dns", format!("resolved '{host}' to {}", addr.ip())),
+            None => failed("dns", format!("no address for '{host}'")),
+        },
+        Err(err) => failed(
+            "dns",
+            format!(
+                "resolve '{host}': {err}, the static build reads /etc/resolv.conf and /etc/hosts only, without nsswitch"
+            ),
+        ),
+    }
+}
+
+/// The host and port of the server URL, such as `https://10.0.0.1:6443`.
+fn parse_host(server: &str) -> Option<(&str, u16)> {
+    let (scheme, rest) = server.split_once("://")?;
+    let authority = rest.split('/').next()?;
+    let default_port = if scheme == "http" { 80 } else { 443 };
+    // The IPv6 address is in brackets, such as `[::1]:6443`.
+    if let Some(rest) = authority.strip_prefix('[') {
+        let (host, rest) = rest.split_once(']')?;
+        let port = match rest.strip_prefix(':') {
+            Some(port) => port.parse().ok()?,
+            None => default_port,
+        };
+        return Some((host, port));
+    }
+    match authority.rsplit_once(':') {
+        Some((host, port)) => Some((host, port.parse().ok()?)),
+        None => Some((authority, default_port)),
+    }
+}
+
+fn check_locale() -> Check {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .into_iter()
+        .find_map(|key| env::var(key).ok().filter(|value| !value.is_empty()));
+    let locale = match locale {
+        Some(locale) => locale,
+        None => {
+            return warn(
+                "locale",
+                "no locale is set, the selector and the emoji in prompt might be garbled, set LANG=C.UTF-8",
+            )
+        }
+    };
+    let lower = locale.to_lowercase();
+    if lower.contains("utf-8") || lower.contains("utf8") {
+        return ok("locale", locale);
+    }
+    warn(
+        "locale",
+        format!("'{locale}' is not UTF-8, the selector and the emoji in prompt might be garbled, set LANG=C.UTF-8"),
+    )
+}
+
+fn check_home() -> Check {
+    match get_home_dir() {
+        Ok(home) => ok("home", home.display().to_string()),
+        Err(err) => failed("home", format!("{err:#}")),
+    }
+}
+
+fn check_dir(name: &'static str, dir: &Path, env: &str) -> Check {
+    let probe = dir.join(format!(".selftest-{}", std::process::id()));
+    let result = context::create_private_dir(dir).and_then(|_| {
+        fs::write(&probe, b"")
+            .with_context(|| format!("write probe file '{}'", probe.display()))?;
+        fs::remove_file(&probe).with_context(|| format!("remove probe file '{}'", probe.display()))
+    });
+    match result {
+        Ok(()) => ok(name, dir.display().to_string()),
+        Err(err) => failed(name, format!("{err:#}, set {env} to a writable dir")),
+    }
+}
+
+fn run(cmd: &mut Command) -> Result<()> {
+    let display = format_command(cmd);
+    let output = cmd
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("spawn '{display}'"))?;
+    if !output.status.success() {
+        bail!("'{display}' exited with {}", output.status);
+    }
+    Ok(())
+}
+
+fn ok(name: &'static str, detail: impl Into<String>) -> Check {
+    new_check(name, Status::Ok, detail)
+}
+
+fn warn(name: &'static str, detail: impl Into<String>) -> Check {
+    new_check(name, Status::Warn, detail)
+}
+
+fn failed(name: &'static str, detail: impl Into<String>) -> Check {
+    new_check(name, Status::Failed, detail)
+}
+
+fn skipped(name: &'static str, detail: impl Into<String>) -> Check {
+    new_check(name, Status::Skipped, detail)
+}
+
+fn new_check(name: &'static str, status: Status, detail: impl Into<String>) -> Check {
+    Check {
+        name,
+        status,
+        detail: detail.into(),
+    }
+}
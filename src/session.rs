@@ -0,0 +1,172 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::config::{get_runtime_dir, Config};
+use crate::context::create_private_dir;
+
+/// The env snapshot of the shell session, taken at the first switch in the shell,
+/// so that unsetting restores the values before switching, such as a KUBECONFIG
+/// set by the user, rather than just clearing them.
+pub struct Session;
+
+impl Session {
+    /// The shell pid exported by the wrap script to identify the session.
+    const SESSION_ENV: &'static str = "KUBESWITCH_SESSION";
+
+    /// The env set by the wrap script when switching. KUBECONFIG is only set with
+    /// `kube.export_kubeconfig` or by `ks merge`, it is recorded separately.
+    const VARS: [&'static str; 5] = [
+        "KUBESWITCH_NAME",
+        "KUBESWITCH_NAMESPACE",
+        "KUBESWITCH_DISPLAY",
+        "KUBESWITCH_COLOR",
+        "KUBESWITCH_EMOJI",
+    ];
+
+    /// The line marking that KUBECONFIG is recorded, so it is managed by kubeswitch
+    /// in the session even without `kube.export_kubeconfig`. It has no '=', which
+    /// is not restored.
+    const KUBECONFIG_MARK: &'static str = "#KUBECONFIG";
+
+    /// Record the current values of the env to be set by switching. Only the first
+    /// switch in the session is recorded, the later ones see the values set by
+    /// kubeswitch itself, except that KUBECONFIG is recorded by the first merged
+    /// switch, see `ks merge`.
+    pub fn record(cfg: &Config, merged: bool) -> Result<()> {
+        let path = match Self::get_path() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        // The session file left by a dead shell with the same pid is overwritten,
+        // since this shell has not switched yet.
+        if env::var_os("KUBESWITCH_NAME").is_some() {
+            if merged && !Self::is_kubeconfig_recorded() {
+                return Self::record_kubeconfig(&path);
+            }
+            return Ok(());
+        }
+
+        let mut data = String::new();
+        for name in Self::VARS {
+            Self::record_var(&mut data, name);
+        }
+        if cfg.kube.export_kubeconfig || merged {
+            Self::record_var(&mut data, "KUBECONFIG");
+            data.push_str(&format!("{}\n", Self::KUBECONFIG_MARK));
+        }
+        if let Some(dir) = path.parent() {
+            create_private_dir(dir)?;
+        }
+        fs::write(&path, data).with_context(|| format!("write session file '{}'", path.display()))
+    }
+
+    /// Whether KUBECONFIG is recorded in the session, it should be exported when
+    /// switching, and unset or restored when unsetting.
+    pub fn is_kubeconfig_recorded() -> bool {
+        let path = match Self::get_path() {
+            Some(path) => path,
+            None => return false,
+        };
+        match fs::read_to_string(path) {
+            Ok(data) => data.lines().any(|line| line == Self::KUBECONFIG_MARK),
+            Err(_) => false,
+        }
+    }
+
+    /// Append the KUBECONFIG to the session file, it is not set by kubeswitch yet.
+    fn record_kubeconfig(path: &Path) -> Result<()> {
+        let mut data = fs::read_to_string(path).unwrap_or_default();
+        if !data.is_empty() && !data.ends_with('\n') {
+            data.push('\n');
+        }
+        Self::record_var(&mut data, "KUBECONFIG");
+        data.push_str(&format!("{}\n", Self::KUBECONFIG_MARK));
+        if let Some(dir) = path.parent() {
+            create_private_dir(dir)?;
+        }
+        fs::write(path, data).with_context(|| format!("write session file '{}'", path.display()))
+    }
+
+    fn record_var(data: &mut String, name: &str) {
+        // The values are restored by lines.
+        if let Ok(value) = env::var(name) {
+            if !value.contains('\n') {
+                data.push_str(&format!("{name}={value}\n"));
+            }
+        }
+    }
+
+    /// Take the recorded values to restore, in `NAME=VALUE` format, the session
+    /// ends. The env not recorded should be unset.
+    pub fn restore() -> Result<Vec<String>> {
+        let path = match Self::get_path() {
+            Some(path) => path,
+            None => return Ok(Vec::new()),
+        };
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("read session file '{}'", path.display()))
+            }
+        };
+        fs::remove_file(&path)
+            .with_context(|| format!("remove session file '{}'", path.display()))?;
+
+        Ok(data
+            .lines()
+            .filter(|line| line.contains('='))
+            .map(String::from)
+            .collect())
+    }
+
+    /// Remember the pin file applied by the auto switch in the session, `None` after
+    /// leaving the pinned dirs. Return false if it is the same as the last one, so
+    /// that moving between the subdirs of the project does not switch back after the
+    /// user switched away.
+    pub fn swap_pin(pin: Option<&Path>) -> Result<bool> {
+        let path = match Self::get_path() {
+            Some(path) => path.with_extension("pin"),
+            None => return Ok(pin.is_some()),
+        };
+        let last = fs::read_to_string(&path).ok();
+        let pin = pin.map(|pin| pin.to_string_lossy());
+        if last.as_deref() == pin.as_deref() {
+            return Ok(false);
+        }
+
+        match pin {
+            Some(pin) => {
+                if let Some(dir) = path.parent() {
+                    create_private_dir(dir)?;
+                }
+                fs::write(&path, pin.as_bytes())
+                    .with_context(|| format!("write session pin file '{}'", path.display()))?;
+            }
+            None => {
+                fs::remove_file(&path)
+                    .with_context(|| format!("remove session pin file '{}'", path.display()))?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Check if the wrap script passes the session, it is supported since the
+    /// protocol version 4.
+    pub fn is_enabled() -> bool {
+        Self::get_path().is_some()
+    }
+
+    /// Return `None` if the wrap script does not pass the session.
+    fn get_path() -> Option<PathBuf> {
+        let session = env::var(Self::SESSION_ENV).ok()?;
+        if session.is_empty() || !session.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        Some(get_runtime_dir().join("sessions").join(session))
+    }
+}
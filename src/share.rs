@@ -0,0 +1,140 @@
+use std::fs;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde_yaml::{Mapping, Value};
+
+use crate::config::Config;
+use crate::context::{ensure_dir, KubeContext, SelectOption};
+use crate::history;
+use crate::import::KubeconfigDoc;
+
+/// The credential fields of the user entry, removed by redacting.
+const USER_SECRET_FIELDS: [&str; 7] = [
+    "token",
+    "tokenFile",
+    "client-certificate",
+    "client-certificate-data",
+    "client-key",
+    "client-key-data",
+    "password",
+];
+
+/// The credential fields of the auth provider config, removed by redacting.
+const AUTH_PROVIDER_SECRET_FIELDS: [&str; 4] =
+    ["access-token", "client-secret", "id-token", "refresh-token"];
+
+/// Export the contexts as standalone kubeconfigs to send to teammates. A single
+/// context is printed to stdout, or all of them are written to the `bundle` tar
+/// file.
+pub fn share(cfg: &Config, names: &[String], redact: bool, bundle: &Option<String>) -> Result<()> {
+    let bundle = match bundle.as_ref() {
+        Some(bundle) => bundle,
+        None => {
+            if names.len() != 1 {
+                bail!("sharing multiple contexts requires `--bundle`");
+            }
+            let content = export(cfg, &names[0], redact)?;
+            print!("{content}");
+            return Ok(());
+        }
+    };
+
+    let path = shellexpand::full(bundle)
+        .with_context(|| format!("expand bundle path '{bundle}'"))?
+        .into_owned();
+    ensure_dir(Path::new(&path))?;
+    // The bundle holds the credentials unless redacted.
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(&path)
+        .with_context(|| format!("create bundle file '{path}'"))?;
+    let mut builder = tar::Builder::new(file);
+    for name in names {
+        let content = export(cfg, name, redact)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o600);
+        header.set_mtime(history::now());
+        header.set_cksum();
+        builder
+            .append_data(&mut header, name, content.as_bytes())
+            .with_context(|| format!("append context '{name}' to bundle"))?;
+    }
+    builder
+        .into_inner()
+        .with_context(|| format!("write bundle file '{path}'"))?;
+
+    info!("Shared {} contexts to '{path}'", names.len());
+    Ok(())
+}
+
+/// Build the sanitized kubeconfig of the context, only its cluster and user are
+/// kept, see [`KubeconfigDoc::minify`].
+fn export(cfg: &Config, name: &str, redact: bool) -> Result<String> {
+    let ctx = KubeContext::select(cfg, &Some(name.to_string()), SelectOption::GetRequired)?;
+    let doc = KubeconfigDoc::read(&ctx.get_decrypted_path()?)?;
+    let context = doc.main_context()?;
+    let mut mapping = doc
+        .minify_mapping(&context)
+        .with_context(|| format!("minify kubeconfig of '{name}'"))?;
+
+    let mut header = format!("# The kubeconfig of context '{name}' shared by kubeswitch.\n");
+    if redact {
+        let login = redact_users(&mut mapping);
+        match login {
+            Some(login) => header.push_str(&format!(
+                "# The credentials are removed, they are issued by running: {login}\n"
+            )),
+            None => header
+                .push_str("# The credentials are removed, please fill in your own in 'users'.\n"),
+        }
+    }
+
+    let content = serde_yaml::to_string(&mapping).context("serialize kubeconfig")?;
+    Ok(format!("{header}{content}"))
+}
+
+/// Remove the credentials of the users, returns the login command of the exec
+/// plugin if there is one, so that the teammate knows how to log in.
+pub fn redact_users(doc: &mut Mapping) -> Option<String> {
+    let users = match doc.get_mut("users") {
+        Some(Value::Sequence(users)) => users,
+        _ => return None,
+    };
+
+    let mut login = None;
+    for user in users.iter_mut() {
+        let entry = match user.get_mut("user") {
+            Some(Value::Mapping(entry)) => entry,
+            _ => continue,
+        };
+        for field in USER_SECRET_FIELDS {
+            entry.remove(field);
+        }
+        if let Some(Value::Mapping(provider)) = entry.get_mut("auth-provider") {
+            if let Some(Value::Mapping(config)) = provider.get_mut("config") {
+                for field in AUTH_PROVIDER_SECRET_FIELDS {
+                    config.remove(field);
+                }
+            }
+        }
+        if let Some(exec) = entry.get("exec") {
+            login = get_exec_command(exec);
+        }
+    }
+    login
+}
+
+fn get_exec_command(exec: &Value) -> Option<String> {
+    let command = exec.get("command").and_then(Value::as_str)?;
+    let mut fields = vec![command];
+    if let Some(Value::Sequence(args)) = exec.get("args") {
+        fields.extend(args.iter().filter_map(Value::as_str));
+    }
+    Some(fields.join(" "))
+}
@@ -0,0 +1,418 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Instant;
+use std::{env, fs};
+
+use anyhow::{bail, Context, Result};
+use serde_yaml::Mapping;
+
+use crate::changes::{Change, ChangeKind};
+use crate::config::{Config, SyncConfig, SyncSecrets};
+use crate::context::quote_shell;
+use crate::encryption;
+use crate::ignore::IgnoreRules;
+use crate::process;
+use crate::share;
+
+/// The git filter removing the credentials when committing, it calls back to the
+/// hidden `sync-filter` command.
+const REDACT_FILTER: &str = "kubeswitch-redact";
+
+/// The header of the git info files managed by kubeswitch, they are rewritten on
+/// every sync.
+const MANAGED_HEADER: &str = "# Generated by kubeswitch from `sync` config, DO NOT EDIT.\n";
+
+/// The git working tree of `kube.dir`.
+struct Repo<'a> {
+    cfg: &'a Config,
+    sync: &'a SyncConfig,
+    dir: &'a Path,
+}
+
+/// Commit all the changes in `kube.dir` and push them to the remote. The remote
+/// must be merged by [`pull`] first if it has new commits.
+pub fn push(cfg: &Config) -> Result<()> {
+    if cfg.offline {
+        bail!("cannot sync in offline mode");
+    }
+    let repo = Repo::open(cfg)?;
+    repo.output(["add", "--all"])?;
+    if repo.sync.secrets == SyncSecrets::Encrypted {
+        if let Err(err) = repo.check_encrypted() {
+            repo.output(["reset", "--quiet"])?;
+            return Err(err);
+        }
+    }
+
+    let staged = repo.output(["diff", "--cached", "--name-only"])?;
+    if !staged.is_empty() {
+        let message = format!("Sync from {}", get_hostname());
+        repo.output(["commit", "--quiet", "--message", &message])?;
+    }
+
+    let head = match repo.rev_parse("HEAD")? {
+        Some(head) => head,
+        None => {
+            info!("Nothing to push, the kube dir is empty");
+            return Ok(());
+        }
+    };
+    let tracking = format!("refs/remotes/origin/{}", repo.sync.branch);
+    if repo.rev_parse(&tracking)?.as_deref() == Some(head.as_str()) {
+        info!("Everything is up to date");
+        return Ok(());
+    }
+
+    let refspec = format!("HEAD:refs/heads/{}", repo.sync.branch);
+    repo.run(["push", "--quiet", "origin", &refspec]).context(
+        "push to remote, if the remote has new changes, please run `ks sync pull` first",
+    )?;
+    info!("Pushed to {}", repo.sync.remote);
+    Ok(())
+}
+
+/// Fetch and merge the remote changes into `kube.dir`, returns the changed
+/// contexts. Nothing is merged if any of the changed files was also edited
+/// locally and not pushed yet.
+pub fn pull(cfg: &Config) -> Result<Vec<Change>> {
+    if cfg.offline {
+        bail!("cannot sync in offline mode");
+    }
+    let repo = Repo::open(cfg)?;
+    repo.run(["fetch", "--quiet", "origin", &repo.sync.branch])
+        .with_context(|| {
+            format!(
+                "fetch branch '{}' from remote, please run `ks sync push` first if the remote is empty",
+                repo.sync.branch
+            )
+        })?;
+
+    let remote_changes = match repo.rev_parse("HEAD")? {
+        // Only the changes made in remote since the last merge, the local commits
+        // not pushed yet are excluded.
+        Some(_) => repo.output_nul([
+            "diff",
+            "--name-status",
+            "--no-renames",
+            "-z",
+            "HEAD...FETCH_HEAD",
+        ])?,
+        None => repo
+            .output_nul(["ls-tree", "-r", "--name-only", "-z", "FETCH_HEAD"])?
+            .into_iter()
+            .flat_map(|path| [String::from("A"), path])
+            .collect(),
+    };
+    let remote_changes: Vec<(&str, &str)> = remote_changes
+        .chunks_exact(2)
+        .map(|pair| (pair[0].as_str(), pair[1].as_str()))
+        .collect();
+    if remote_changes.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let local = repo.get_local_changes()?;
+    let conflicts: Vec<_> = remote_changes
+        .iter()
+        .filter(|(_, path)| local.contains(*path))
+        .map(|(_, path)| repo.get_name(path))
+        .collect();
+    if !conflicts.is_empty() {
+        bail!(
+            "these files are changed both locally and in remote: {}, please revert the local changes, or resolve them with git in '{}'",
+            conflicts.join(", "),
+            repo.dir.display()
+        );
+    }
+
+    if let Err(err) = repo.run(["merge", "--quiet", "--no-edit", "FETCH_HEAD"]) {
+        // Leave the kube dir as it was, the merge state is confusing for the user.
+        let _ = repo.output(["merge", "--abort"]);
+        return Err(err).context("merge remote changes");
+    }
+
+    let rules = IgnoreRules::load(repo.dir)?;
+    let mut changes = Vec::with_capacity(remote_changes.len());
+    for (status, path) in remote_changes {
+        let name = repo.get_name(path);
+        if rules.is_ignored(&name) {
+            continue;
+        }
+        let kind = match status {
+            "A" => ChangeKind::Added,
+            "D" => ChangeKind::Removed,
+            _ => ChangeKind::Updated,
+        };
+        changes.push(Change {
+            name,
+            kind,
+            modified: None,
+        });
+    }
+    if repo.sync.secrets == SyncSecrets::Redact && !changes.is_empty() {
+        warning!(
+            "the credentials in the pulled contexts are redacted, please log in again if needed"
+        );
+    }
+    Ok(changes)
+}
+
+/// The git clean filter for redacting, the kubeconfig in stdin is written to
+/// stdout without the credentials. The other files, such as the encrypted ones,
+/// are kept as they are.
+pub fn filter() -> Result<()> {
+    let mut data = Vec::new();
+    io::stdin()
+        .read_to_end(&mut data)
+        .context("read content from stdin")?;
+
+    let mapping = std::str::from_utf8(&data)
+        .ok()
+        .and_then(|content| serde_yaml::from_str::<Mapping>(content).ok())
+        .filter(|mapping| mapping.contains_key("users"));
+    let data = match mapping {
+        Some(mut mapping) => {
+            share::redact_users(&mut mapping);
+            serde_yaml::to_string(&mapping)
+                .context("serialize redacted kubeconfig")?
+                .into_bytes()
+        }
+        None => data,
+    };
+
+    io::stdout()
+        .write_all(&data)
+        .context("write content to stdout")
+}
+
+impl Repo<'_> {
+    /// Open the git working tree of `kube.dir`, it is initialized on first use. The
+    /// remote and the managed git info files are updated according to the config.
+    fn open(cfg: &Config) -> Result<Repo<'_>> {
+        let sync = match cfg.sync.as_ref() {
+            Some(sync) => sync,
+            None => bail!("sync is not configured, please add `sync` to your config"),
+        };
+        let dir = Path::new(&cfg.kube.dir);
+        fs::create_dir_all(dir).with_context(|| format!("create kube dir '{}'", dir.display()))?;
+        let repo = Repo { cfg, sync, dir };
+
+        if fs::metadata(dir.join(".git")).is_err() {
+            let branch = format!("--initial-branch={}", sync.branch);
+            repo.output(["init", "--quiet", &branch])?;
+            info!("Initialized git repo in '{}'", dir.display());
+        }
+
+        match repo.output(["remote", "get-url", "origin"]) {
+            Ok(url) if url == sync.remote => {}
+            Ok(_) => {
+                repo.output(["remote", "set-url", "origin", &sync.remote])?;
+            }
+            Err(_) => {
+                repo.output(["remote", "add", "origin", &sync.remote])?;
+            }
+        }
+
+        let info_dir = dir.join(".git").join("info");
+        fs::create_dir_all(&info_dir)
+            .with_context(|| format!("create git info dir '{}'", info_dir.display()))?;
+
+        let mut exclude = String::from(MANAGED_HEADER);
+        for pattern in sync.ignore.iter() {
+            exclude.push_str(pattern);
+            exclude.push('\n');
+        }
+        write_if_changed(&info_dir.join("exclude"), &exclude)?;
+
+        let mut attributes = String::from(MANAGED_HEADER);
+        if sync.secrets == SyncSecrets::Redact {
+            let exe = env::current_exe().context("get current executable path")?;
+            let command = format!("{} sync-filter", quote_shell(&exe.display().to_string()));
+            repo.output(["config", &format!("filter.{REDACT_FILTER}.clean"), &command])?;
+            attributes.push_str(&format!("* filter={REDACT_FILTER}\n"));
+        }
+        let changed = write_if_changed(&info_dir.join("attributes"), &attributes)?;
+        if changed && repo.rev_parse("HEAD")?.is_some() {
+            // The files are not cleaned again until modified, force it when the
+            // redacting is switched.
+            repo.output(["add", "--renormalize", "."])?;
+        }
+
+        Ok(repo)
+    }
+
+    /// Refuse the staged kubeconfig files to be committed in plaintext, all of them
+    /// are expected to be encrypted by `encryption` in the `encrypted` mode.
+    fn check_encrypted(&self) -> Result<()> {
+        let files =
+            self.output_nul(["diff", "--cached", "--name-only", "--diff-filter=d", "-z"])?;
+        let plain: Vec<_> = files
+            .iter()
+            .filter(|path| {
+                !path.ends_with(encryption::SUFFIX) && !path.ends_with(IgnoreRules::FILE_NAME)
+            })
+            .map(String::as_str)
+            .collect();
+        if !plain.is_empty() {
+            bail!(
+                "refuse to push the kubeconfig files not encrypted: {}, please encrypt them or add them to `sync.ignore`",
+                plain.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// The files edited locally and not committed yet, including the untracked
+    /// ones.
+    fn get_local_changes(&self) -> Result<HashSet<String>> {
+        let entries = self.output_nul(["status", "--porcelain", "--untracked-files=all", "-z"])?;
+        let mut paths = HashSet::new();
+        let mut entries = entries.into_iter();
+        while let Some(entry) = entries.next() {
+            let (status, path) = match entry.get(..3) {
+                Some(status) => (status, &entry[3..]),
+                None => continue,
+            };
+            // The source path of the renamed or copied entry follows it.
+            if status.starts_with(['R', 'C']) {
+                if let Some(from) = entries.next() {
+                    paths.insert(from);
+                }
+            }
+            paths.insert(path.to_string());
+        }
+        Ok(paths)
+    }
+
+    /// The context name of the file in kube dir.
+    fn get_name(&self, path: &str) -> String {
+        encryption::strip_suffix(self.cfg, path)
+            .unwrap_or(path)
+            .to_string()
+    }
+
+    fn rev_parse(&self, rev: &str) -> Result<Option<String>> {
+        let rev = self.output(["rev-parse", "--verify", "--quiet", rev]).ok();
+        Ok(rev.filter(|rev| !rev.is_empty()))
+    }
+
+    fn command<I, S>(&self, args: I) -> Command
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut cmd = Command::new(&self.sync.exec);
+        cmd.args(args);
+        cmd.current_dir(self.dir);
+        cmd.stdin(Stdio::null());
+        cmd
+    }
+
+    /// Run the git command talking to the remote, its stderr is shown to the user
+    /// for the progress and the auth errors.
+    fn run<I, S>(&self, args: I) -> Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut cmd = self.command(args);
+        cmd.stdin(Stdio::inherit());
+        cmd.stdout(io::stderr());
+        cmd.stderr(Stdio::inherit());
+
+        log::debug!("execute {}", process::format_command(&cmd));
+        let start = Instant::now();
+        let status = cmd
+            .status()
+            .with_context(|| format!("execute {}", self.sync.exec))?;
+        log::debug!(
+            "{} exited with {status} in {:.2?}",
+            self.sync.exec,
+            start.elapsed()
+        );
+        if !status.success() {
+            bail!(
+                "execute '{}' exited with bad status: {status}",
+                process::format_command(&cmd)
+            );
+        }
+        Ok(())
+    }
+
+    fn output<I, S>(&self, args: I) -> Result<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let output = self.output_raw(args)?;
+        Ok(output.trim().to_string())
+    }
+
+    /// Run the git command with `-z`, returns the NUL separated fields.
+    fn output_nul<I, S>(&self, args: I) -> Result<Vec<String>>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let output = self.output_raw(args)?;
+        Ok(output
+            .split('\0')
+            .filter(|field| !field.is_empty())
+            .map(String::from)
+            .collect())
+    }
+
+    fn output_raw<I, S>(&self, args: I) -> Result<String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        let mut cmd = self.command(args);
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+
+        log::debug!("execute {}", process::format_command(&cmd));
+        let start = Instant::now();
+        let output = cmd
+            .output()
+            .with_context(|| format!("execute {}", self.sync.exec))?;
+        log::debug!(
+            "{} exited with {} in {:.2?}",
+            self.sync.exec,
+            output.status,
+            start.elapsed()
+        );
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!(
+                "execute '{}' exited with bad status {}: {}",
+                process::format_command(&cmd),
+                output.status,
+                stderr.trim()
+            );
+        }
+        String::from_utf8(output.stdout)
+            .with_context(|| format!("decode {} output", self.sync.exec))
+    }
+}
+
+/// Write the file only if its content is changed, returns whether it was written.
+fn write_if_changed(path: &Path, content: &str) -> Result<bool> {
+    if fs::read_to_string(path).ok().as_deref() == Some(content) {
+        return Ok(false);
+    }
+    fs::write(path, content).with_context(|| format!("write file '{}'", path.display()))?;
+    Ok(true)
+}
+
+fn get_hostname() -> String {
+    env::var("HOSTNAME")
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| String::from("unknown host"))
+}
@@ -0,0 +1,126 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+use std::{fs, io};
+
+use anyhow::{bail, Context, Result};
+
+use crate::config::get_state_path;
+
+/// The tags of contexts, such as `env=prod`, stored in a sidecar file in the home
+/// dir, or the state dir if set. Every line is a context name followed by its tags.
+pub struct Tags {
+    contexts: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Tags {
+    const TAGS_NAME: &'static str = ".kubeswitch_tags";
+
+    pub fn load() -> Result<Tags> {
+        let path = Self::get_path()?;
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("read tags file '{}'", path.display()))
+            }
+        };
+
+        let mut contexts = BTreeMap::new();
+        for line in data.lines() {
+            let mut fields = line.split_whitespace();
+            let name = match fields.next() {
+                Some(name) => name,
+                None => continue,
+            };
+            let tags: BTreeMap<_, _> = fields.filter_map(|field| parse_tag(field).ok()).collect();
+            if !tags.is_empty() {
+                contexts.insert(name.to_string(), tags);
+            }
+        }
+        Ok(Tags { contexts })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let mut data = String::new();
+        for (name, tags) in self.contexts.iter() {
+            if tags.is_empty() {
+                continue;
+            }
+            data.push_str(name);
+            for (key, value) in tags.iter() {
+                data.push_str(&format!(" {key}={value}"));
+            }
+            data.push('\n');
+        }
+
+        let path = Self::get_path()?;
+        fs::write(&path, data).with_context(|| format!("write tags file '{}'", path.display()))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&BTreeMap<String, String>> {
+        self.contexts.get(name)
+    }
+
+    pub fn set(&mut self, name: &str, key: String, value: String) {
+        self.contexts
+            .entry(name.to_string())
+            .or_default()
+            .insert(key, value);
+    }
+
+    pub fn remove(&mut self, name: &str, key: &str) {
+        if let Some(tags) = self.contexts.get_mut(name) {
+            tags.remove(key);
+        }
+    }
+
+    /// Move the tags of the renamed context, or drop them if `new_name` is `None`.
+    pub fn rename(&mut self, name: &str, new_name: Option<&str>) {
+        if let Some(tags) = self.contexts.remove(name) {
+            if let Some(new_name) = new_name {
+                self.contexts.insert(new_name.to_string(), tags);
+            }
+        }
+    }
+
+    /// All the distinct tags in use, for completion.
+    pub fn all(&self) -> BTreeSet<(&str, &str)> {
+        self.contexts
+            .values()
+            .flat_map(|tags| tags.iter())
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect()
+    }
+
+    /// Check if the context has all the tags in the filter.
+    pub fn matches(&self, name: &str, filter: &[(String, String)]) -> bool {
+        let tags = match self.contexts.get(name) {
+            Some(tags) => tags,
+            None => return filter.is_empty(),
+        };
+        filter
+            .iter()
+            .all(|(key, value)| tags.get(key) == Some(value))
+    }
+
+    fn get_path() -> Result<PathBuf> {
+        get_state_path(Self::TAGS_NAME)
+    }
+}
+
+/// Parse the tag in `key=value` format.
+pub fn parse_tag(s: &str) -> Result<(String, String)> {
+    let (key, value) = match s.split_once('=') {
+        Some((key, value)) => (key.trim(), value.trim()),
+        None => bail!("invalid tag '{s}', should be in 'key=value' format"),
+    };
+    let valid = |s: &str| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'))
+    };
+    if !valid(key) || !valid(value) {
+        bail!("invalid tag '{s}', the key and value can only contain alphanumeric, '-', '_', '.' and '/'");
+    }
+    Ok((key.to_string(), value.to_string()))
+}
@@ -0,0 +1,97 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use rev_lines::RevLines;
+
+use crate::config::get_state_path;
+use crate::history::now;
+
+/// Tombstone records a context that was deleted or renamed, so that the scripts
+/// still using the old name can be redirected to its successor, or get a clear
+/// message.
+pub struct Tombstone {
+    pub timestamp: u64,
+    pub successor: Option<String>,
+}
+
+impl Tombstone {
+    const TOMBSTONE_NAME: &'static str = ".kubeswitch_tombstones";
+
+    /// The max length of rename chain to follow, to avoid loop.
+    const MAX_CHAIN: usize = 16;
+
+    pub fn write(name: &str, successor: Option<&str>) -> Result<()> {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(Self::get_path()?)
+            .with_context(|| format!("open tombstone file '{}'", Self::TOMBSTONE_NAME))?;
+
+        let line = format!("{} {name} {}\n", now(), successor.unwrap_or("-"));
+        file.write_all(line.as_bytes())
+            .context("write content to tombstone file")?;
+        Ok(())
+    }
+
+    /// Find the latest tombstone of the name. If the context was renamed several
+    /// times, follow the chain and return the tombstone with the final successor.
+    pub fn lookup(name: &str) -> Result<Option<Tombstone>> {
+        let mut tombstone = match Self::find(name)? {
+            Some(tombstone) => tombstone,
+            None => return Ok(None),
+        };
+
+        for _ in 0..Self::MAX_CHAIN {
+            let successor = match tombstone.successor.as_ref() {
+                Some(successor) => successor,
+                None => return Ok(Some(tombstone)),
+            };
+            match Self::find(successor)? {
+                Some(next) if next.timestamp >= tombstone.timestamp => tombstone = next,
+                _ => return Ok(Some(tombstone)),
+            }
+        }
+
+        bail!("rename chain of context '{name}' is too long");
+    }
+
+    fn find(name: &str) -> Result<Option<Tombstone>> {
+        let path = Self::get_path()?;
+        let file = match fs::File::open(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("open tombstone file '{}'", path.display()))
+            }
+        };
+
+        for line in RevLines::new(file) {
+            let line = line.context("read tombstone file")?;
+            let fields: Vec<_> = line.trim().split(' ').collect();
+            if fields.len() != 3 || fields[1] != name {
+                continue;
+            }
+            let timestamp = match fields[0].parse() {
+                Ok(timestamp) => timestamp,
+                Err(_) => continue,
+            };
+            let successor = match fields[2] {
+                "-" => None,
+                successor => Some(successor.to_string()),
+            };
+            return Ok(Some(Tombstone {
+                timestamp,
+                successor,
+            }));
+        }
+
+        Ok(None)
+    }
+
+    fn get_path() -> Result<PathBuf> {
+        get_state_path(Self::TOMBSTONE_NAME)
+    }
+}
@@ -0,0 +1,264 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, VaultConfig, VaultContextConfig};
+use crate::context::{create_private_dir, write_private};
+use crate::history::now;
+
+/// The credentials will be refreshed a little earlier than the real expiry time,
+/// so that kubectl won't hit an expired token right after switching.
+const EXPIRE_MARGIN_SECS: u64 = 30;
+
+#[derive(Debug, Deserialize)]
+struct VaultSecret {
+    lease_duration: u64,
+    data: VaultSecretData,
+}
+
+#[derive(Debug, Deserialize)]
+struct VaultSecretData {
+    service_account_token: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Kubeconfig<'a> {
+    #[serde(rename = "apiVersion")]
+    api_version: &'static str,
+    kind: &'static str,
+    clusters: Vec<NamedCluster<'a>>,
+    contexts: Vec<NamedContext<'a>>,
+    users: Vec<NamedUser<'a>>,
+    #[serde(rename = "current-context")]
+    current_context: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct NamedCluster<'a> {
+    name: &'a str,
+    cluster: Cluster<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct Cluster<'a> {
+    server: &'a str,
+    #[serde(
+        rename = "certificate-authority",
+        skip_serializing_if = "Option::is_none"
+    )]
+    certificate_authority: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+struct NamedContext<'a> {
+    name: &'a str,
+    context: ContextEntry<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct ContextEntry<'a> {
+    cluster: &'a str,
+    user: &'a str,
+    namespace: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct NamedUser<'a> {
+    name: &'a str,
+    user: User<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct User<'a> {
+    token: &'a str,
+}
+
+/// Find the vault context config for the context name, the name should contain
+/// the vault prefix, for example, "vault/prod". The vault config it belongs to
+/// is returned along with it.
+pub fn lookup<'a>(
+    cfg: &'a Config,
+    name: &str,
+) -> Option<(&'a VaultConfig, &'a VaultContextConfig)> {
+    let vault = cfg.vault.as_ref()?;
+    let name = name.strip_prefix(&vault.prefix)?.strip_prefix('/')?;
+    let ctx = vault.contexts.iter().find(|ctx| ctx.name == name)?;
+    Some((vault, ctx))
+}
+
+/// List all the vault context names (with prefix).
+pub fn list(cfg: &Config) -> Vec<String> {
+    match cfg.vault.as_ref() {
+        Some(vault) => vault
+            .contexts
+            .iter()
+            .map(|ctx| format!("{}/{}", vault.prefix, ctx.name))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// The temporary kubeconfig path for the vault context.
+pub fn get_path(vault: &VaultConfig, ctx: &VaultContextConfig) -> PathBuf {
+    PathBuf::from(&vault.dir).join(&ctx.name)
+}
+
+/// Make sure that the temporary kubeconfig for the vault context exists and its
+/// lease is still valid, otherwise, fetch new credentials from vault.
+pub fn ensure(cfg: &Config, vault: &VaultConfig, ctx: &VaultContextConfig) -> Result<PathBuf> {
+    let path = get_path(vault, ctx);
+    if let Some(expire) = read_lease(&path)? {
+        if expire > now() + EXPIRE_MARGIN_SECS {
+            return Ok(path);
+        }
+    }
+    remove_credentials(&path)?;
+    if cfg.offline {
+        bail!(
+            "credentials of vault context '{}' expired, cannot refresh in offline mode",
+            ctx.name
+        );
+    }
+
+    let secret = read_secret(vault, ctx)
+        .with_context(|| format!("read credentials for vault context '{}'", ctx.name))?;
+    write_credentials(vault, ctx, &path, &secret)?;
+
+    Ok(path)
+}
+
+/// Remove all the temporary kubeconfigs whose lease has expired, the credentials
+/// should never persist beyond their lease.
+pub fn purge_expired(cfg: &Config) -> Result<()> {
+    let vault = match cfg.vault.as_ref() {
+        Some(vault) => vault,
+        None => return Ok(()),
+    };
+
+    let now = now();
+    for ctx in vault.contexts.iter() {
+        let path = get_path(vault, ctx);
+        if let Some(expire) = read_lease(&path)? {
+            if expire > now {
+                continue;
+            }
+        }
+        remove_credentials(&path)?;
+    }
+
+    Ok(())
+}
+
+fn read_secret(vault: &VaultConfig, ctx: &VaultContextConfig) -> Result<VaultSecret> {
+    let path = ctx.path.as_ref().unwrap_or(&vault.path);
+    let path = path
+        .replace("{name}", &ctx.name)
+        .replace("{namespace}", &ctx.namespace);
+    let namespace_arg = format!("kubernetes_namespace={}", ctx.namespace);
+
+    let mut cmd = Command::new(&vault.exec);
+    cmd.args([
+        "write",
+        "-format=json",
+        path.as_str(),
+        namespace_arg.as_str(),
+    ]);
+    cmd.stdin(Stdio::inherit());
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+
+    let output = match cmd.output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            bail!("cannot find vault in your system, please install it first");
+        }
+        Err(e) => return Err(e).context("execute vault command"),
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("vault write '{path}' failed: {}", stderr.trim());
+    }
+
+    serde_json::from_slice(&output.stdout).context("parse vault output json")
+}
+
+fn write_credentials(
+    vault: &VaultConfig,
+    ctx: &VaultContextConfig,
+    path: &Path,
+    secret: &VaultSecret,
+) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        create_private_dir(dir)?;
+    }
+
+    let name = format!("{}/{}", vault.prefix, ctx.name);
+    let kubeconfig = Kubeconfig {
+        api_version: "v1",
+        kind: "Config",
+        clusters: vec![NamedCluster {
+            name: &name,
+            cluster: Cluster {
+                server: &ctx.server,
+                certificate_authority: ctx.ca.as_deref(),
+            },
+        }],
+        contexts: vec![NamedContext {
+            name: &name,
+            context: ContextEntry {
+                cluster: &name,
+                user: &name,
+                namespace: &ctx.namespace,
+            },
+        }],
+        users: vec![NamedUser {
+            name: &name,
+            user: User {
+                token: &secret.data.service_account_token,
+            },
+        }],
+        current_context: &name,
+    };
+    let data = serde_yaml::to_string(&kubeconfig).context("serialize vault kubeconfig")?;
+    write_private(path, data.as_bytes())?;
+
+    let expire = now() + secret.lease_duration;
+    write_private(&get_lease_path(path), format!("{expire}\n").as_bytes())?;
+
+    Ok(())
+}
+
+fn read_lease(path: &Path) -> Result<Option<u64>> {
+    let lease_path = get_lease_path(path);
+    let data = match fs::read_to_string(&lease_path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err).with_context(|| format!("read lease file '{}'", lease_path.display()))
+        }
+    };
+    Ok(data.trim().parse().ok())
+}
+
+fn remove_credentials(path: &Path) -> Result<()> {
+    for path in [path.to_path_buf(), get_lease_path(path)] {
+        match fs::remove_file(&path) {
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(err).with_context(|| format!("remove file '{}'", path.display()))
+            }
+        }
+    }
+    Ok(())
+}
+
+fn get_lease_path(path: &Path) -> PathBuf {
+    let mut lease = path.as_os_str().to_owned();
+    lease.push(".lease");
+    PathBuf::from(lease)
+}
@@ -0,0 +1,95 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+const KUBECONFIG: &str = "apiVersion: v1
+kind: Config
+current-context: {name}
+contexts:
+- name: {name}
+  context:
+    cluster: {name}
+    namespace: default
+clusters:
+- name: {name}
+  cluster:
+    server: https://127.0.0.1:6443
+";
+
+/// A temporary home with the kube dir, config and state dir, removed on drop.
+struct TestDir(PathBuf);
+
+impl TestDir {
+    fn new(name: &str, contexts: &[&str]) -> TestDir {
+        let dir = env::temp_dir().join(format!("kubeswitch-test-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let kube_dir = dir.join("kube");
+        for ctx in contexts {
+            let path = kube_dir.join(ctx);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, KUBECONFIG.replace("{name}", ctx)).unwrap();
+        }
+        let config = format!(
+            "sort = \"name\"\n[kube]\ndir = \"{}\"\n",
+            kube_dir.display()
+        );
+        fs::write(dir.join("config.toml"), config).unwrap();
+        TestDir(dir)
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_kubeswitch"));
+        for (key, _) in env::vars_os() {
+            let key = key.to_string_lossy();
+            if key.starts_with("KUBESWITCH_") || key.starts_with("XDG_") {
+                cmd.env_remove(key.as_ref());
+            }
+        }
+        cmd.env("HOME", self.path())
+            .env("EDITOR", "vi")
+            .env("KUBESWITCH_CONFIG_PATH", self.path().join("config.toml"))
+            .arg("--state-dir")
+            .arg(self.path().join("state"))
+            .args(args);
+        cmd.output().unwrap()
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn test_pick_by_index() {
+    let dir = TestDir::new("pick", &["dev", "prod", "team/a", "team/b"]);
+
+    for (idx, name) in ["dev", "prod", "team/a", "team/b"].iter().enumerate() {
+        let output = dir.run(&["--select-index", &idx.to_string(), "pick"]);
+        assert_eq!(stdout(&output), format!("{name}\n"));
+    }
+
+    let output = dir.run(&["--select-index", "1", "pick", "team/"]);
+    assert_eq!(stdout(&output), "team/b\n");
+
+    let output = dir.run(&["--select-index", "4", "pick"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("select index 4 out of range"), "{stderr}");
+}